@@ -0,0 +1,237 @@
+//! Benchmarks of the per-frame billboard systems for many billboards, to catch performance
+//! regressions across bevy upgrades.
+//!
+//! The extraction and text layout benchmarks run on headless worlds. Queueing needs a render
+//! world, so it runs on a software fallback adapter and is skipped when there is none.
+//! Texts are shaped and their meshes built on the compute task pool, so compare runs on machines
+//! with the same core count. Text layout is also benchmarked on a single thread, to tell how much
+//! the pool helps.
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use bevy::core_pipeline::core_3d::Transparent3d;
+use bevy::prelude::*;
+use bevy::render::render_phase::ViewSortedRenderPhases;
+use bevy::render::{MainWorld, RenderApp};
+use bevy::utils::{Duration, Instant};
+use bevy_mod_billboard::batch::ExtractedBillboardTextBatches;
+use bevy_mod_billboard::pipeline::queue_billboard_texture;
+use bevy_mod_billboard::prelude::*;
+use bevy_mod_billboard::text::{
+    extract_billboard_text, update_billboard_text_layout, BillboardTextHandles,
+};
+use bevy_mod_billboard::texture::extract_billboard_texture;
+use common::{main_world_app, render_app, render_target, update_until};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+const COUNTS: [usize; 2] = [100, 1000];
+const LAYOUT_COUNTS: [usize; 3] = [10, 100, 1000];
+
+fn spawn_camera(app: &mut App) {
+    let world = app.world_mut();
+    let target = render_target(&mut world.resource_mut::<Assets<Image>>());
+
+    world.spawn(Camera3dBundle {
+        camera: Camera {
+            target,
+            ..default()
+        },
+        transform: Transform::from_xyz(0., 0., 50.).looking_at(Vec3::ZERO, Vec3::Y),
+        ..default()
+    });
+}
+
+fn spawn_texts(app: &mut App, count: usize) {
+    let font: Handle<Font> = app
+        .world()
+        .resource::<AssetServer>()
+        .load("FiraSans-Regular.ttf");
+
+    update_until(app, |app| {
+        app.world().resource::<Assets<Font>>().contains(&font)
+    });
+
+    for i in 0..count {
+        app.world_mut().spawn(BillboardTextBundle {
+            text: Text::from_section(
+                format!("Label {i}"),
+                TextStyle {
+                    font: font.clone(),
+                    font_size: 60.0,
+                    color: Color::WHITE,
+                },
+            ),
+            transform: Transform::from_xyz(i as f32 % 32.0 - 16.0, i as f32 / 32.0 - 16.0, 0.0)
+                .with_scale(Vec3::splat(0.0085)),
+            ..default()
+        });
+    }
+
+    update_until(app, |app| {
+        let world = app.world_mut();
+        world
+            .query::<&BillboardTextHandles>()
+            .iter(world)
+            .all(|handles| !handles.is_empty())
+    });
+}
+
+fn spawn_textures(app: &mut App, count: usize) {
+    let world = app.world_mut();
+    let mesh = world
+        .resource_mut::<Assets<Mesh>>()
+        .add(Rectangle::from_size(Vec2::ONE));
+    let image = world.resource_mut::<Assets<Image>>().add(Image::default());
+
+    // A grid in view of the camera, so none of them are frustum culled
+    for i in 0..count {
+        world.spawn(BillboardTextureBundle {
+            mesh: BillboardMeshHandle(mesh.clone()),
+            texture: BillboardTextureHandle(image.clone()),
+            transform: Transform::from_xyz(i as f32 % 32.0 - 16.0, i as f32 / 32.0 - 16.0, 0.0),
+            ..default()
+        });
+    }
+
+    // Computes the visibility extraction filters by
+    app.update();
+}
+
+/// Render world extracting from the main world of `app`, without a renderer.
+fn extraction_world(app: &mut App) -> World {
+    let mut main_world = MainWorld::default();
+    std::mem::swap(&mut *main_world, app.world_mut());
+
+    let mut render_world = World::new();
+    render_world.insert_resource(main_world);
+    render_world.init_resource::<ExtractedBillboardTextBatches>();
+    render_world
+}
+
+fn extract_text(c: &mut Criterion) {
+    let mut group = c.benchmark_group("extract_billboard_text");
+
+    for count in COUNTS {
+        let mut app = main_world_app();
+        spawn_camera(&mut app);
+        spawn_texts(&mut app, count);
+
+        let mut render_world = extraction_world(&mut app);
+        let system = render_world.register_system(extract_billboard_text);
+
+        group.bench_function(BenchmarkId::from_parameter(count), |b| {
+            b.iter(|| render_world.run_system(system).unwrap());
+        });
+    }
+
+    group.finish();
+}
+
+fn extract_texture(c: &mut Criterion) {
+    let mut group = c.benchmark_group("extract_billboard_texture");
+
+    for count in COUNTS {
+        let mut app = main_world_app();
+        spawn_camera(&mut app);
+        spawn_textures(&mut app, count);
+
+        let mut render_world = extraction_world(&mut app);
+        let system = render_world.register_system(extract_billboard_texture);
+
+        group.bench_function(BenchmarkId::from_parameter(count), |b| {
+            b.iter(|| render_world.run_system(system).unwrap());
+        });
+    }
+
+    group.finish();
+}
+
+fn text_layout(c: &mut Criterion) {
+    let mut group = c.benchmark_group("update_billboard_text_layout");
+
+    for count in LAYOUT_COUNTS {
+        for (name, serial_layout) in [("parallel", false), ("serial", true)] {
+            let mut app = main_world_app();
+            app.world_mut()
+                .resource_mut::<BillboardTextSettings>()
+                .serial_layout = serial_layout;
+            spawn_texts(&mut app, count);
+
+            let world = app.world_mut();
+            let system = world.register_system(update_billboard_text_layout);
+            let mut bounds = world.query::<&mut BillboardTextBounds>();
+
+            group.bench_function(BenchmarkId::new(name, count), |b| {
+                b.iter(|| {
+                    // Changed bounds always need a full relayout
+                    for mut bounds in bounds.iter_mut(world) {
+                        bounds.set_changed();
+                    }
+
+                    world.run_system(system).unwrap();
+                });
+            });
+        }
+    }
+
+    group.finish();
+}
+
+fn queue_texture(c: &mut Criterion) {
+    let mut group = c.benchmark_group("queue_billboard_texture");
+
+    for count in COUNTS {
+        let Some(mut app) = render_app() else {
+            return;
+        };
+        spawn_camera(&mut app);
+        spawn_textures(&mut app, count);
+
+        // Until the pipeline is compiled and the assets are prepared
+        update_until(&mut app, |app| {
+            app.sub_app(RenderApp)
+                .world()
+                .resource::<ViewSortedRenderPhases<Transparent3d>>()
+                .iter()
+                .any(|(_, phase)| phase.items.len() == count)
+        });
+
+        // A registered system would be an entity, which the render world can't keep
+        let mut system = IntoSystem::into_system(queue_billboard_texture);
+        system.initialize(app.sub_app_mut(RenderApp).world_mut());
+
+        group.bench_function(BenchmarkId::from_parameter(count), |b| {
+            b.iter_custom(|iters| {
+                let mut time = Duration::ZERO;
+
+                for _ in 0..iters {
+                    let mut main_world = std::mem::take(app.world_mut());
+                    app.sub_app_mut(RenderApp).extract(&mut main_world);
+                    *app.world_mut() = main_world;
+
+                    let render_world = app.sub_app_mut(RenderApp).world_mut();
+                    let start = Instant::now();
+                    system.run((), render_world);
+                    time += start.elapsed();
+
+                    // Like at the end of every frame
+                    render_world.clear_entities();
+                }
+
+                time
+            });
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    extract_text,
+    extract_texture,
+    text_layout,
+    queue_texture
+);
+criterion_main!(benches);
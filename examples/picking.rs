@@ -0,0 +1,104 @@
+use bevy::color::palettes;
+use bevy::prelude::*;
+use bevy_mod_billboard::prelude::*;
+
+fn main() {
+    App::new()
+        .add_plugins(DefaultPlugins)
+        .add_plugins((BillboardPlugin, BillboardPickingPlugin))
+        // Only the opaque part of the logo and the glyphs can be hovered
+        .insert_resource(BillboardPickingSettings {
+            alpha_threshold: Some(0.5),
+        })
+        .add_systems(Startup, (setup_billboard, setup_scene))
+        .add_systems(Update, (highlight_hovered, log_clicks, rotate_camera))
+        .run();
+}
+
+fn setup_billboard(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    let fira_sans_regular_handle = asset_server.load("FiraSans-Regular.ttf");
+    commands.spawn(BillboardTextBundle {
+        transform: Transform::from_xyz(0., 1.5, 0.).with_scale(Vec3::splat(0.0085)),
+        text: Text::from_section(
+            "Click me",
+            TextStyle {
+                font_size: 60.0,
+                font: fira_sans_regular_handle,
+                color: Color::WHITE,
+            },
+        )
+        .with_justify(JustifyText::Center),
+        ..default()
+    });
+
+    commands.spawn(BillboardTextureBundle {
+        texture: BillboardTextureHandle(asset_server.load("rust-logo-256x256.png")),
+        mesh: BillboardMeshHandle(meshes.add(Rectangle::from_size(Vec2::splat(2.0)))),
+        ..default()
+    });
+}
+
+fn highlight_hovered(
+    mut over_events: EventReader<BillboardPointerOver>,
+    mut out_events: EventReader<BillboardPointerOut>,
+    mut texts: Query<&mut Text>,
+) {
+    for event in out_events.read() {
+        if let Ok(mut text) = texts.get_mut(event.entity) {
+            text.sections[0].style.color = Color::WHITE;
+        }
+    }
+
+    for event in over_events.read() {
+        if let Ok(mut text) = texts.get_mut(event.hit.entity) {
+            text.sections[0].style.color = Color::Srgba(palettes::css::ORANGE);
+        }
+    }
+}
+
+fn log_clicks(mut click_events: EventReader<BillboardClick>) {
+    for event in click_events.read() {
+        info!(
+            "Clicked {} with {:?} at uv {}",
+            event.hit.entity, event.button, event.hit.uv
+        );
+    }
+}
+
+// Important bits are above, the code below is for camera, reference cube and rotation
+
+#[derive(Component)]
+pub struct CameraHolder;
+
+fn setup_scene(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands
+        .spawn((CameraHolder, Transform::IDENTITY, GlobalTransform::IDENTITY))
+        .with_children(|parent| {
+            parent.spawn(Camera3dBundle {
+                transform: Transform::from_translation(Vec3::new(5., 0., 0.))
+                    .looking_at(Vec3::ZERO, Vec3::Y),
+                ..default()
+            });
+        });
+
+    commands.spawn(PbrBundle {
+        mesh: meshes.add(Cuboid::default()),
+        material: materials.add(Color::Srgba(palettes::css::GRAY)),
+        transform: Transform::from_translation(Vec3::NEG_Y * 2.),
+        ..default()
+    });
+}
+
+fn rotate_camera(mut camera: Query<&mut Transform, With<CameraHolder>>, time: Res<Time>) {
+    let mut camera = camera.single_mut();
+
+    camera.rotate_y(time.delta_seconds() * 0.2);
+}
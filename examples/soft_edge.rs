@@ -0,0 +1,77 @@
+use bevy::color::palettes;
+use bevy::core_pipeline::prepass::DepthPrepass;
+use bevy::prelude::*;
+use bevy_mod_billboard::prelude::*;
+
+fn main() {
+    App::new()
+        .add_plugins(DefaultPlugins)
+        .add_plugins(BillboardPlugin)
+        .add_systems(Startup, (setup_billboard, setup_scene))
+        .add_systems(Update, rotate_camera)
+        .run();
+}
+
+fn setup_billboard(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    let image_handle = asset_server.load("rust-logo-256x256.png");
+    let mesh_handle = meshes.add(Rectangle::from_size(Vec2::splat(2.0)));
+
+    commands.spawn(BillboardTextureBundle {
+        transform: Transform::from_translation(Vec3::new(0., -0.5, 1.5)),
+        texture: BillboardTextureHandle(image_handle.clone()),
+        mesh: BillboardMeshHandle(mesh_handle.clone()),
+        ..default()
+    });
+
+    commands.spawn((
+        BillboardTextureBundle {
+            transform: Transform::from_translation(Vec3::new(0., -0.5, -1.5)),
+            texture: BillboardTextureHandle(image_handle),
+            mesh: BillboardMeshHandle(mesh_handle),
+            ..default()
+        },
+        BillboardSoftEdge(0.5),
+    ));
+}
+
+// Important bits are above, the code below is for camera, reference ground and rotation
+
+#[derive(Component)]
+pub struct CameraHolder;
+
+fn setup_scene(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands
+        .spawn((CameraHolder, Transform::IDENTITY, GlobalTransform::IDENTITY))
+        .with_children(|parent| {
+            parent.spawn((
+                Camera3dBundle {
+                    transform: Transform::from_translation(Vec3::new(5., 1., 0.))
+                        .looking_at(Vec3::ZERO, Vec3::Y),
+                    ..default()
+                },
+                // Soft edges read the depth of the scene from the prepass
+                DepthPrepass,
+            ));
+        });
+
+    commands.spawn(PbrBundle {
+        mesh: meshes.add(Plane3d::default().mesh().size(8., 8.)),
+        material: materials.add(Color::Srgba(palettes::css::GRAY)),
+        transform: Transform::from_translation(Vec3::NEG_Y),
+        ..default()
+    });
+}
+
+fn rotate_camera(mut camera: Query<&mut Transform, With<CameraHolder>>, time: Res<Time>) {
+    let mut camera = camera.single_mut();
+
+    camera.rotate_y(time.delta_seconds());
+}
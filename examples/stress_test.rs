@@ -11,13 +11,19 @@
 //! change detection to BillboardTexture.
 //! For example `cargo run --example stress_test text recompute_text` will render text billboards
 //! and recompute them every frame.
+//!
+//! Add the `gpu_culling` argument to cull and draw the image-based billboards on the GPU.
+//! Run it as `cargo run --example stress_test instances` to render the image-based billboards as
+//! the instances of a single entity instead.
 
 use bevy::{
     color::palettes,
     diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin},
     prelude::*,
+    time::common_conditions::on_timer,
 };
-use bevy_mod_billboard::prelude::*;
+use bevy_mod_billboard::{prelude::*, text::BillboardTextMeshStats};
+use std::time::Duration;
 
 fn main() {
     App::new()
@@ -29,6 +35,10 @@ fn main() {
         ))
         .add_systems(Startup, setup)
         .add_systems(Update, recompute_billboards)
+        .add_systems(
+            Update,
+            log_text_mesh_stats.run_if(on_timer(Duration::from_secs(1))),
+        )
         .run();
 }
 
@@ -44,6 +54,7 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>, mut meshes: Res
     let mesh_handle = meshes.add(Rectangle::from_size(Vec2::splat(1.0)));
     let billboard_mesh = BillboardMeshHandle(mesh_handle);
     let fira_sans_regular_handle = asset_server.load("FiraSans-Regular.ttf");
+    let gpu_culling = std::env::args().any(|arg| arg == "gpu_culling");
 
     commands.spawn(Camera3dBundle {
         transform: Transform::from_translation(Vec3::new(0., 0., 50.))
@@ -76,17 +87,40 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>, mut meshes: Res
                 }
 
                 if std::env::args().any(|arg| arg == "texture") {
-                    commands.spawn(BillboardTextureBundle {
+                    let mut billboard = commands.spawn(BillboardTextureBundle {
                         texture: billboard_texture.clone(),
                         mesh: billboard_mesh.clone(),
                         transform: Transform::from_translation(translation),
                         ..Default::default()
                     });
+
+                    if gpu_culling {
+                        billboard.insert(BillboardGpuCulling::default());
+                    }
                 }
             }
         }
     }
 
+    if std::env::args().any(|arg| arg == "instances") {
+        let instances = (-10..=10)
+            .flat_map(|x| (-10..=10).flat_map(move |y| (-10..=10).map(move |z| (x, y, z))))
+            .map(|(x, y, z)| BillboardInstance {
+                position: Vec3::new(x as f32, y as f32, z as f32),
+                ..default()
+            })
+            .collect();
+
+        commands.spawn(BillboardInstancesBundle {
+            instances: BillboardInstances {
+                instances,
+                atlas: None,
+            },
+            texture: billboard_texture.clone(),
+            ..default()
+        });
+    }
+
     commands.insert_resource(Settings {
         recompute_texture: std::env::args().any(|arg| arg == "recompute_texture"),
         recompute_text: std::env::args().any(|arg| arg == "recompute_text"),
@@ -113,3 +147,10 @@ fn recompute_billboards(
         }
     }
 }
+
+fn log_text_mesh_stats(stats: Res<BillboardTextMeshStats>) {
+    info!(
+        "Text meshes allocated: {}, reused: {}",
+        stats.allocated, stats.reused
+    );
+}
@@ -0,0 +1,433 @@
+use crate::oit::BillboardViewPhase;
+use crate::pipeline::{
+    BillboardImageBindGroups, BillboardPhases, BillboardPipeline, BillboardPipelineKey,
+    BillboardUniform, BillboardViews, RenderBillboardImage, SetBillboardTextureBindGroup,
+    SetBillboardViewBindGroup,
+};
+use crate::text::{RenderBillboard, ATTRIBUTE_ATLAS_LAYER};
+use crate::Billboard;
+use bevy::ecs::entity::EntityHashMap;
+use bevy::ecs::query::ROQueryItem;
+use bevy::ecs::system::lifetimeless::{Read, SRes};
+use bevy::ecs::system::SystemParamItem;
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, VertexAttributeValues};
+use bevy::render::render_asset::RenderAssets;
+use bevy::render::render_phase::{
+    PhaseItem, RenderCommand, RenderCommandResult, SetItemPipeline, TrackedRenderPass,
+};
+use bevy::render::render_resource::{
+    BindGroup, BindGroupEntry, BufferUsages, IndexFormat, PipelineCache, RawBufferVec,
+    SpecializedRenderPipelines, StorageBuffer,
+};
+use bevy::render::renderer::{RenderDevice, RenderQueue};
+use bevy::render::texture::GpuImage;
+use bevy::render::Extract;
+use bevy::utils::HashMap;
+use bytemuck::{Pod, Zeroable};
+use smallvec::SmallVec;
+use std::ops::Range;
+
+pub(crate) fn text_batches_supported(render_device: &RenderDevice) -> bool {
+    // Storage buffers aren't available on WebGL2
+    render_device.limits().max_storage_buffers_per_shader_stage > 0
+}
+
+/// Texts drawn in batches this frame, extracted here instead of as render entities of their own
+/// when [`BillboardTextSettings::batching`](crate::text::BillboardTextSettings::batching) is on.
+#[derive(Resource, Default)]
+pub struct ExtractedBillboardTextBatches {
+    /// Whether the device can draw batches, texts are drawn one by one otherwise.
+    pub supported: bool,
+    pub texts: EntityHashMap<ExtractedBatchedText>,
+}
+
+pub struct ExtractedBatchedText {
+    pub uniform: BillboardUniform,
+    pub billboard: RenderBillboard,
+    /// Mesh and atlas texture of every atlas group of the text.
+    pub groups: SmallVec<[(AssetId<Mesh>, AssetId<Image>); 1]>,
+}
+
+#[derive(Clone, Copy, Pod, Zeroable)]
+#[repr(C)]
+pub struct BillboardTextBatchVertex {
+    pub position: [f32; 3],
+    pub uv: [f32; 2],
+    pub color: [f32; 4],
+    pub atlas_layer: u32,
+    /// Index of the billboard of the text in the storage buffer of the batches.
+    pub billboard_index: u32,
+}
+
+/// Vertices of the meshes of batched texts. The render world only has the GPU buffers of
+/// meshes, so they are copied from the main world when they are first batched or change.
+#[derive(Resource, Default)]
+pub struct BillboardTextBatchMeshes {
+    meshes: HashMap<AssetId<Mesh>, BatchMesh>,
+}
+
+struct BatchMesh {
+    vertices: Vec<BillboardTextBatchVertex>,
+    indices: Vec<u32>,
+    texture_array: bool,
+}
+
+impl BatchMesh {
+    /// Only reads the attributes of text meshes, so other meshes aren't batched.
+    fn from_mesh(mesh: &Mesh) -> Option<Self> {
+        let (
+            Some(VertexAttributeValues::Float32x3(positions)),
+            Some(VertexAttributeValues::Float32x2(uvs)),
+            Some(VertexAttributeValues::Float32x4(colors)),
+            Some(Indices::U32(indices)),
+        ) = (
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION),
+            mesh.attribute(Mesh::ATTRIBUTE_UV_0),
+            mesh.attribute(Mesh::ATTRIBUTE_COLOR),
+            mesh.indices(),
+        )
+        else {
+            return None;
+        };
+
+        let layers = match mesh.attribute(ATTRIBUTE_ATLAS_LAYER) {
+            Some(VertexAttributeValues::Uint32(layers)) => Some(layers),
+            _ => None,
+        };
+
+        let vertices = (0..positions.len())
+            .map(|index| BillboardTextBatchVertex {
+                position: positions[index],
+                uv: uvs[index],
+                color: colors[index],
+                atlas_layer: layers.map_or(0, |layers| layers[index]),
+                billboard_index: 0,
+            })
+            .collect();
+
+        Some(Self {
+            vertices,
+            indices: indices.clone(),
+            texture_array: layers.is_some(),
+        })
+    }
+}
+
+pub fn extract_billboard_text_batch_meshes(
+    mut batch_meshes: ResMut<BillboardTextBatchMeshes>,
+    text_batches: Res<ExtractedBillboardTextBatches>,
+    mut mesh_events: Extract<EventReader<AssetEvent<Mesh>>>,
+    meshes: Extract<Res<Assets<Mesh>>>,
+) {
+    for event in mesh_events.read() {
+        match event {
+            AssetEvent::Modified { id }
+            | AssetEvent::Removed { id }
+            | AssetEvent::Unused { id } => {
+                batch_meshes.meshes.remove(id);
+            }
+            AssetEvent::Added { .. } | AssetEvent::LoadedWithDependencies { .. } => {}
+        }
+    }
+
+    for text in text_batches.texts.values() {
+        for &(id, _) in &text.groups {
+            if batch_meshes.meshes.contains_key(&id) {
+                continue;
+            }
+
+            if let Some(batch_mesh) = meshes.get(id).and_then(BatchMesh::from_mesh) {
+                batch_meshes.meshes.insert(id, batch_mesh);
+            }
+        }
+    }
+}
+
+/// Vertices, indices and billboards of the text batches of every view this frame.
+#[derive(Resource)]
+pub struct BillboardTextBatches {
+    vertices: RawBufferVec<BillboardTextBatchVertex>,
+    indices: RawBufferVec<u32>,
+    billboards: StorageBuffer<Vec<BillboardUniform>>,
+    bind_group: Option<BindGroup>,
+}
+
+impl BillboardTextBatches {
+    /// Number of vertices of the batched texts this frame, shared by every view.
+    pub fn vertex_count(&self) -> usize {
+        self.vertices.len()
+    }
+}
+
+impl Default for BillboardTextBatches {
+    fn default() -> Self {
+        Self {
+            vertices: RawBufferVec::new(BufferUsages::VERTEX),
+            indices: RawBufferVec::new(BufferUsages::INDEX),
+            billboards: StorageBuffer::default(),
+            bind_group: None,
+        }
+    }
+}
+
+/// Indices of a text batch in [`BillboardTextBatches`], drawn with the atlas texture of its
+/// [`RenderBillboardImage`].
+#[derive(Component, Clone)]
+pub struct BillboardTextBatch {
+    pub indices: Range<u32>,
+}
+
+/// Indices of a batch of a view, before they are added to [`BillboardTextBatches`].
+pub struct ViewBatch {
+    indices: Vec<u32>,
+    /// Distance of the farthest text of the batch, which the whole batch is sorted by.
+    distance: f32,
+}
+
+/// Batches of a view by atlas texture and pipeline.
+type ViewBatches = HashMap<(AssetId<Image>, BillboardPipelineKey), ViewBatch>;
+
+/// First vertex of every atlas group of a text in [`BillboardTextBatches`], `None` for the
+/// groups whose mesh wasn't extracted.
+type TextVertexBases = SmallVec<[Option<u32>; 1]>;
+
+pub fn queue_billboard_text_batches(
+    mut commands: Commands,
+    views: Query<BillboardViews>,
+    phases: BillboardPhases,
+    (pipeline_cache, mut billboard_pipelines, billboard_pipeline): (
+        Res<PipelineCache>,
+        ResMut<SpecializedRenderPipelines<BillboardPipeline>>,
+        Res<BillboardPipeline>,
+    ),
+    (mut image_bind_groups, gpu_images, render_device, msaa): (
+        ResMut<BillboardImageBindGroups>,
+        Res<RenderAssets<GpuImage>>,
+        Res<RenderDevice>,
+        Res<Msaa>,
+    ),
+    (text_batches, batch_meshes, mut batches): (
+        Res<ExtractedBillboardTextBatches>,
+        Res<BillboardTextBatchMeshes>,
+        ResMut<BillboardTextBatches>,
+    ),
+    (mut vertex_bases, mut view_batches): (
+        Local<EntityHashMap<TextVertexBases>>,
+        Local<ViewBatches>,
+    ),
+) {
+    let BillboardPhases {
+        transparent: mut transparent_render_phases,
+        oit: mut oit_render_phases,
+        transparent_draw_functions,
+        oit_draw_functions,
+    } = phases;
+
+    let batches = batches.as_mut();
+    batches.vertices.clear();
+    batches.indices.clear();
+    batches.billboards.get_mut().clear();
+    vertex_bases.clear();
+
+    if text_batches.texts.is_empty() {
+        return;
+    }
+
+    for (view_entity, view, visible_entities, depth_prepass, oit) in &views {
+        let Some(mut phase) = BillboardViewPhase::get::<DrawBillboardTextBatch>(
+            view_entity,
+            oit,
+            &mut transparent_render_phases,
+            &mut oit_render_phases,
+            &transparent_draw_functions,
+            &oit_draw_functions,
+        ) else {
+            continue;
+        };
+
+        let rangefinder = view.rangefinder3d();
+
+        for visible_entity in visible_entities.iter::<With<Billboard>>() {
+            let Some(text) = text_batches.texts.get(visible_entity) else {
+                continue;
+            };
+
+            let key = BillboardPipelineKey::from_billboard(
+                &text.billboard,
+                view,
+                msaa.samples(),
+                phase.is_oit(),
+                depth_prepass,
+            );
+            let distance = rangefinder.distance(&text.uniform.transform) + text.billboard.sort_bias;
+
+            // Texts seen by several views share their billboard and vertices, only the indices
+            // of the batches are per view
+            let bases = vertex_bases.entry(*visible_entity).or_insert_with(|| {
+                let billboards = batches.billboards.get_mut();
+                billboards.push(text.uniform);
+                let billboard_index = billboards.len() as u32 - 1;
+
+                text.groups
+                    .iter()
+                    .map(|(mesh, _)| {
+                        let mesh = batch_meshes.meshes.get(mesh)?;
+                        let base = batches.vertices.len() as u32;
+                        batches.vertices.extend(mesh.vertices.iter().map(|vertex| {
+                            BillboardTextBatchVertex {
+                                billboard_index,
+                                ..*vertex
+                            }
+                        }));
+                        Some(base)
+                    })
+                    .collect()
+            });
+
+            for (&(mesh, image), &base) in text.groups.iter().zip(bases.iter()) {
+                let (Some(mesh), Some(base)) = (batch_meshes.meshes.get(&mesh), base) else {
+                    continue;
+                };
+                let Some(gpu_image) = gpu_images.get(image) else {
+                    continue;
+                };
+
+                let mut key = key;
+                if mesh.texture_array {
+                    key |= BillboardPipelineKey::TEXTURE_ARRAY;
+                }
+
+                image_bind_groups.prepare(
+                    image,
+                    gpu_image,
+                    mesh.texture_array,
+                    &billboard_pipeline,
+                    &render_device,
+                );
+
+                let batch = view_batches.entry((image, key)).or_insert(ViewBatch {
+                    indices: Vec::new(),
+                    distance,
+                });
+                batch.distance = batch.distance.min(distance);
+                batch
+                    .indices
+                    .extend(mesh.indices.iter().map(|index| base + index));
+            }
+        }
+
+        for ((image, key), batch) in view_batches.drain() {
+            let start = batches.indices.len() as u32;
+            batches.indices.extend(batch.indices);
+            let indices = start..batches.indices.len() as u32;
+
+            let entity = commands
+                .spawn((
+                    RenderBillboardImage { id: image },
+                    BillboardTextBatch { indices },
+                ))
+                .id();
+            let pipeline =
+                billboard_pipelines.specialize(&pipeline_cache, &billboard_pipeline, key);
+
+            phase.add(pipeline, entity, batch.distance);
+        }
+    }
+}
+
+pub fn prepare_billboard_text_batches(
+    mut batches: ResMut<BillboardTextBatches>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    billboard_pipeline: Res<BillboardPipeline>,
+) {
+    let batches = batches.as_mut();
+    batches.bind_group = None;
+
+    if batches.vertices.is_empty() {
+        return;
+    }
+
+    batches.vertices.write_buffer(&render_device, &render_queue);
+    batches.indices.write_buffer(&render_device, &render_queue);
+    batches
+        .billboards
+        .write_buffer(&render_device, &render_queue);
+
+    let (Some(layout), Some(binding)) = (
+        billboard_pipeline.text_batch_layout.as_ref(),
+        batches.billboards.binding(),
+    ) else {
+        return;
+    };
+
+    batches.bind_group = Some(render_device.create_bind_group(
+        Some("billboard_text_batch_bind_group"),
+        layout,
+        &[BindGroupEntry {
+            binding: 0,
+            resource: binding,
+        }],
+    ));
+}
+
+pub struct SetBillboardTextBatchBindGroup<const I: usize>;
+impl<P: PhaseItem, const I: usize> RenderCommand<P> for SetBillboardTextBatchBindGroup<I> {
+    type Param = SRes<BillboardTextBatches>;
+    type ViewQuery = ();
+    type ItemQuery = ();
+
+    fn render<'w>(
+        _item: &P,
+        _view: ROQueryItem<'w, Self::ViewQuery>,
+        _item_query: Option<ROQueryItem<'w, Self::ItemQuery>>,
+        batches: SystemParamItem<'w, '_, Self::Param>,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        let Some(bind_group) = batches.into_inner().bind_group.as_ref() else {
+            return RenderCommandResult::Failure;
+        };
+
+        pass.set_bind_group(I, bind_group, &[]);
+
+        RenderCommandResult::Success
+    }
+}
+
+pub struct DrawBillboardTextBatchMesh;
+impl<P: PhaseItem> RenderCommand<P> for DrawBillboardTextBatchMesh {
+    type Param = SRes<BillboardTextBatches>;
+    type ViewQuery = ();
+    type ItemQuery = Read<BillboardTextBatch>;
+
+    fn render<'w>(
+        _item: &P,
+        _view: ROQueryItem<'w, Self::ViewQuery>,
+        batch: Option<ROQueryItem<'w, Self::ItemQuery>>,
+        batches: SystemParamItem<'w, '_, Self::Param>,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        let batches = batches.into_inner();
+
+        let (Some(batch), Some(vertices), Some(indices)) =
+            (batch, batches.vertices.buffer(), batches.indices.buffer())
+        else {
+            return RenderCommandResult::Failure;
+        };
+
+        pass.set_vertex_buffer(0, vertices.slice(..));
+        pass.set_index_buffer(indices.slice(..), 0, IndexFormat::Uint32);
+        pass.draw_indexed(batch.indices.clone(), 0, 0..1);
+
+        RenderCommandResult::Success
+    }
+}
+
+pub type DrawBillboardTextBatch = (
+    SetItemPipeline,
+    SetBillboardViewBindGroup<0>,
+    SetBillboardTextBatchBindGroup<1>,
+    SetBillboardTextureBindGroup<2>,
+    DrawBillboardTextBatchMesh,
+);
@@ -0,0 +1,353 @@
+use crate::gpu_culling::BillboardGpuCulling;
+use crate::instances::BillboardInstances;
+use crate::screen::BillboardScreenRects;
+use crate::Billboard;
+use bevy::ecs::entity::EntityHashMap;
+use bevy::math::FloatOrd;
+use bevy::prelude::*;
+use bevy::render::view::VisibleEntities;
+use std::cmp::Reverse;
+
+/// Overlap of rects in logical pixels small enough for them to only be touching.
+const TOUCHING: f32 = 1e-3;
+
+/// Keeps the billboards with a [`BillboardDeclutterPriority`] this camera sees from overlapping
+/// on its screen, the billboards of lower priority giving way.
+///
+/// A billboard is decluttered by the camera with the highest order that sees it, and is moved
+/// and faded the same way in the other cameras, which is meant for a single map or RTS camera.
+/// Billboards are placed one by one from the highest priority, so the cost grows with the square
+/// of the number of billboards on the screen.
+#[derive(Clone, Copy, Component, Debug, Reflect)]
+#[reflect(Component)]
+pub struct BillboardDeclutter {
+    pub mode: BillboardDeclutterMode,
+    /// Space kept around billboards, in logical pixels.
+    pub margin: f32,
+    /// Farthest a billboard is moved up by [`BillboardDeclutterMode::Nudge`] and
+    /// [`BillboardDeclutterMode::Stack`], in logical pixels. Billboards that would have to be
+    /// moved farther are hidden instead.
+    pub max_offset: f32,
+    /// Rate billboards move and fade to their decluttered state at, the higher the faster.
+    /// `f32::INFINITY` moves them right away.
+    pub transition_speed: f32,
+}
+
+impl Default for BillboardDeclutter {
+    fn default() -> Self {
+        Self {
+            mode: BillboardDeclutterMode::default(),
+            margin: 2.0,
+            max_offset: 100.0,
+            transition_speed: 10.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Reflect)]
+pub enum BillboardDeclutterMode {
+    /// Fades out the billboards overlapping one of higher priority.
+    #[default]
+    Hide,
+    /// Moves the billboards overlapping one of higher priority up, until they don't overlap
+    /// anything.
+    Nudge,
+    /// Stacks the billboards overlapping one of higher priority above it, in a column centered
+    /// on it.
+    Stack,
+}
+
+/// Opts a billboard into decluttering. Billboards of higher priority are kept in place, ties are
+/// broken by distance to the camera. Billboards with [`BillboardGpuCulling`] or
+/// [`BillboardInstances`] aren't decluttered.
+#[derive(Clone, Copy, Component, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Reflect)]
+#[reflect(Component)]
+pub struct BillboardDeclutterPriority(pub i32);
+
+/// Where decluttering moved a billboard and how faded it is, added by
+/// [`declutter_billboards`].
+#[derive(Clone, Copy, Component, Debug, PartialEq, Reflect)]
+#[reflect(Component)]
+pub struct BillboardDeclutterState {
+    /// Offset of the billboard from its transform, in world space.
+    pub(crate) offset: Vec3,
+    pub(crate) alpha: f32,
+    /// Offset on the screen of the camera decluttering the billboard, in logical pixels.
+    screen_offset: Vec2,
+}
+
+impl Default for BillboardDeclutterState {
+    fn default() -> Self {
+        Self {
+            offset: Vec3::ZERO,
+            alpha: 1.0,
+            screen_offset: Vec2::ZERO,
+        }
+    }
+}
+
+impl BillboardDeclutterState {
+    pub fn offset(&self) -> Vec3 {
+        self.offset
+    }
+
+    pub fn alpha(&self) -> f32 {
+        self.alpha
+    }
+
+    pub fn screen_offset(&self) -> Vec2 {
+        self.screen_offset
+    }
+}
+
+/// Decluttered state a billboard transitions to.
+#[derive(Clone, Copy)]
+struct DeclutterTarget {
+    camera: Entity,
+    screen_offset: Vec2,
+    hidden: bool,
+    transition_speed: f32,
+}
+
+type DeclutterStateQuery = (
+    Entity,
+    &'static GlobalTransform,
+    Option<&'static mut BillboardDeclutterState>,
+);
+
+/// Billboards being decluttered, and the ones going back to where they were.
+type DeclutterStateFilter = Or<(
+    With<BillboardDeclutterPriority>,
+    With<BillboardDeclutterState>,
+)>;
+
+/// Billboards that can be decluttered, the others are drawn without going through the CPU.
+type Decluttered = (
+    With<Billboard>,
+    Without<BillboardGpuCulling>,
+    Without<BillboardInstances>,
+);
+
+pub fn declutter_billboards(
+    mut commands: Commands,
+    time: Res<Time>,
+    cameras: Query<(Entity, &Camera, &GlobalTransform, &BillboardDeclutter)>,
+    mut params: ParamSet<(
+        BillboardScreenRects,
+        Query<&mut VisibleEntities>,
+        Query<DeclutterStateQuery, DeclutterStateFilter>,
+    )>,
+    billboards: Query<(&BillboardDeclutterPriority, &GlobalTransform), Decluttered>,
+) {
+    let mut cameras: Vec<_> = cameras
+        .iter()
+        .filter(|(_, camera, _, _)| camera.is_active)
+        .collect();
+    cameras.sort_by_key(|(_, camera, _, _)| Reverse(camera.order));
+
+    let mut targets = EntityHashMap::default();
+
+    for &(camera_entity, _, camera_transform, declutter) in &cameras {
+        let screen_rects = params.p0();
+
+        // Rects where the billboards would be without decluttering, highest priority first
+        let mut rects: Vec<_> = screen_rects
+            .iter_undecluttered(camera_entity)
+            .filter(|(entity, _)| !targets.contains_key(entity))
+            .filter_map(|(entity, rect)| {
+                let (priority, transform) = billboards.get(entity).ok()?;
+                let distance = transform
+                    .translation()
+                    .distance_squared(camera_transform.translation());
+                Some((
+                    entity,
+                    rect,
+                    (Reverse(*priority), FloatOrd(distance), entity),
+                ))
+            })
+            .collect();
+        rects.sort_by_key(|&(_, _, order)| order);
+
+        for (entity, screen_offset) in place_rects(&rects, declutter) {
+            targets.insert(
+                entity,
+                DeclutterTarget {
+                    camera: camera_entity,
+                    screen_offset: screen_offset.unwrap_or_default(),
+                    hidden: screen_offset.is_none(),
+                    transition_speed: declutter.transition_speed,
+                },
+            );
+        }
+    }
+
+    let delta = time.delta_seconds();
+    let mut hidden = Vec::new();
+
+    for (entity, transform, state) in &mut params.p2() {
+        let target = targets.get(&entity);
+        if target.is_none() && state.is_none() {
+            continue;
+        }
+
+        let mut next = state.as_deref().copied().unwrap_or_default();
+
+        // Billboards that aren't decluttered anymore go back to where they were
+        let (target_offset, target_alpha, speed) = match target {
+            Some(target) => (
+                target.screen_offset,
+                if target.hidden { 0.0 } else { 1.0 },
+                target.transition_speed,
+            ),
+            None => (
+                Vec2::ZERO,
+                1.0,
+                cameras
+                    .first()
+                    .map_or(f32::INFINITY, |(_, _, _, declutter)| {
+                        declutter.transition_speed
+                    }),
+            ),
+        };
+
+        let step = if speed.is_infinite() {
+            1.0
+        } else {
+            1.0 - (-speed * delta).exp()
+        };
+        next.screen_offset += (target_offset - next.screen_offset) * step;
+        next.alpha += (target_alpha - next.alpha) * step;
+
+        // Snapped once close enough, so settled billboards aren't changed every update
+        if next.screen_offset.distance_squared(target_offset) < 0.01 {
+            next.screen_offset = target_offset;
+        }
+        if (next.alpha - target_alpha).abs() < 0.01 {
+            next.alpha = target_alpha;
+        }
+
+        // Billboards going back are moved on the screen of the first decluttering camera
+        let camera = match target {
+            Some(target) => cameras.iter().find(|(camera, ..)| *camera == target.camera),
+            None => cameras.first(),
+        };
+        next.offset = camera
+            .and_then(|&(_, camera, camera_transform, _)| {
+                world_offset(camera, camera_transform, transform, next.screen_offset)
+            })
+            .unwrap_or(Vec3::ZERO);
+
+        if let Some(target) = target.filter(|target| target.hidden && next.alpha == 0.0) {
+            hidden.push((target.camera, entity));
+        }
+
+        match state {
+            Some(mut state) => {
+                state.set_if_neq(next);
+            }
+            None => {
+                commands.entity(entity).insert(next);
+            }
+        }
+    }
+
+    // Fully faded out billboards aren't drawn at all
+    let mut views = params.p1();
+    for (camera, entity) in hidden {
+        if let Ok(mut visible_entities) = views.get_mut(camera) {
+            visible_entities
+                .get_mut::<With<Billboard>>()
+                .retain(|visible| *visible != entity);
+        }
+    }
+}
+
+/// Offset on the screen of every billboard after decluttering, `None` for the hidden ones.
+fn place_rects<T>(
+    rects: &[(Entity, Rect, T)],
+    declutter: &BillboardDeclutter,
+) -> Vec<(Entity, Option<Vec2>)> {
+    let overlaps = |a: Rect, b: Rect| {
+        // Rects moved right next to each other can still touch by rounding errors
+        let overlap = a.inflate(declutter.margin).intersect(b);
+        overlap.width() > TOUCHING && overlap.height() > TOUCHING
+    };
+
+    // Rects of the billboards that were placed, with the billboard at the bottom of their
+    // stack, and the top of the stack of every billboard at the bottom of one
+    let mut placed: Vec<(Rect, usize)> = Vec::with_capacity(rects.len());
+    let mut tops: Vec<f32> = Vec::with_capacity(rects.len());
+    let mut offsets = Vec::with_capacity(rects.len());
+
+    for &(entity, rect, _) in rects {
+        let overlapped = placed.iter().position(|&(other, _)| overlaps(rect, other));
+
+        let (offset, anchor) = match (declutter.mode, overlapped) {
+            (_, None) => (Some(Vec2::ZERO), placed.len()),
+            (BillboardDeclutterMode::Hide, Some(_)) => (None, 0),
+            (BillboardDeclutterMode::Nudge, Some(_)) => {
+                let mut moved = rect;
+                // Every move clears a placed rect, so this ends after as many moves at most
+                for _ in 0..=placed.len() {
+                    let Some(&(other, _)) =
+                        placed.iter().find(|&&(other, _)| overlaps(moved, other))
+                    else {
+                        break;
+                    };
+                    let up = moved.max.y - other.min.y + declutter.margin;
+                    moved.min.y -= up;
+                    moved.max.y -= up;
+                }
+
+                let offset = moved.min - rect.min;
+                let clear = !placed.iter().any(|&(other, _)| overlaps(moved, other));
+                let offset = (clear && offset.length() <= declutter.max_offset).then_some(offset);
+                (offset, placed.len())
+            }
+            (BillboardDeclutterMode::Stack, Some(overlapped)) => {
+                let anchor = placed[overlapped].1;
+                let offset = Vec2::new(
+                    placed[anchor].0.center().x - rect.center().x,
+                    tops[anchor] - declutter.margin - rect.max.y,
+                );
+
+                // The top of the column can be taken by a neighboring billboard or column
+                let moved = Rect::from_corners(rect.min + offset, rect.max + offset);
+                let clear = !placed.iter().any(|&(other, _)| overlaps(moved, other));
+                let offset = (clear && offset.length() <= declutter.max_offset).then_some(offset);
+                (offset, anchor)
+            }
+        };
+
+        if let Some(offset) = offset {
+            let moved = Rect::from_corners(rect.min + offset, rect.max + offset);
+            placed.push((moved, anchor));
+            tops.push(moved.min.y);
+            tops[anchor] = moved.min.y;
+        }
+        offsets.push((entity, offset));
+    }
+
+    offsets
+}
+
+/// Offset in world space moving a billboard by `screen_offset` on the screen of a camera, in
+/// the plane facing the camera at the billboard's distance.
+fn world_offset(
+    camera: &Camera,
+    camera_transform: &GlobalTransform,
+    transform: &GlobalTransform,
+    screen_offset: Vec2,
+) -> Option<Vec3> {
+    if screen_offset == Vec2::ZERO {
+        return Some(Vec3::ZERO);
+    }
+
+    let center = transform.translation();
+    let position = camera.world_to_viewport(camera_transform, center)?;
+    let ray = camera.viewport_to_world(camera_transform, position + screen_offset)?;
+
+    let normal = camera_transform.forward();
+    let distance = ray.intersect_plane(center, InfinitePlane3d::new(*normal))?;
+    Some(ray.get_point(distance) - center)
+}
@@ -0,0 +1,886 @@
+use crate::oit::{BillboardOit, BillboardViewPhase};
+use crate::pipeline::{
+    BillboardMeshPipelines, BillboardPhases, BillboardPipeline, BillboardPipelineKey,
+    BillboardUniform, RenderBillboardImage, RenderBillboardMesh, SetBillboardTextureBindGroup,
+    SetBillboardViewBindGroup,
+};
+use crate::text::{RenderBillboard, ATTRIBUTE_ATLAS_LAYER};
+use crate::utils::{calculate_billboard_uniform, ExtractedBillboards};
+use crate::visibility::{mesh_radius, BillboardCameras};
+use crate::{
+    BillboardDepth, BillboardLockAxis, BillboardMeshHandle, BillboardSoftEdge, BillboardSortBias,
+    BillboardTextureHandle, BILLBOARD_GPU_CULLING_SHADER_HANDLE,
+};
+use bevy::core_pipeline::prepass::DepthPrepass;
+use bevy::ecs::query::{QueryItem, ROQueryItem};
+use bevy::ecs::system::lifetimeless::{Read, SRes};
+use bevy::ecs::system::{SystemParamItem, SystemState};
+use bevy::log::{error, warn_once};
+use bevy::prelude::*;
+use bevy::render::mesh::{GpuBufferInfo, GpuMesh};
+use bevy::render::primitives::Frustum;
+use bevy::render::render_asset::RenderAssets;
+use bevy::render::render_graph::{NodeRunError, RenderGraphContext, RenderLabel, ViewNode};
+use bevy::render::render_phase::{
+    PhaseItem, RenderCommand, RenderCommandResult, SetItemPipeline, TrackedRenderPass,
+};
+use bevy::render::render_resource::{
+    BindGroup, BindGroupEntry, BindGroupLayout, BindGroupLayoutEntry, BindingType, Buffer,
+    BufferBindingType, BufferDescriptor, BufferUsages, CachedComputePipelineId,
+    ComputePassDescriptor, ComputePipelineDescriptor, DynamicUniformBuffer, PipelineCache,
+    RawBufferVec, ShaderStages, ShaderType, StorageBuffer,
+};
+use bevy::render::renderer::{RenderAdapter, RenderContext, RenderDevice, RenderQueue};
+use bevy::render::texture::GpuImage;
+use bevy::render::view::{ExtractedView, RenderLayers};
+use bevy::render::Extract;
+use bevy::utils::HashMap;
+use bytemuck::{Pod, Zeroable};
+use wgpu::DownlevelFlags;
+
+/// Instances culled by one invocation of the culling compute shader.
+const WORKGROUP_SIZE: u32 = 64;
+
+/// Culls a texture billboard on the GPU, against the camera frustum and `max_distance`, and
+/// draws it in a single indirect draw together with every billboard sharing its mesh, texture
+/// and settings.
+///
+/// Meant for large numbers of billboards like grass, debris or star fields. They skip being
+/// extracted and queued one by one, but are sorted as a group against other transparent
+/// billboards, by the farthest corner of the bounds of their positions, and by their
+/// [`BillboardSortBias`] only if they all share it. They are on the default render layer, views
+/// can't be filtered per billboard on the GPU, so billboards with `RenderLayers` or
+/// [`BillboardCameras`] are drawn like any other instead, with a warning. So are billboards where
+/// compute shaders aren't available (e.g. WebGL2). Has no effect on texts.
+///
+/// [`BillboardSortBias`]: crate::BillboardSortBias
+#[derive(Clone, Copy, Component, Debug, Reflect)]
+#[reflect(Component)]
+pub struct BillboardGpuCulling {
+    /// Distance from the camera beyond which the billboard isn't drawn.
+    pub max_distance: f32,
+}
+
+impl Default for BillboardGpuCulling {
+    fn default() -> Self {
+        Self {
+            max_distance: f32::INFINITY,
+        }
+    }
+}
+
+/// Billboards culled on the GPU, when the device supports it.
+pub(crate) type GpuCulled = (
+    With<BillboardGpuCulling>,
+    With<BillboardMeshHandle>,
+    With<BillboardTextureHandle>,
+    Without<RenderLayers>,
+    Without<BillboardCameras>,
+);
+
+/// Whether the device can cull billboards, for the main world to leave [`GpuCulled`] billboards
+/// out of frustum culling.
+#[derive(Resource, Clone, Copy, Default)]
+pub struct GpuCullingSupported(pub(crate) bool);
+
+pub(crate) fn gpu_culling_supported(
+    render_device: &RenderDevice,
+    render_adapter: &RenderAdapter,
+) -> bool {
+    // Instances are culled into storage buffers read by the vertex shader of indirect draws
+    let flags = render_adapter.get_downlevel_capabilities().flags;
+
+    flags.contains(
+        DownlevelFlags::COMPUTE_SHADERS
+            | DownlevelFlags::INDIRECT_EXECUTION
+            | DownlevelFlags::VERTEX_STORAGE,
+    ) && render_device.limits().max_storage_buffers_per_shader_stage >= 3
+}
+
+pub use shader_types::{BillboardGpuCullUniform, BillboardGpuInstance};
+
+// Holds only the structs shared with the culling shader, whose `ShaderType` field checks are
+// never called
+#[allow(dead_code)]
+mod shader_types {
+    use crate::pipeline::BillboardUniform;
+    use bevy::math::{Vec3, Vec4};
+    use bevy::render::render_resource::ShaderType;
+
+    #[derive(Clone, Copy, ShaderType)]
+    pub struct BillboardGpuInstance {
+        pub(super) billboard: BillboardUniform,
+        pub(super) max_distance: f32,
+    }
+
+    /// Culling of the instances of a group for a view.
+    #[derive(Clone, Copy, ShaderType)]
+    pub struct BillboardGpuCullUniform {
+        /// Every half space of the view frustum except the far one, which is left to the
+        /// `max_distance` of the instances.
+        pub(super) frustum: [Vec4; 5],
+        pub(super) camera_position: Vec3,
+        pub(super) mesh_radius: f32,
+        pub(super) instance_offset: u32,
+        pub(super) instance_count: u32,
+        /// Start of the indices of the instances that survive culling in the visible buffer.
+        pub(super) visible_offset: u32,
+        pub(super) indirect_index: u32,
+    }
+}
+
+impl BillboardGpuCullUniform {
+    /// Culling against the view frustum of `clip_from_world`, for a group whose mesh reaches
+    /// `mesh_radius` from its origin. The instances of the group are placed by the queue system.
+    pub fn new(clip_from_world: &Mat4, camera_position: Vec3, mesh_radius: f32) -> Self {
+        let half_spaces = Frustum::from_clip_from_world(clip_from_world).half_spaces;
+
+        Self {
+            frustum: std::array::from_fn(|index| half_spaces[index].normal_d()),
+            camera_position,
+            mesh_radius,
+            instance_offset: 0,
+            instance_count: 0,
+            visible_offset: 0,
+            indirect_index: 0,
+        }
+    }
+
+    /// Whether an instance with the `model` matrix survives culling, computed the same way as in
+    /// the culling shader.
+    pub fn is_visible(&self, model: &Mat4, max_distance: f32) -> bool {
+        let center = model.w_axis.truncate();
+        if center.distance(self.camera_position) > max_distance {
+            return false;
+        }
+
+        // Bounding sphere of the mesh facing the camera in any direction
+        let scale = model
+            .x_axis
+            .truncate()
+            .length()
+            .max(model.y_axis.truncate().length())
+            .max(model.z_axis.truncate().length());
+        let radius = self.mesh_radius * scale;
+
+        self.frustum
+            .iter()
+            .all(|half_space| half_space.truncate().dot(center) + half_space.w + radius > 0.0)
+    }
+}
+
+/// Billboards culled on the GPU this frame, grouped by what they are drawn with.
+#[derive(Resource, Default)]
+pub struct ExtractedBillboardGpuInstances {
+    /// Whether the device can cull billboards, they are drawn one by one otherwise.
+    pub supported: bool,
+    pub(crate) groups: HashMap<GpuInstanceGroupKey, GpuInstanceGroup>,
+}
+
+impl ExtractedBillboardGpuInstances {
+    /// Number of billboards culled on the GPU this frame.
+    pub fn len(&self) -> usize {
+        self.groups
+            .values()
+            .map(|group| group.instances.len())
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct GpuInstanceGroupKey {
+    mesh: AssetId<Mesh>,
+    image: AssetId<Image>,
+    depth: bool,
+    lock_y: bool,
+    lock_rotation: bool,
+    soft_edge: bool,
+}
+
+pub(crate) struct GpuInstanceGroup {
+    /// Settings of the billboard the group was created for, which only differ from the others in
+    /// what isn't part of [`GpuInstanceGroupKey`].
+    billboard: RenderBillboard,
+    instances: Vec<BillboardGpuInstance>,
+    /// Bounds of the instance positions, the group is sorted by their farthest corner.
+    min: Vec3,
+    max: Vec3,
+    /// Sort bias shared by every instance, `None` if they differ.
+    sort_bias: Option<f32>,
+    /// Distance of the farthest vertex of the mesh from its origin, if it's loaded.
+    mesh_radius: Option<f32>,
+}
+
+type GpuCulledBillboardQuery = (
+    Entity,
+    &'static InheritedVisibility,
+    Ref<'static, GlobalTransform>,
+    Ref<'static, Transform>,
+    &'static BillboardMeshHandle,
+    &'static BillboardTextureHandle,
+    &'static BillboardDepth,
+    Option<&'static BillboardLockAxis>,
+    Option<&'static BillboardSoftEdge>,
+    Option<&'static BillboardSortBias>,
+    &'static BillboardGpuCulling,
+);
+
+/// Billboards asking for GPU culling while only drawn for some cameras, which it can't do.
+type GpuCullingRefused = (
+    With<BillboardGpuCulling>,
+    Or<(With<RenderLayers>, With<BillboardCameras>)>,
+);
+
+pub fn extract_billboard_gpu_instances(
+    mut gpu_instances: ResMut<ExtractedBillboardGpuInstances>,
+    mut extracted: Local<ExtractedBillboards<(BillboardUniform, RenderBillboard)>>,
+    mut mesh_radii: Local<HashMap<AssetId<Mesh>, f32>>,
+    mut mesh_events: Extract<EventReader<AssetEvent<Mesh>>>,
+    meshes: Extract<Res<Assets<Mesh>>>,
+    billboard_query: Extract<Query<GpuCulledBillboardQuery, GpuCulled>>,
+    refused_query: Extract<Query<(), GpuCullingRefused>>,
+) {
+    for event in mesh_events.read() {
+        match event {
+            AssetEvent::Modified { id }
+            | AssetEvent::Removed { id }
+            | AssetEvent::Unused { id } => {
+                mesh_radii.remove(id);
+            }
+            AssetEvent::Added { .. } | AssetEvent::LoadedWithDependencies { .. } => {}
+        }
+    }
+
+    if !gpu_instances.supported {
+        return;
+    }
+
+    if !refused_query.is_empty() {
+        warn_once!(
+            "BillboardGpuCulling is ignored on billboards with RenderLayers or BillboardCameras, \
+            they are drawn one by one instead."
+        );
+    }
+
+    // Groups are kept from the last frame to reuse their allocations
+    for group in gpu_instances.groups.values_mut() {
+        group.instances.clear();
+        group.min = Vec3::INFINITY;
+        group.max = Vec3::NEG_INFINITY;
+    }
+
+    for (
+        entity,
+        visibility,
+        global_transform,
+        transform,
+        billboard_mesh,
+        billboard_texture,
+        &depth,
+        lock_axis,
+        soft_edge,
+        sort_bias,
+        culling,
+    ) in &billboard_query
+    {
+        let billboard = RenderBillboard {
+            depth,
+            lock_axis: lock_axis.copied(),
+            soft_edge: soft_edge.copied(),
+            sort_bias: sort_bias.map_or(0.0, |bias| bias.0),
+        };
+        let &(uniform, billboard) = extracted.get_or_extract(
+            entity,
+            |(_, extracted)| {
+                global_transform.is_changed() || transform.is_changed() || *extracted != billboard
+            },
+            || {
+                let uniform = calculate_billboard_uniform(
+                    &global_transform,
+                    &transform,
+                    lock_axis,
+                    soft_edge,
+                    None,
+                );
+                (uniform, billboard)
+            },
+        );
+
+        // Culled against the views on the GPU instead
+        if !visibility.get() {
+            continue;
+        }
+
+        let key = GpuInstanceGroupKey {
+            mesh: billboard_mesh.0.id(),
+            image: billboard_texture.0.id(),
+            depth: depth.0,
+            lock_y: lock_axis.is_some_and(|lock| lock.y_axis),
+            lock_rotation: lock_axis.is_some_and(|lock| lock.rotation),
+            soft_edge: soft_edge.is_some_and(|soft_edge| soft_edge.0 > 0.0),
+        };
+        // Every setting the pipeline is specialized from is part of the key, while the soft edge
+        // distance of every instance is in its own uniform
+        let group = gpu_instances
+            .groups
+            .entry(key)
+            .or_insert_with(|| GpuInstanceGroup {
+                billboard,
+                instances: Vec::new(),
+                min: Vec3::INFINITY,
+                max: Vec3::NEG_INFINITY,
+                sort_bias: None,
+                mesh_radius: None,
+            });
+
+        if group.instances.is_empty() {
+            group.sort_bias = Some(billboard.sort_bias);
+        } else if group.sort_bias != Some(billboard.sort_bias) {
+            group.sort_bias = None;
+        }
+
+        let position = uniform.transform().w_axis.truncate();
+        group.min = group.min.min(position);
+        group.max = group.max.max(position);
+        group.instances.push(BillboardGpuInstance {
+            billboard: uniform,
+            max_distance: culling.max_distance,
+        });
+    }
+
+    extracted.finish_frame();
+    gpu_instances
+        .groups
+        .retain(|_, group| !group.instances.is_empty());
+
+    for (key, group) in &mut gpu_instances.groups {
+        if !mesh_radii.contains_key(&key.mesh) {
+            if let Some(radius) = meshes.get(key.mesh).and_then(mesh_radius) {
+                mesh_radii.insert(key.mesh, radius);
+            }
+        }
+
+        group.mesh_radius = mesh_radii.get(&key.mesh).copied();
+    }
+}
+
+/// Arguments of an indirect draw, laid out for both indexed and non-indexed draws.
+#[derive(Clone, Copy, Pod, Zeroable)]
+#[repr(C)]
+pub struct BillboardIndirectArgs {
+    count: u32,
+    instance_count: u32,
+    first: u32,
+    base_vertex: i32,
+    first_instance: u32,
+}
+
+/// Instances, culling uniforms and indirect draws of every view this frame.
+#[derive(Resource)]
+pub struct BillboardGpuInstances {
+    instances: StorageBuffer<Vec<BillboardGpuInstance>>,
+    culls: DynamicUniformBuffer<BillboardGpuCullUniform>,
+    indirect: RawBufferVec<BillboardIndirectArgs>,
+    /// Indices of the instances that survive culling, written by the compute shader.
+    visible: Option<Buffer>,
+    visible_len: u64,
+    cull_bind_group: Option<BindGroup>,
+    draw_bind_group: Option<BindGroup>,
+}
+
+impl Default for BillboardGpuInstances {
+    fn default() -> Self {
+        Self {
+            instances: StorageBuffer::default(),
+            culls: DynamicUniformBuffer::default(),
+            indirect: RawBufferVec::new(BufferUsages::INDIRECT | BufferUsages::STORAGE),
+            visible: None,
+            visible_len: 0,
+            cull_bind_group: None,
+            draw_bind_group: None,
+        }
+    }
+}
+
+/// Indirect draw of the instances of a group that survive culling for a view.
+#[derive(Component, Clone)]
+pub struct BillboardGpuCull {
+    pub uniform_offset: u32,
+    pub indirect_index: u32,
+}
+
+/// Culling dispatches of a view, run before its main pass.
+#[derive(Component, Default)]
+pub struct BillboardViewGpuCulls(Vec<ViewGpuCull>);
+
+struct ViewGpuCull {
+    uniform_offset: u32,
+    workgroups: u32,
+}
+
+type GpuCullingViews = (
+    Entity,
+    &'static ExtractedView,
+    Option<&'static RenderLayers>,
+    Has<DepthPrepass>,
+    Has<BillboardOit>,
+);
+
+pub fn queue_billboard_gpu_instances(
+    mut commands: Commands,
+    views: Query<GpuCullingViews>,
+    phases: BillboardPhases,
+    mesh_pipelines: BillboardMeshPipelines,
+    (gpu_images, gpu_meshes): (Res<RenderAssets<GpuImage>>, Res<RenderAssets<GpuMesh>>),
+    extracted: Res<ExtractedBillboardGpuInstances>,
+    mut gpu_instances: ResMut<BillboardGpuInstances>,
+) {
+    let BillboardPhases {
+        transparent: mut transparent_render_phases,
+        oit: mut oit_render_phases,
+        transparent_draw_functions,
+        oit_draw_functions,
+    } = phases;
+    let BillboardMeshPipelines {
+        pipeline_cache,
+        pipelines: mut billboard_pipelines,
+        pipeline: billboard_pipeline,
+        mut image_bind_groups,
+        render_device,
+        msaa,
+    } = mesh_pipelines;
+
+    let gpu_instances = gpu_instances.as_mut();
+    gpu_instances.instances.get_mut().clear();
+    gpu_instances.culls.clear();
+    gpu_instances.indirect.clear();
+    gpu_instances.visible_len = 0;
+
+    if extracted.groups.is_empty() {
+        return;
+    }
+
+    // Instances are shared by every view, each view culls them into its own visible range
+    let mut groups = Vec::with_capacity(extracted.groups.len());
+    for (key, group) in &extracted.groups {
+        let instances = gpu_instances.instances.get_mut();
+        groups.push((key, group, instances.len() as u32));
+        instances.extend_from_slice(&group.instances);
+    }
+
+    for (view_entity, view, view_layers, depth_prepass, oit) in &views {
+        // Culled billboards are on the default layer
+        if view_layers.is_some_and(|layers| !layers.intersects(&RenderLayers::default())) {
+            continue;
+        }
+
+        let Some(mut phase) = BillboardViewPhase::get::<DrawBillboardGpuCulled>(
+            view_entity,
+            oit,
+            &mut transparent_render_phases,
+            &mut oit_render_phases,
+            &transparent_draw_functions,
+            &oit_draw_functions,
+        ) else {
+            continue;
+        };
+
+        let rangefinder = view.rangefinder3d();
+        let clip_from_world = view.clip_from_world.unwrap_or_else(|| {
+            view.clip_from_view * view.world_from_view.compute_matrix().inverse()
+        });
+        let view_cull =
+            BillboardGpuCullUniform::new(&clip_from_world, view.world_from_view.translation(), 0.0);
+
+        let mut view_culls = BillboardViewGpuCulls::default();
+
+        for &(key, group, instance_offset) in &groups {
+            let Some(mesh_radius) = group.mesh_radius else {
+                continue;
+            };
+            let Some(gpu_image) = gpu_images.get(key.image) else {
+                continue;
+            };
+            let Some(gpu_mesh) = gpu_meshes.get(key.mesh) else {
+                continue;
+            };
+
+            let pipeline_key = BillboardPipelineKey::from_billboard(
+                &group.billboard,
+                view,
+                msaa.samples(),
+                phase.is_oit(),
+                depth_prepass,
+            ) | BillboardPipelineKey::GPU_CULLING;
+
+            let pipeline = match billboard_pipelines.specialize(
+                &pipeline_cache,
+                &billboard_pipeline,
+                pipeline_key,
+                &gpu_mesh.layout,
+            ) {
+                Ok(id) => id,
+                Err(err) => {
+                    error!("{err:?}");
+                    continue;
+                }
+            };
+
+            image_bind_groups.prepare(
+                key.image,
+                gpu_image,
+                gpu_mesh.layout.0.contains(ATTRIBUTE_ATLAS_LAYER),
+                &billboard_pipeline,
+                &render_device,
+            );
+
+            let count = match &gpu_mesh.buffer_info {
+                GpuBufferInfo::Indexed { count, .. } => *count,
+                GpuBufferInfo::NonIndexed => gpu_mesh.vertex_count,
+            };
+            let indirect_index = gpu_instances.indirect.push(BillboardIndirectArgs {
+                count,
+                instance_count: 0,
+                first: 0,
+                base_vertex: 0,
+                first_instance: 0,
+            }) as u32;
+
+            let instance_count = group.instances.len() as u32;
+            let uniform_offset = gpu_instances.culls.push(&BillboardGpuCullUniform {
+                mesh_radius,
+                instance_offset,
+                instance_count,
+                visible_offset: gpu_instances.visible_len as u32,
+                indirect_index,
+                ..view_cull
+            });
+            gpu_instances.visible_len += instance_count as u64;
+
+            view_culls.0.push(ViewGpuCull {
+                uniform_offset,
+                workgroups: instance_count.div_ceil(WORKGROUP_SIZE),
+            });
+
+            let entity = commands
+                .spawn((
+                    RenderBillboardMesh { id: key.mesh },
+                    RenderBillboardImage { id: key.image },
+                    BillboardGpuCull {
+                        uniform_offset,
+                        indirect_index,
+                    },
+                ))
+                .id();
+            // Sorted like a text batch, by its farthest member, which is at most as far as the
+            // farthest corner of the bounds
+            let distance = (0..8)
+                .map(|corner| {
+                    let corner = Vec3::select(
+                        BVec3::new(corner & 1 != 0, corner & 2 != 0, corner & 4 != 0),
+                        group.max,
+                        group.min,
+                    );
+                    rangefinder.distance_translation(&corner)
+                })
+                .fold(f32::INFINITY, f32::min)
+                + group.sort_bias.unwrap_or(0.0);
+
+            phase.add(pipeline, entity, distance);
+        }
+
+        commands.entity(view_entity).insert(view_culls);
+    }
+}
+
+pub fn prepare_billboard_gpu_instances(
+    mut gpu_instances: ResMut<BillboardGpuInstances>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    billboard_pipeline: Res<BillboardPipeline>,
+    cull_pipeline: Option<Res<BillboardGpuCullingPipeline>>,
+) {
+    let gpu_instances = gpu_instances.as_mut();
+    gpu_instances.cull_bind_group = None;
+    gpu_instances.draw_bind_group = None;
+
+    let (Some(cull_pipeline), Some(draw_layout)) = (
+        cull_pipeline,
+        billboard_pipeline.gpu_culling_layout.as_ref(),
+    ) else {
+        return;
+    };
+
+    if gpu_instances.indirect.is_empty() {
+        return;
+    }
+
+    gpu_instances
+        .instances
+        .write_buffer(&render_device, &render_queue);
+    gpu_instances
+        .culls
+        .write_buffer(&render_device, &render_queue);
+    gpu_instances
+        .indirect
+        .write_buffer(&render_device, &render_queue);
+
+    let visible_size = gpu_instances.visible_len.max(1) * std::mem::size_of::<u32>() as u64;
+    if gpu_instances
+        .visible
+        .as_ref()
+        .is_none_or(|visible| visible.size() < visible_size)
+    {
+        gpu_instances.visible = Some(render_device.create_buffer(&BufferDescriptor {
+            label: Some("billboard_gpu_visible_instances"),
+            size: visible_size.next_power_of_two(),
+            usage: BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        }));
+    }
+
+    let (Some(instances), Some(culls), Some(indirect), Some(visible)) = (
+        gpu_instances.instances.binding(),
+        gpu_instances.culls.binding(),
+        gpu_instances.indirect.buffer(),
+        gpu_instances.visible.as_ref(),
+    ) else {
+        return;
+    };
+
+    gpu_instances.cull_bind_group = Some(render_device.create_bind_group(
+        Some("billboard_gpu_cull_bind_group"),
+        &cull_pipeline.layout,
+        &[
+            BindGroupEntry {
+                binding: 0,
+                resource: instances.clone(),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: visible.as_entire_binding(),
+            },
+            BindGroupEntry {
+                binding: 2,
+                resource: indirect.as_entire_binding(),
+            },
+            BindGroupEntry {
+                binding: 3,
+                resource: culls.clone(),
+            },
+        ],
+    ));
+
+    gpu_instances.draw_bind_group = Some(render_device.create_bind_group(
+        Some("billboard_gpu_draw_bind_group"),
+        draw_layout,
+        &[
+            BindGroupEntry {
+                binding: 0,
+                resource: instances,
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: visible.as_entire_binding(),
+            },
+            BindGroupEntry {
+                binding: 2,
+                resource: culls,
+            },
+        ],
+    ));
+}
+
+#[derive(Resource)]
+pub struct BillboardGpuCullingPipeline {
+    layout: BindGroupLayout,
+    pipeline: CachedComputePipelineId,
+}
+
+impl FromWorld for BillboardGpuCullingPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let mut system_state: SystemState<(Res<RenderDevice>, Res<PipelineCache>)> =
+            SystemState::new(world);
+
+        let (render_device, pipeline_cache) = system_state.get(world);
+
+        let storage_entry = |binding, read_only| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        let layout = render_device.create_bind_group_layout(
+            "billboard_gpu_culling_layout",
+            &[
+                storage_entry(0, true),
+                storage_entry(1, false),
+                storage_entry(2, false),
+                BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: Some(BillboardGpuCullUniform::min_size()),
+                    },
+                    count: None,
+                },
+            ],
+        );
+
+        let pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            label: Some("billboard_gpu_culling_pipeline".into()),
+            layout: vec![layout.clone()],
+            push_constant_ranges: vec![],
+            shader: BILLBOARD_GPU_CULLING_SHADER_HANDLE,
+            shader_defs: vec![],
+            entry_point: "cull_instances".into(),
+        });
+
+        Self { layout, pipeline }
+    }
+}
+
+#[derive(RenderLabel, Debug, Clone, Hash, PartialEq, Eq)]
+pub struct BillboardGpuCullingPass;
+
+#[derive(Default)]
+pub struct BillboardGpuCullingNode;
+
+impl ViewNode for BillboardGpuCullingNode {
+    type ViewQuery = &'static BillboardViewGpuCulls;
+
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        view_culls: QueryItem<Self::ViewQuery>,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        if view_culls.0.is_empty() {
+            return Ok(());
+        }
+
+        let Some(bind_group) = world
+            .resource::<BillboardGpuInstances>()
+            .cull_bind_group
+            .as_ref()
+        else {
+            return Ok(());
+        };
+
+        // Nothing is drawn until the pipeline is ready, the indirect draws have no instances
+        let Some(pipeline) = world
+            .resource::<PipelineCache>()
+            .get_compute_pipeline(world.resource::<BillboardGpuCullingPipeline>().pipeline)
+        else {
+            return Ok(());
+        };
+
+        let mut compute_pass =
+            render_context
+                .command_encoder()
+                .begin_compute_pass(&ComputePassDescriptor {
+                    label: Some("billboard_gpu_culling_pass"),
+                    timestamp_writes: None,
+                });
+
+        compute_pass.set_pipeline(pipeline);
+
+        for cull in &view_culls.0 {
+            compute_pass.set_bind_group(0, bind_group, &[cull.uniform_offset]);
+            compute_pass.dispatch_workgroups(cull.workgroups, 1, 1);
+        }
+
+        Ok(())
+    }
+}
+
+pub struct SetBillboardGpuCullingBindGroup<const I: usize>;
+impl<P: PhaseItem, const I: usize> RenderCommand<P> for SetBillboardGpuCullingBindGroup<I> {
+    type Param = SRes<BillboardGpuInstances>;
+    type ViewQuery = ();
+    type ItemQuery = Read<BillboardGpuCull>;
+
+    fn render<'w>(
+        _item: &P,
+        _view: ROQueryItem<'w, Self::ViewQuery>,
+        cull: Option<ROQueryItem<'w, Self::ItemQuery>>,
+        gpu_instances: SystemParamItem<'w, '_, Self::Param>,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        let (Some(cull), Some(bind_group)) =
+            (cull, gpu_instances.into_inner().draw_bind_group.as_ref())
+        else {
+            return RenderCommandResult::Failure;
+        };
+
+        pass.set_bind_group(I, bind_group, &[cull.uniform_offset]);
+
+        RenderCommandResult::Success
+    }
+}
+
+pub struct DrawBillboardGpuInstances;
+impl<P: PhaseItem> RenderCommand<P> for DrawBillboardGpuInstances {
+    type Param = (SRes<RenderAssets<GpuMesh>>, SRes<BillboardGpuInstances>);
+    type ViewQuery = ();
+    type ItemQuery = (Read<RenderBillboardMesh>, Read<BillboardGpuCull>);
+
+    fn render<'w>(
+        _item: &P,
+        _view: ROQueryItem<'w, Self::ViewQuery>,
+        item: Option<ROQueryItem<'w, Self::ItemQuery>>,
+        (meshes, gpu_instances): SystemParamItem<'w, '_, Self::Param>,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        let Some((mesh, cull)) = item else {
+            return RenderCommandResult::Failure;
+        };
+        let (Some(gpu_mesh), Some(indirect)) = (
+            meshes.into_inner().get(mesh.id),
+            gpu_instances.into_inner().indirect.buffer(),
+        ) else {
+            return RenderCommandResult::Failure;
+        };
+
+        let indirect_offset =
+            cull.indirect_index as u64 * std::mem::size_of::<BillboardIndirectArgs>() as u64;
+
+        pass.set_vertex_buffer(0, gpu_mesh.vertex_buffer.slice(..));
+
+        match &gpu_mesh.buffer_info {
+            GpuBufferInfo::Indexed {
+                buffer,
+                index_format,
+                ..
+            } => {
+                pass.set_index_buffer(buffer.slice(..), 0, *index_format);
+                pass.draw_indexed_indirect(indirect, indirect_offset);
+            }
+            GpuBufferInfo::NonIndexed => {
+                pass.draw_indirect(indirect, indirect_offset);
+            }
+        }
+
+        RenderCommandResult::Success
+    }
+}
+
+pub type DrawBillboardGpuCulled = (
+    SetItemPipeline,
+    SetBillboardViewBindGroup<0>,
+    SetBillboardGpuCullingBindGroup<1>,
+    SetBillboardTextureBindGroup<2>,
+    DrawBillboardGpuInstances,
+);
@@ -0,0 +1,378 @@
+use crate::oit::BillboardViewPhase;
+use crate::pipeline::{
+    BillboardImageBindGroups, BillboardPhases, BillboardPipeline, BillboardPipelineKey,
+    BillboardUniform, BillboardViews, RenderBillboardImage, SetBillboardBindGroup,
+    SetBillboardTextureBindGroup, SetBillboardViewBindGroup,
+};
+use crate::text::RenderBillboard;
+use crate::utils::{calculate_billboard_uniform, ExtractedBillboards};
+use crate::{
+    Billboard, BillboardDepth, BillboardLockAxis, BillboardSoftEdge, BillboardSortBias,
+    BillboardTextureHandle,
+};
+use bevy::ecs::entity::{EntityHashMap, EntityHashSet};
+use bevy::ecs::query::ROQueryItem;
+use bevy::ecs::system::lifetimeless::SRes;
+use bevy::ecs::system::SystemParamItem;
+use bevy::prelude::*;
+use bevy::render::render_asset::RenderAssets;
+use bevy::render::render_phase::{
+    PhaseItem, RenderCommand, RenderCommandResult, SetItemPipeline, TrackedRenderPass,
+};
+use bevy::render::render_resource::{
+    Buffer, BufferInitDescriptor, BufferUsages, PipelineCache, SpecializedRenderPipelines,
+};
+use bevy::render::renderer::RenderDevice;
+use bevy::render::texture::GpuImage;
+use bevy::render::Extract;
+use bevy::utils::HashSet;
+use bytemuck::{Pod, Zeroable};
+
+/// Many billboards drawn from a single entity, e.g. static decoration. They share the texture,
+/// transform, lock axis and other settings of the entity, and are drawn in one instanced draw,
+/// so they can't be sorted against each other.
+#[derive(Clone, Component, Debug, Default, Reflect)]
+#[reflect(Component)]
+pub struct BillboardInstances {
+    pub instances: Vec<BillboardInstance>,
+    /// Layout of the texture atlas the `atlas_index` of the instances is a texture of. Without
+    /// one, instances show the whole texture. Read again when the instances or the layout
+    /// change.
+    pub atlas: Option<Handle<TextureAtlasLayout>>,
+}
+
+#[derive(Clone, Copy, Debug, Reflect)]
+pub struct BillboardInstance {
+    /// Position of the center of the instance, relative to the entity.
+    pub position: Vec3,
+    pub size: Vec2,
+    /// Tint of the texture.
+    pub color: Color,
+    pub atlas_index: usize,
+}
+
+impl Default for BillboardInstance {
+    fn default() -> Self {
+        Self {
+            position: Vec3::ZERO,
+            size: Vec2::ONE,
+            color: Color::WHITE,
+            atlas_index: 0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Pod, Zeroable)]
+#[repr(C)]
+pub struct BillboardInstanceVertex {
+    pub position: [f32; 3],
+    pub size: [f32; 2],
+    pub color: [f32; 4],
+    /// Minimum and maximum uv of the texture of the instance.
+    pub uv_rect: [f32; 4],
+}
+
+impl BillboardInstanceVertex {
+    pub(crate) fn new(instance: &BillboardInstance, atlas: Option<&TextureAtlasLayout>) -> Self {
+        let uv_rect = atlas
+            .and_then(|atlas| {
+                let rect = atlas.textures.get(instance.atlas_index)?.as_rect();
+                let size = atlas.size.as_vec2();
+                Some([
+                    rect.min.x / size.x,
+                    rect.min.y / size.y,
+                    rect.max.x / size.x,
+                    rect.max.y / size.y,
+                ])
+            })
+            .unwrap_or([0.0, 0.0, 1.0, 1.0]);
+
+        Self {
+            position: instance.position.to_array(),
+            size: instance.size.to_array(),
+            color: instance.color.to_linear().to_f32_array(),
+            uv_rect,
+        }
+    }
+}
+
+/// Marks the render entity of [`BillboardInstances`], whose instance buffer is in
+/// [`BillboardInstanceBuffers`].
+#[derive(Clone, Copy, Component, Debug)]
+pub struct RenderBillboardInstances;
+
+/// Instances of the billboards that changed this frame, and every billboard with instances, to
+/// keep the buffers of hidden billboards around.
+#[derive(Resource, Default)]
+pub struct ExtractedBillboardInstances {
+    changed: EntityHashMap<Vec<BillboardInstanceVertex>>,
+    live: EntityHashSet,
+}
+
+impl ExtractedBillboardInstances {
+    /// Instances of `entity` extracted this frame, to be uploaded. `None` if they didn't change.
+    pub fn get(&self, entity: Entity) -> Option<&[BillboardInstanceVertex]> {
+        self.changed.get(&entity).map(Vec::as_slice)
+    }
+}
+
+type BillboardInstancesQuery = (
+    Entity,
+    &'static ViewVisibility,
+    Ref<'static, GlobalTransform>,
+    Ref<'static, Transform>,
+    Ref<'static, BillboardInstances>,
+    &'static BillboardTextureHandle,
+    &'static BillboardDepth,
+    Option<&'static BillboardLockAxis>,
+    Option<&'static BillboardSoftEdge>,
+    Option<&'static BillboardSortBias>,
+);
+
+pub fn extract_billboard_instances(
+    mut commands: Commands,
+    mut previous_len: Local<usize>,
+    mut extracted: Local<ExtractedBillboards<(BillboardUniform, RenderBillboard)>>,
+    mut extracted_instances: ResMut<ExtractedBillboardInstances>,
+    atlas_layouts: Extract<Res<Assets<TextureAtlasLayout>>>,
+    mut atlas_layout_events: Extract<EventReader<AssetEvent<TextureAtlasLayout>>>,
+    billboard_query: Extract<Query<BillboardInstancesQuery>>,
+) {
+    let mut batch = Vec::with_capacity(*previous_len);
+    let extracted_instances = extracted_instances.as_mut();
+    let previous_live = std::mem::take(&mut extracted_instances.live);
+
+    // Layouts can load after the instances, or be edited
+    let changed_layouts: HashSet<_> = atlas_layout_events
+        .read()
+        .filter_map(|event| match event {
+            AssetEvent::Added { id } | AssetEvent::Modified { id } => Some(*id),
+            _ => None,
+        })
+        .collect();
+
+    for (
+        entity,
+        visibility,
+        global_transform,
+        transform,
+        instances,
+        billboard_texture,
+        &depth,
+        lock_axis,
+        soft_edge,
+        sort_bias,
+    ) in &billboard_query
+    {
+        // Instances are uploaded once, and again only when they or their layout change
+        let layout_changed = instances
+            .atlas
+            .as_ref()
+            .is_some_and(|atlas| changed_layouts.contains(&atlas.id()));
+        if instances.is_changed() || layout_changed || !previous_live.contains(&entity) {
+            let atlas = instances
+                .atlas
+                .as_ref()
+                .and_then(|atlas| atlas_layouts.get(atlas));
+            let vertices = instances
+                .instances
+                .iter()
+                .map(|instance| BillboardInstanceVertex::new(instance, atlas))
+                .collect();
+            extracted_instances.changed.insert(entity, vertices);
+        }
+        extracted_instances.live.insert(entity);
+
+        let billboard = RenderBillboard {
+            depth,
+            lock_axis: lock_axis.copied(),
+            soft_edge: soft_edge.copied(),
+            sort_bias: sort_bias.map_or(0.0, |bias| bias.0),
+        };
+        let &(uniform, billboard) = extracted.get_or_extract(
+            entity,
+            |(_, extracted)| {
+                global_transform.is_changed() || transform.is_changed() || *extracted != billboard
+            },
+            || {
+                let uniform = calculate_billboard_uniform(
+                    &global_transform,
+                    &transform,
+                    lock_axis,
+                    soft_edge,
+                    None,
+                );
+                (uniform, billboard)
+            },
+        );
+
+        if !visibility.get() {
+            continue;
+        }
+
+        batch.push((
+            entity,
+            (
+                uniform,
+                RenderBillboardImage {
+                    id: billboard_texture.0.id(),
+                },
+                billboard,
+                RenderBillboardInstances,
+            ),
+        ));
+    }
+
+    extracted.finish_frame();
+    *previous_len = batch.len();
+    commands.insert_or_spawn_batch(batch);
+}
+
+/// Instance buffers of [`BillboardInstances`], kept across frames.
+#[derive(Resource, Default)]
+pub struct BillboardInstanceBuffers {
+    buffers: EntityHashMap<(Buffer, u32)>,
+}
+
+pub fn prepare_billboard_instance_buffers(
+    mut extracted_instances: ResMut<ExtractedBillboardInstances>,
+    mut instance_buffers: ResMut<BillboardInstanceBuffers>,
+    render_device: Res<RenderDevice>,
+) {
+    let extracted_instances = extracted_instances.as_mut();
+
+    instance_buffers
+        .buffers
+        .retain(|entity, _| extracted_instances.live.contains(entity));
+
+    for (entity, vertices) in extracted_instances.changed.drain() {
+        if vertices.is_empty() {
+            instance_buffers.buffers.remove(&entity);
+            continue;
+        }
+
+        let buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
+            label: Some("billboard_instance_buffer"),
+            contents: bytemuck::cast_slice(&vertices),
+            usage: BufferUsages::VERTEX,
+        });
+        instance_buffers
+            .buffers
+            .insert(entity, (buffer, vertices.len() as u32));
+    }
+}
+
+type RenderBillboardInstancesQuery = (
+    &'static BillboardUniform,
+    &'static RenderBillboardImage,
+    &'static RenderBillboard,
+);
+
+pub fn queue_billboard_instances(
+    views: Query<BillboardViews>,
+    phases: BillboardPhases,
+    (pipeline_cache, mut billboard_pipelines, billboard_pipeline): (
+        Res<PipelineCache>,
+        ResMut<SpecializedRenderPipelines<BillboardPipeline>>,
+        Res<BillboardPipeline>,
+    ),
+    (mut image_bind_groups, gpu_images, render_device, msaa): (
+        ResMut<BillboardImageBindGroups>,
+        Res<RenderAssets<GpuImage>>,
+        Res<RenderDevice>,
+        Res<Msaa>,
+    ),
+    billboards: Query<RenderBillboardInstancesQuery, With<RenderBillboardInstances>>,
+) {
+    let BillboardPhases {
+        transparent: mut transparent_render_phases,
+        oit: mut oit_render_phases,
+        transparent_draw_functions,
+        oit_draw_functions,
+    } = phases;
+
+    if billboards.is_empty() {
+        return;
+    }
+
+    for (view_entity, view, visible_entities, depth_prepass, oit) in &views {
+        let Some(mut phase) = BillboardViewPhase::get::<DrawBillboardInstanced>(
+            view_entity,
+            oit,
+            &mut transparent_render_phases,
+            &mut oit_render_phases,
+            &transparent_draw_functions,
+            &oit_draw_functions,
+        ) else {
+            continue;
+        };
+
+        let rangefinder = view.rangefinder3d();
+
+        for &entity in visible_entities.iter::<With<Billboard>>() {
+            let Ok((uniform, image, billboard)) = billboards.get(entity) else {
+                continue;
+            };
+            let Some(gpu_image) = gpu_images.get(image.id) else {
+                continue;
+            };
+
+            let key = BillboardPipelineKey::from_billboard(
+                billboard,
+                view,
+                msaa.samples(),
+                phase.is_oit(),
+                depth_prepass,
+            ) | BillboardPipelineKey::INSTANCES;
+            let pipeline =
+                billboard_pipelines.specialize(&pipeline_cache, &billboard_pipeline, key);
+
+            image_bind_groups.prepare(
+                image.id,
+                gpu_image,
+                false,
+                &billboard_pipeline,
+                &render_device,
+            );
+
+            let distance = rangefinder.distance(&uniform.transform()) + billboard.sort_bias;
+
+            phase.add(pipeline, entity, distance);
+        }
+    }
+}
+
+pub struct DrawBillboardInstances;
+impl<P: PhaseItem> RenderCommand<P> for DrawBillboardInstances {
+    type Param = SRes<BillboardInstanceBuffers>;
+    type ViewQuery = ();
+    type ItemQuery = ();
+
+    fn render<'w>(
+        item: &P,
+        _view: ROQueryItem<'w, Self::ViewQuery>,
+        _item_query: Option<ROQueryItem<'w, Self::ItemQuery>>,
+        instance_buffers: SystemParamItem<'w, '_, Self::Param>,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        // No instances, nothing to draw
+        let Some((buffer, count)) = instance_buffers.into_inner().buffers.get(&item.entity())
+        else {
+            return RenderCommandResult::Success;
+        };
+
+        // Every instance is a quad of two triangles
+        pass.set_vertex_buffer(0, buffer.slice(..));
+        pass.draw(0..6, 0..*count);
+
+        RenderCommandResult::Success
+    }
+}
+
+pub type DrawBillboardInstanced = (
+    SetItemPipeline,
+    SetBillboardViewBindGroup<0>,
+    SetBillboardBindGroup<1>,
+    SetBillboardTextureBindGroup<2>,
+    DrawBillboardInstances,
+);
@@ -1,15 +1,28 @@
+pub mod batch;
+pub mod declutter;
+pub mod gpu_culling;
+pub mod instances;
+pub mod math;
+pub mod oit;
+pub mod picking;
 pub mod pipeline;
 pub mod plugin;
+pub mod screen;
 pub mod text;
 pub mod texture;
 mod utils;
+pub mod visibility;
 
+use crate::instances::BillboardInstances;
 use crate::text::{BillboardTextBounds, BillboardTextHandles};
 use bevy::prelude::*;
 use bevy::sprite::Anchor;
 
-pub(self) const BILLBOARD_SHADER_HANDLE: Handle<Shader> =
-    Handle::weak_from_u128(12823766040132746076);
+const BILLBOARD_SHADER_HANDLE: Handle<Shader> = Handle::weak_from_u128(12823766040132746076);
+const BILLBOARD_OIT_RESOLVE_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(90182736459102837465);
+const BILLBOARD_GPU_CULLING_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(48201937465018273645);
 
 #[derive(Clone, Component, Reflect, Default)]
 #[reflect(Component)]
@@ -19,7 +32,7 @@ pub struct BillboardMeshHandle(pub Handle<Mesh>);
 #[reflect(Component)]
 pub struct BillboardTextureHandle(pub Handle<Image>);
 
-#[derive(Clone, Copy, Component, Debug, Reflect)]
+#[derive(Clone, Copy, Component, Debug, PartialEq, Reflect)]
 pub struct BillboardDepth(pub bool);
 
 impl Default for BillboardDepth {
@@ -31,7 +44,26 @@ impl Default for BillboardDepth {
 #[derive(Default, Clone, Copy, Component, Debug, Reflect)]
 pub struct Billboard;
 
+/// Fades the billboard out as it gets closer than the given distance (in world units) to the
+/// geometry behind it. Requires the camera to have a `DepthPrepass`, has no effect otherwise or
+/// with a distance of 0.
+#[derive(Clone, Copy, Component, Debug, PartialEq, Reflect)]
+#[reflect(Component)]
+pub struct BillboardSoftEdge(pub f32);
+
+impl Default for BillboardSoftEdge {
+    fn default() -> Self {
+        Self(1.0)
+    }
+}
+
+/// Offsets the distance a billboard is sorted by when drawing. Billboards with a higher bias are
+/// drawn on top of the ones sharing the same position, e.g. a name above its health bar.
 #[derive(Default, Clone, Copy, Component, Debug, Reflect)]
+#[reflect(Component)]
+pub struct BillboardSortBias(pub f32);
+
+#[derive(Default, Clone, Copy, Component, Debug, PartialEq, Reflect)]
 pub struct BillboardLockAxis {
     pub y_axis: bool,
     pub rotation: bool,
@@ -56,6 +88,19 @@ pub struct BillboardTextureBundle {
     pub billboard_depth: BillboardDepth,
 }
 
+#[derive(Bundle, Default)]
+pub struct BillboardInstancesBundle {
+    pub billboard: Billboard,
+    pub instances: BillboardInstances,
+    pub texture: BillboardTextureHandle,
+    pub transform: Transform,
+    pub global_transform: GlobalTransform,
+    pub visibility: Visibility,
+    pub inherited_visibility: InheritedVisibility,
+    pub view_visibility: ViewVisibility,
+    pub billboard_depth: BillboardDepth,
+}
+
 #[derive(Bundle, Default)]
 pub struct BillboardTextBundle {
     pub billboard: Billboard,
@@ -73,7 +118,22 @@ pub struct BillboardTextBundle {
 
 pub mod prelude {
     pub use crate::{
-        plugin::BillboardPlugin, text::BillboardTextBounds, BillboardMeshHandle,
+        declutter::{BillboardDeclutter, BillboardDeclutterMode, BillboardDeclutterPriority},
+        gpu_culling::BillboardGpuCulling,
+        instances::{BillboardInstance, BillboardInstances},
+        oit::BillboardOit,
+        picking::{
+            BillboardClick, BillboardPickingPlugin, BillboardPickingSettings, BillboardPointerHits,
+            BillboardPointerOut, BillboardPointerOver, BillboardRaycast, BillboardRaycastSettings,
+        },
+        plugin::BillboardPlugin,
+        screen::BillboardScreenRects,
+        text::{
+            BillboardTextBounds, BillboardTextBudget, BillboardTextError, BillboardTextRetry,
+            BillboardTextSettings,
+        },
+        visibility::BillboardCameras,
+        BillboardInstancesBundle, BillboardMeshHandle, BillboardSoftEdge, BillboardSortBias,
         BillboardTextBundle, BillboardTextureBundle, BillboardTextureHandle,
     };
 }
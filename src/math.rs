@@ -0,0 +1,59 @@
+use crate::BillboardLockAxis;
+use bevy::math::{Mat4, Vec2, Vec3, Vec4};
+use bevy::transform::components::GlobalTransform;
+
+/// Directions the x and y axes of a billboard mesh are mapped to before the billboard's model
+/// matrix is applied, computed the same way as in the billboard vertex shader.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BillboardBasis {
+    pub right: Vec3,
+    pub up: Vec3,
+}
+
+impl BillboardBasis {
+    pub fn new(camera_transform: &GlobalTransform, lock_axis: Option<&BillboardLockAxis>) -> Self {
+        // Rotation locked billboards keep their own orientation, which is part of the model
+        if lock_axis.is_some_and(|lock| lock.rotation) {
+            return Self {
+                right: Vec3::NEG_X,
+                up: Vec3::Y,
+            };
+        }
+
+        let world_from_view = camera_transform.compute_matrix();
+
+        let right = world_from_view.x_axis.truncate().normalize();
+        let up = if lock_axis.is_some_and(|lock| lock.y_axis) {
+            Vec3::Y
+        } else {
+            world_from_view.y_axis.truncate().normalize()
+        };
+
+        Self { right, up }
+    }
+
+    /// Position of a mesh vertex in the billboard's model space.
+    pub fn local_position(&self, vertex: Vec2) -> Vec3 {
+        self.right * vertex.x + self.up * vertex.y
+    }
+}
+
+/// Distance in front of the view of a point at `depth` in the depth buffer, computed the same way
+/// as in the billboard fragment shader.
+pub fn view_distance(view_from_clip: &Mat4, depth: f32) -> f32 {
+    let view_position = *view_from_clip * Vec4::new(0.0, 0.0, depth, 1.0);
+    -view_position.z / view_position.w
+}
+
+/// Opacity a [`BillboardSoftEdge`](crate::BillboardSoftEdge) of `soft_edge` leaves a billboard
+/// fragment at `depth` with, in front of the scene at `scene_depth`, computed the same way as in
+/// the billboard fragment shader.
+pub fn soft_edge_fade(view_from_clip: &Mat4, scene_depth: f32, depth: f32, soft_edge: f32) -> f32 {
+    // Billboards without a soft edge aren't drawn with one
+    if soft_edge <= 0.0 {
+        return 1.0;
+    }
+
+    let gap = view_distance(view_from_clip, scene_depth) - view_distance(view_from_clip, depth);
+    (gap / soft_edge).clamp(0.0, 1.0)
+}
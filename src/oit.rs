@@ -0,0 +1,504 @@
+use crate::pipeline::BillboardPipelineKey;
+use crate::BILLBOARD_OIT_RESOLVE_SHADER_HANDLE;
+use bevy::core_pipeline::core_3d::Transparent3d;
+use bevy::core_pipeline::fullscreen_vertex_shader::fullscreen_shader_vertex_state;
+use bevy::ecs::entity::EntityHashSet;
+use bevy::ecs::query::QueryItem;
+use bevy::ecs::system::SystemState;
+use bevy::math::FloatOrd;
+use bevy::prelude::*;
+use bevy::render::camera::ExtractedCamera;
+use bevy::render::extract_component::ExtractComponent;
+use bevy::render::render_graph::{NodeRunError, RenderGraphContext, RenderLabel, ViewNode};
+use bevy::render::render_phase::{
+    CachedRenderPipelinePhaseItem, DrawFunctionId, DrawFunctions, PhaseItem, PhaseItemExtraIndex,
+    SortedPhaseItem, SortedRenderPhase, ViewSortedRenderPhases,
+};
+use bevy::render::render_resource::{
+    BindGroup, BindGroupEntry, BindGroupLayout, BindGroupLayoutEntry, BindingResource, BindingType,
+    BlendComponent, BlendFactor, BlendOperation, BlendState, CachedRenderPipelineId,
+    ColorTargetState, ColorWrites, Extent3d, FragmentState, LoadOp, MultisampleState, Operations,
+    PipelineCache, PrimitiveState, RenderPassColorAttachment, RenderPassDescriptor,
+    RenderPipelineDescriptor, ShaderStages, SpecializedRenderPipeline, SpecializedRenderPipelines,
+    StoreOp, TextureDescriptor, TextureDimension, TextureFormat, TextureSampleType, TextureUsages,
+    TextureView, TextureViewDimension,
+};
+use bevy::render::renderer::{RenderContext, RenderDevice};
+use bevy::render::texture::{BevyDefault, CachedTexture, TextureCache};
+use bevy::render::view::{ViewDepthTexture, ViewTarget};
+use bevy::render::Extract;
+use std::ops::Range;
+
+// Reference:
+// https://jcgt.org/published/0002/02/09/
+
+pub(crate) const ACCUM_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+pub(crate) const REVEALAGE_FORMAT: TextureFormat = TextureFormat::R16Float;
+
+/// Renders the billboards seen by this camera with weighted blended order-independent
+/// transparency, instead of sorting them into `Transparent3d` by their origin.
+#[derive(Clone, Copy, Component, Debug, Default, Reflect, ExtractComponent)]
+#[reflect(Component)]
+pub struct BillboardOit;
+
+pub struct BillboardOit3d {
+    pub pipeline: CachedRenderPipelineId,
+    pub entity: Entity,
+    pub draw_function: DrawFunctionId,
+    pub batch_range: Range<u32>,
+    pub extra_index: PhaseItemExtraIndex,
+    pub distance: f32,
+}
+
+impl PhaseItem for BillboardOit3d {
+    #[inline]
+    fn entity(&self) -> Entity {
+        self.entity
+    }
+
+    #[inline]
+    fn draw_function(&self) -> DrawFunctionId {
+        self.draw_function
+    }
+
+    #[inline]
+    fn batch_range(&self) -> &Range<u32> {
+        &self.batch_range
+    }
+
+    #[inline]
+    fn batch_range_mut(&mut self) -> &mut Range<u32> {
+        &mut self.batch_range
+    }
+
+    #[inline]
+    fn extra_index(&self) -> PhaseItemExtraIndex {
+        self.extra_index
+    }
+
+    #[inline]
+    fn batch_range_and_extra_index_mut(&mut self) -> (&mut Range<u32>, &mut PhaseItemExtraIndex) {
+        (&mut self.batch_range, &mut self.extra_index)
+    }
+}
+
+impl SortedPhaseItem for BillboardOit3d {
+    type SortKey = FloatOrd;
+
+    #[inline]
+    fn sort_key(&self) -> Self::SortKey {
+        FloatOrd(self.distance)
+    }
+
+    // NOTE: Blending is order independent, so the items are left in queue order.
+    #[inline]
+    fn sort(_items: &mut [Self]) {}
+}
+
+impl CachedRenderPipelinePhaseItem for BillboardOit3d {
+    #[inline]
+    fn cached_pipeline(&self) -> CachedRenderPipelineId {
+        self.pipeline
+    }
+}
+
+pub fn extract_billboard_oit_phases(
+    mut oit_phases: ResMut<ViewSortedRenderPhases<BillboardOit3d>>,
+    cameras: Extract<Query<(Entity, &Camera), With<BillboardOit>>>,
+    mut live_entities: Local<EntityHashSet>,
+) {
+    live_entities.clear();
+
+    for (entity, camera) in &cameras {
+        if !camera.is_active {
+            continue;
+        }
+
+        oit_phases.insert_or_clear(entity);
+        live_entities.insert(entity);
+    }
+
+    oit_phases.retain(|entity, _| live_entities.contains(entity));
+}
+
+/// Render phase the billboards of a view are queued in, with the draw function they are drawn
+/// with in it.
+pub(crate) enum BillboardViewPhase<'a> {
+    Transparent(&'a mut SortedRenderPhase<Transparent3d>, DrawFunctionId),
+    Oit(&'a mut SortedRenderPhase<BillboardOit3d>, DrawFunctionId),
+}
+
+impl<'a> BillboardViewPhase<'a> {
+    /// Phase of a view for billboards drawn with `D`, `None` if the view has no transparent
+    /// phase.
+    ///
+    /// Views with [`BillboardOit`] fall back to the transparent phase when their camera isn't
+    /// rendering (yet), as their OIT phase is only extracted for active cameras.
+    pub(crate) fn get<D: 'static>(
+        view_entity: Entity,
+        oit: bool,
+        transparent_phases: &'a mut ViewSortedRenderPhases<Transparent3d>,
+        oit_phases: &'a mut ViewSortedRenderPhases<BillboardOit3d>,
+        transparent_draw_functions: &DrawFunctions<Transparent3d>,
+        oit_draw_functions: &DrawFunctions<BillboardOit3d>,
+    ) -> Option<Self> {
+        let transparent_phase = transparent_phases.get_mut(&view_entity)?;
+
+        let oit_phase = if oit {
+            oit_phases.get_mut(&view_entity)
+        } else {
+            None
+        };
+
+        Some(match oit_phase {
+            Some(oit_phase) => Self::Oit(oit_phase, oit_draw_functions.read().id::<D>()),
+            None => Self::Transparent(
+                transparent_phase,
+                transparent_draw_functions.read().id::<D>(),
+            ),
+        })
+    }
+
+    /// Whether billboards are drawn with OIT pipelines, see [`BillboardPipelineKey::OIT`].
+    pub(crate) fn is_oit(&self) -> bool {
+        matches!(self, Self::Oit(..))
+    }
+
+    /// Queues a single draw of `entity`, sorted by `distance` in the transparent phase.
+    pub(crate) fn add(&mut self, pipeline: CachedRenderPipelineId, entity: Entity, distance: f32) {
+        match self {
+            Self::Transparent(phase, draw_function) => phase.add(Transparent3d {
+                pipeline,
+                entity,
+                draw_function: *draw_function,
+                batch_range: 0..1,
+                extra_index: PhaseItemExtraIndex::NONE,
+                distance,
+            }),
+            Self::Oit(phase, draw_function) => phase.add(BillboardOit3d {
+                pipeline,
+                entity,
+                draw_function: *draw_function,
+                batch_range: 0..1,
+                extra_index: PhaseItemExtraIndex::NONE,
+                distance,
+            }),
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct BillboardOitTextures {
+    accum: CachedTexture,
+    revealage: CachedTexture,
+    // Single sampled targets the MSAA textures are resolved to, read by the resolve pass
+    resolve: Option<(CachedTexture, CachedTexture)>,
+}
+
+impl BillboardOitTextures {
+    fn color_attachments(&self) -> [Option<RenderPassColorAttachment<'_>>; 2] {
+        let (accum_resolve, revealage_resolve) = match &self.resolve {
+            Some((accum, revealage)) => {
+                (Some(&*accum.default_view), Some(&*revealage.default_view))
+            }
+            None => (None, None),
+        };
+
+        [
+            Some(RenderPassColorAttachment {
+                view: &self.accum.default_view,
+                resolve_target: accum_resolve,
+                ops: Operations {
+                    load: LoadOp::Clear(LinearRgba::NONE.into()),
+                    store: StoreOp::Store,
+                },
+            }),
+            Some(RenderPassColorAttachment {
+                view: &self.revealage.default_view,
+                resolve_target: revealage_resolve,
+                ops: Operations {
+                    load: LoadOp::Clear(LinearRgba::WHITE.into()),
+                    store: StoreOp::Store,
+                },
+            }),
+        ]
+    }
+
+    fn sampled_views(&self) -> (&TextureView, &TextureView) {
+        match &self.resolve {
+            Some((accum, revealage)) => (&accum.default_view, &revealage.default_view),
+            None => (&self.accum.default_view, &self.revealage.default_view),
+        }
+    }
+}
+
+pub fn prepare_billboard_oit_textures(
+    mut commands: Commands,
+    mut texture_cache: ResMut<TextureCache>,
+    render_device: Res<RenderDevice>,
+    msaa: Res<Msaa>,
+    views: Query<(Entity, &ExtractedCamera), With<BillboardOit>>,
+) {
+    for (entity, camera) in &views {
+        let Some(size) = camera.physical_target_size else {
+            continue;
+        };
+
+        let mut texture = |label, format, sample_count| {
+            // Only the single sampled textures are read, the MSAA ones are resolved to them.
+            // Leaving out the binding usage also has the GL backend use renderbuffers for the
+            // MSAA ones, as multisampled textures were neither cleared nor resolved on llvmpipe.
+            let usage = if sample_count > 1 {
+                TextureUsages::RENDER_ATTACHMENT
+            } else {
+                TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING
+            };
+
+            texture_cache.get(
+                &render_device,
+                TextureDescriptor {
+                    label: Some(label),
+                    size: Extent3d {
+                        width: size.x,
+                        height: size.y,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count,
+                    dimension: TextureDimension::D2,
+                    format,
+                    usage,
+                    view_formats: &[],
+                },
+            )
+        };
+
+        let samples = msaa.samples();
+
+        let accum = texture("billboard_oit_accum_texture", ACCUM_FORMAT, samples);
+        let revealage = texture("billboard_oit_revealage_texture", REVEALAGE_FORMAT, samples);
+        let resolve = (samples > 1).then(|| {
+            (
+                texture("billboard_oit_accum_resolve_texture", ACCUM_FORMAT, 1),
+                texture(
+                    "billboard_oit_revealage_resolve_texture",
+                    REVEALAGE_FORMAT,
+                    1,
+                ),
+            )
+        });
+
+        commands.entity(entity).insert(BillboardOitTextures {
+            accum,
+            revealage,
+            resolve,
+        });
+    }
+}
+
+#[derive(Component)]
+pub struct BillboardOitResolve {
+    pipeline: CachedRenderPipelineId,
+    bind_group: BindGroup,
+}
+
+pub fn prepare_billboard_oit_resolve(
+    mut commands: Commands,
+    pipeline_cache: Res<PipelineCache>,
+    mut resolve_pipelines: ResMut<SpecializedRenderPipelines<BillboardOitResolvePipeline>>,
+    resolve_pipeline: Res<BillboardOitResolvePipeline>,
+    render_device: Res<RenderDevice>,
+    msaa: Res<Msaa>,
+    views: Query<(Entity, &ViewTarget, &BillboardOitTextures)>,
+) {
+    for (entity, target, textures) in &views {
+        let mut key = BillboardPipelineKey::from_msaa_samples(msaa.samples());
+
+        if target.is_hdr() {
+            key |= BillboardPipelineKey::HDR;
+        }
+
+        let pipeline = resolve_pipelines.specialize(&pipeline_cache, &resolve_pipeline, key);
+
+        let (accum, revealage) = textures.sampled_views();
+        let bind_group = render_device.create_bind_group(
+            Some("billboard_oit_resolve_bind_group"),
+            &resolve_pipeline.layout,
+            &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(accum),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::TextureView(revealage),
+                },
+            ],
+        );
+
+        commands.entity(entity).insert(BillboardOitResolve {
+            pipeline,
+            bind_group,
+        });
+    }
+}
+
+#[derive(Resource)]
+pub struct BillboardOitResolvePipeline {
+    layout: BindGroupLayout,
+}
+
+impl FromWorld for BillboardOitResolvePipeline {
+    fn from_world(world: &mut World) -> Self {
+        let mut system_state: SystemState<(Res<RenderDevice>,)> = SystemState::new(world);
+
+        let (render_device,) = system_state.get(world);
+
+        let texture_entry = |binding| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Texture {
+                multisampled: false,
+                sample_type: TextureSampleType::Float { filterable: false },
+                view_dimension: TextureViewDimension::D2,
+            },
+            count: None,
+        };
+
+        let layout = render_device.create_bind_group_layout(
+            "billboard_oit_resolve_layout",
+            &[texture_entry(0), texture_entry(1)],
+        );
+
+        Self { layout }
+    }
+}
+
+impl SpecializedRenderPipeline for BillboardOitResolvePipeline {
+    type Key = BillboardPipelineKey;
+
+    fn specialize(&self, key: Self::Key) -> RenderPipelineDescriptor {
+        RenderPipelineDescriptor {
+            label: Some("billboard_oit_resolve_pipeline".into()),
+            layout: vec![self.layout.clone()],
+            vertex: fullscreen_shader_vertex_state(),
+            fragment: Some(FragmentState {
+                shader: BILLBOARD_OIT_RESOLVE_SHADER_HANDLE,
+                entry_point: "fragment".into(),
+                shader_defs: vec![],
+                targets: vec![Some(ColorTargetState {
+                    format: if key.contains(BillboardPipelineKey::HDR) {
+                        ViewTarget::TEXTURE_FORMAT_HDR
+                    } else {
+                        TextureFormat::bevy_default()
+                    },
+                    // The resolve shader outputs the revealage as alpha, which is how much of
+                    // the background stays visible.
+                    blend: Some(BlendState {
+                        color: BlendComponent {
+                            src_factor: BlendFactor::OneMinusSrcAlpha,
+                            dst_factor: BlendFactor::SrcAlpha,
+                            operation: BlendOperation::Add,
+                        },
+                        alpha: BlendComponent {
+                            src_factor: BlendFactor::Zero,
+                            dst_factor: BlendFactor::One,
+                            operation: BlendOperation::Add,
+                        },
+                    }),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState {
+                count: key.msaa_samples(),
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            push_constant_ranges: vec![],
+        }
+    }
+}
+
+#[derive(RenderLabel, Debug, Clone, Hash, PartialEq, Eq)]
+pub struct BillboardOitPass;
+
+#[derive(Default)]
+pub struct BillboardOitNode;
+
+impl ViewNode for BillboardOitNode {
+    type ViewQuery = (
+        &'static ExtractedCamera,
+        &'static ViewTarget,
+        &'static ViewDepthTexture,
+        &'static BillboardOitTextures,
+        &'static BillboardOitResolve,
+    );
+
+    fn run(
+        &self,
+        graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        (camera, target, depth, textures, resolve): QueryItem<Self::ViewQuery>,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let view_entity = graph.view_entity();
+
+        let Some(oit_phases) = world.get_resource::<ViewSortedRenderPhases<BillboardOit3d>>()
+        else {
+            return Ok(());
+        };
+
+        let Some(oit_phase) = oit_phases.get(&view_entity) else {
+            return Ok(());
+        };
+
+        if oit_phase.items.is_empty() {
+            return Ok(());
+        }
+
+        let Some(resolve_pipeline) = world
+            .resource::<PipelineCache>()
+            .get_render_pipeline(resolve.pipeline)
+        else {
+            return Ok(());
+        };
+
+        {
+            let mut render_pass = render_context.begin_tracked_render_pass(RenderPassDescriptor {
+                label: Some("billboard_oit_accumulate_pass"),
+                color_attachments: &textures.color_attachments(),
+                // NOTE: Depth is only tested against, stored for the same reason as the
+                // transparent pass (https://github.com/bevyengine/bevy/issues/3776).
+                depth_stencil_attachment: Some(depth.get_attachment(StoreOp::Store)),
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+
+            if let Some(viewport) = camera.viewport.as_ref() {
+                render_pass.set_camera_viewport(viewport);
+            }
+
+            oit_phase.render(&mut render_pass, world, view_entity);
+        }
+
+        let mut render_pass = render_context.begin_tracked_render_pass(RenderPassDescriptor {
+            label: Some("billboard_oit_resolve_pass"),
+            color_attachments: &[Some(target.get_color_attachment())],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        if let Some(viewport) = camera.viewport.as_ref() {
+            render_pass.set_camera_viewport(viewport);
+        }
+
+        render_pass.set_render_pipeline(resolve_pipeline);
+        render_pass.set_bind_group(0, &resolve.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,484 @@
+use crate::declutter::BillboardDeclutterState;
+use crate::instances::{BillboardInstanceVertex, BillboardInstances};
+use crate::math::BillboardBasis;
+use crate::text::{BillboardTextHandles, ATTRIBUTE_ATLAS_LAYER};
+use crate::utils::calculate_billboard_uniform;
+use crate::{Billboard, BillboardLockAxis, BillboardMeshHandle, BillboardTextureHandle};
+use bevy::ecs::system::SystemParam;
+use bevy::input::InputSystem;
+use bevy::math::FloatOrd;
+use bevy::prelude::*;
+use bevy::render::camera::NormalizedRenderTarget;
+use bevy::render::mesh::VertexAttributeValues;
+use bevy::render::render_resource::TextureFormat;
+use bevy::render::view::VisibleEntities;
+use bevy::window::PrimaryWindow;
+
+/// Closest point of a billboard a ray hits.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BillboardRayHit {
+    pub entity: Entity,
+    /// Distance from the origin of the ray to the hit, in world units.
+    pub distance: f32,
+    pub position: Vec3,
+    /// Texture coordinates of the hit, in the texture (or font atlas) the billboard samples.
+    pub uv: Vec2,
+    /// Index of the [`BillboardInstance`](crate::instances::BillboardInstance) that was hit, for
+    /// billboards with [`BillboardInstances`].
+    pub instance: Option<usize>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BillboardRaycastSettings {
+    /// Ignores the hits where the texture is more transparent than this, so only the visible
+    /// part of a glyph or icon can be hit. Textures whose data isn't kept in the main world, or
+    /// in a format other than 8-bit RGBA or BGRA, are hit everywhere.
+    pub alpha_threshold: Option<f32>,
+}
+
+type RaycastBillboardQuery = (
+    &'static GlobalTransform,
+    &'static Transform,
+    Option<&'static BillboardLockAxis>,
+    Option<&'static BillboardMeshHandle>,
+    Option<&'static BillboardTextureHandle>,
+    Option<&'static BillboardTextHandles>,
+    Option<&'static BillboardInstances>,
+    Option<&'static BillboardDeclutterState>,
+);
+
+/// Casts rays against the billboards a camera sees, oriented towards that camera the same way
+/// they are rendered. Only the billboards in the [`VisibleEntities`] of the camera from the last
+/// update are hit, and scene geometry in front of them isn't taken into account. Texts are hit
+/// on the quads of their glyphs, placed according to their `Anchor`.
+#[derive(SystemParam)]
+pub struct BillboardRaycast<'w, 's> {
+    cameras: Query<'w, 's, (&'static GlobalTransform, &'static VisibleEntities), With<Camera>>,
+    billboards: Query<'w, 's, RaycastBillboardQuery, With<Billboard>>,
+    meshes: Res<'w, Assets<Mesh>>,
+    images: Res<'w, Assets<Image>>,
+    texture_atlases: Res<'w, Assets<TextureAtlasLayout>>,
+}
+
+impl BillboardRaycast<'_, '_> {
+    /// Billboards of `camera` hit by `ray`, nearest first.
+    pub fn cast_ray(
+        &self,
+        camera: Entity,
+        ray: Ray3d,
+        settings: &BillboardRaycastSettings,
+    ) -> Vec<BillboardRayHit> {
+        let Ok((camera_transform, visible_entities)) = self.cameras.get(camera) else {
+            return Vec::new();
+        };
+
+        let mut hits: Vec<_> = visible_entities
+            .get::<With<Billboard>>()
+            .iter()
+            .filter_map(|&entity| self.cast_ray_at(entity, camera_transform, ray, settings))
+            .collect();
+
+        hits.sort_by_key(|hit| FloatOrd(hit.distance));
+        hits
+    }
+
+    fn cast_ray_at(
+        &self,
+        entity: Entity,
+        camera_transform: &GlobalTransform,
+        ray: Ray3d,
+        settings: &BillboardRaycastSettings,
+    ) -> Option<BillboardRayHit> {
+        let (global_transform, transform, lock_axis, mesh, texture, text, instances, declutter) =
+            self.billboards.get(entity).ok()?;
+
+        let model =
+            calculate_billboard_uniform(global_transform, transform, lock_axis, None, declutter)
+                .transform();
+        let basis = BillboardBasis::new(camera_transform, lock_axis);
+        let caster = Caster {
+            ray,
+            settings,
+            images: &self.images,
+        };
+
+        let hit = if let Some(instances) = instances {
+            let atlas = instances
+                .atlas
+                .as_ref()
+                .and_then(|atlas| self.texture_atlases.get(atlas));
+            let image = texture.map(|texture| &texture.0);
+
+            instances
+                .instances
+                .iter()
+                .enumerate()
+                .filter_map(|(index, instance)| {
+                    let vertex = BillboardInstanceVertex::new(instance, atlas);
+                    let hit = caster.cast_quad(&model, &basis, &vertex, image)?;
+                    Some((hit.0, hit.1, hit.2, Some(index)))
+                })
+                .min_by_key(|hit| FloatOrd(hit.0))
+        } else if let Some(text) = text {
+            text.iter()
+                .filter_map(|group| {
+                    let mesh = self.meshes.get(group.mesh())?;
+                    caster.cast_mesh(&model, &basis, mesh, Some(group.image()))
+                })
+                .min_by_key(|hit| FloatOrd(hit.0))
+                .map(|(distance, position, uv)| (distance, position, uv, None))
+        } else {
+            let mesh = self.meshes.get(&mesh?.0)?;
+            caster
+                .cast_mesh(&model, &basis, mesh, texture.map(|texture| &texture.0))
+                .map(|(distance, position, uv)| (distance, position, uv, None))
+        };
+
+        hit.map(|(distance, position, uv, instance)| BillboardRayHit {
+            entity,
+            distance,
+            position,
+            uv,
+            instance,
+        })
+    }
+}
+
+struct Caster<'a> {
+    ray: Ray3d,
+    settings: &'a BillboardRaycastSettings,
+    images: &'a Assets<Image>,
+}
+
+impl Caster<'_> {
+    /// Nearest hit of the mesh as (distance, position, uv).
+    fn cast_mesh(
+        &self,
+        model: &Mat4,
+        basis: &BillboardBasis,
+        mesh: &Mesh,
+        image: Option<&Handle<Image>>,
+    ) -> Option<(f32, Vec3, Vec2)> {
+        let Some(VertexAttributeValues::Float32x3(positions)) =
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+        else {
+            return None;
+        };
+        let uvs = match mesh.attribute(Mesh::ATTRIBUTE_UV_0) {
+            Some(VertexAttributeValues::Float32x2(uvs)) => Some(uvs),
+            _ => None,
+        };
+        let layers = match mesh.attribute(ATTRIBUTE_ATLAS_LAYER) {
+            Some(VertexAttributeValues::Uint32(layers)) => Some(layers),
+            _ => None,
+        };
+
+        // Vertices are turned the same way as in the vertex shader
+        let positions: Vec<_> = positions
+            .iter()
+            .map(|&[x, y, _]| model.transform_point3(basis.local_position(Vec2::new(x, y))))
+            .collect();
+        let uv = |index: usize| uvs.map_or(Vec2::ZERO, |uvs| Vec2::from(uvs[index]));
+
+        let indices: Vec<usize> = match mesh.indices() {
+            Some(indices) => indices.iter().collect(),
+            None => (0..positions.len()).collect(),
+        };
+
+        indices
+            .chunks_exact(3)
+            .filter_map(|triangle| {
+                let [a, b, c] = [triangle[0], triangle[1], triangle[2]];
+                let layer = layers.map_or(0, |layers| layers[a]);
+
+                self.cast_triangle(
+                    [positions[a], positions[b], positions[c]],
+                    [uv(a), uv(b), uv(c)],
+                    image,
+                    layer,
+                )
+            })
+            .min_by_key(|hit| FloatOrd(hit.0))
+    }
+
+    /// Hit of the quad of an instance as (distance, position, uv).
+    fn cast_quad(
+        &self,
+        model: &Mat4,
+        basis: &BillboardBasis,
+        instance: &BillboardInstanceVertex,
+        image: Option<&Handle<Image>>,
+    ) -> Option<(f32, Vec3, Vec2)> {
+        let [min_u, min_v, max_u, max_v] = instance.uv_rect;
+        let (min_uv, max_uv) = (Vec2::new(min_u, min_v), Vec2::new(max_u, max_v));
+        let size = Vec2::from(instance.size);
+        let center = Vec3::from(instance.position);
+
+        // Corners of the quad, as drawn by the vertex shader
+        let corners = [Vec2::ZERO, Vec2::X, Vec2::ONE, Vec2::Y].map(|corner| {
+            let position = center + basis.local_position((corner - 0.5) * size);
+            let uv = min_uv + (max_uv - min_uv) * Vec2::new(corner.x, 1.0 - corner.y);
+            (model.transform_point3(position), uv)
+        });
+
+        [[0, 1, 2], [0, 2, 3]]
+            .into_iter()
+            .filter_map(|[a, b, c]| {
+                self.cast_triangle(
+                    [corners[a].0, corners[b].0, corners[c].0],
+                    [corners[a].1, corners[b].1, corners[c].1],
+                    image,
+                    0,
+                )
+            })
+            .min_by_key(|hit| FloatOrd(hit.0))
+    }
+
+    fn cast_triangle(
+        &self,
+        positions: [Vec3; 3],
+        uvs: [Vec2; 3],
+        image: Option<&Handle<Image>>,
+        layer: u32,
+    ) -> Option<(f32, Vec3, Vec2)> {
+        let (distance, [u, v]) = intersect_triangle(self.ray, positions)?;
+        let uv = uvs[0] * (1.0 - u - v) + uvs[1] * u + uvs[2] * v;
+
+        if let Some(threshold) = self.settings.alpha_threshold {
+            let alpha = image
+                .and_then(|image| self.images.get(image))
+                .and_then(|image| texture_alpha(image, uv, layer));
+
+            if alpha.is_some_and(|alpha| alpha < threshold) {
+                return None;
+            }
+        }
+
+        Some((distance, self.ray.get_point(distance), uv))
+    }
+}
+
+/// Distance along the ray and barycentric coordinates of where it hits the triangle, from either
+/// side.
+fn intersect_triangle(ray: Ray3d, [a, b, c]: [Vec3; 3]) -> Option<(f32, [f32; 2])> {
+    let ab = b - a;
+    let ac = c - a;
+    let p = ray.direction.cross(ac);
+    let determinant = ab.dot(p);
+
+    // The ray is parallel to the triangle
+    if determinant.abs() < f32::EPSILON {
+        return None;
+    }
+
+    let inverse = determinant.recip();
+    let s = ray.origin - a;
+    let u = s.dot(p) * inverse;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+
+    let q = s.cross(ab);
+    let v = ray.direction.dot(q) * inverse;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    let distance = ac.dot(q) * inverse;
+    (distance > 0.0).then_some((distance, [u, v]))
+}
+
+/// Alpha of the texel at `uv` in a layer of an 8-bit RGBA or BGRA texture.
+fn texture_alpha(image: &Image, uv: Vec2, layer: u32) -> Option<f32> {
+    if !matches!(
+        image.texture_descriptor.format,
+        TextureFormat::Rgba8Unorm
+            | TextureFormat::Rgba8UnormSrgb
+            | TextureFormat::Bgra8Unorm
+            | TextureFormat::Bgra8UnormSrgb
+    ) {
+        return None;
+    }
+
+    let size = image.texture_descriptor.size;
+    let texel = (uv.clamp(Vec2::ZERO, Vec2::ONE)
+        * Vec2::new(size.width as f32, size.height as f32))
+    .as_uvec2()
+    .min(UVec2::new(size.width, size.height).saturating_sub(UVec2::ONE));
+
+    let index = ((layer * size.height + texel.y) * size.width + texel.x) as usize;
+    image
+        .data
+        .get(index * 4 + 3)
+        .map(|&alpha| alpha as f32 / 255.0)
+}
+
+/// Ray-casts the billboards under the mouse cursor every update, into [`BillboardPointerHits`],
+/// and sends the [`BillboardPointerOver`], [`BillboardPointerOut`] and [`BillboardClick`] events
+/// of the front one. Only needed for the pointer, [`BillboardRaycast`] works without it.
+pub struct BillboardPickingPlugin;
+
+impl Plugin for BillboardPickingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<BillboardPointerOver>()
+            .add_event::<BillboardPointerOut>()
+            .add_event::<BillboardClick>()
+            .init_resource::<BillboardPickingSettings>()
+            .init_resource::<BillboardPointerHits>()
+            .register_type::<BillboardPickingSettings>()
+            .add_systems(
+                PreUpdate,
+                (update_billboard_pointer_hits, send_billboard_pointer_events)
+                    .chain()
+                    .after(InputSystem),
+            );
+    }
+}
+
+#[derive(Resource, Clone, Debug, Default, Reflect)]
+#[reflect(Resource)]
+pub struct BillboardPickingSettings {
+    /// See [`BillboardRaycastSettings::alpha_threshold`].
+    pub alpha_threshold: Option<f32>,
+}
+
+/// Billboards under the mouse cursor, front to back: the hits of the camera rendered last come
+/// first, then every camera's hits are ordered by distance.
+#[derive(Resource, Clone, Debug, Default)]
+pub struct BillboardPointerHits {
+    pub hits: Vec<BillboardPointerHit>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BillboardPointerHit {
+    pub camera: Entity,
+    pub hit: BillboardRayHit,
+}
+
+pub fn update_billboard_pointer_hits(
+    mut pointer_hits: ResMut<BillboardPointerHits>,
+    settings: Res<BillboardPickingSettings>,
+    primary_window: Query<Entity, With<PrimaryWindow>>,
+    windows: Query<&Window>,
+    cameras: Query<(Entity, &Camera, &GlobalTransform)>,
+    raycast: BillboardRaycast,
+) {
+    pointer_hits.hits.clear();
+
+    let settings = BillboardRaycastSettings {
+        alpha_threshold: settings.alpha_threshold,
+    };
+
+    let mut cameras: Vec<_> = cameras
+        .iter()
+        .filter(|(_, camera, _)| camera.is_active)
+        .collect();
+    cameras.sort_by_key(|(_, camera, _)| std::cmp::Reverse(camera.order));
+
+    for (entity, camera, camera_transform) in cameras {
+        let Some(NormalizedRenderTarget::Window(window)) =
+            camera.target.normalize(primary_window.get_single().ok())
+        else {
+            continue;
+        };
+        let Some(cursor) = windows
+            .get(window.entity())
+            .ok()
+            .and_then(|window| window.cursor_position())
+        else {
+            continue;
+        };
+
+        // Cursor position relative to the viewport of the camera, if it's in it
+        let viewport = camera.logical_viewport_rect().unwrap_or_default();
+        if !viewport.contains(cursor) {
+            continue;
+        }
+        let Some(ray) = camera.viewport_to_world(camera_transform, cursor - viewport.min) else {
+            continue;
+        };
+
+        pointer_hits.hits.extend(
+            raycast
+                .cast_ray(entity, ray, &settings)
+                .into_iter()
+                .map(|hit| BillboardPointerHit {
+                    camera: entity,
+                    hit,
+                }),
+        );
+    }
+}
+
+/// Sent when the pointer starts hovering a billboard, the front one under the cursor.
+#[derive(Event, Clone, Copy, Debug, PartialEq)]
+pub struct BillboardPointerOver {
+    pub camera: Entity,
+    pub hit: BillboardRayHit,
+}
+
+/// Sent when the billboard the pointer hovered isn't the front one under the cursor anymore, or
+/// the cursor left the window. The billboard could have been despawned since.
+#[derive(Event, Clone, Copy, Debug, PartialEq)]
+pub struct BillboardPointerOut {
+    pub entity: Entity,
+}
+
+/// Sent when a mouse button is pressed and released while hovering the same billboard.
+#[derive(Event, Clone, Copy, Debug, PartialEq)]
+pub struct BillboardClick {
+    pub button: MouseButton,
+    pub camera: Entity,
+    pub hit: BillboardRayHit,
+}
+
+pub fn send_billboard_pointer_events(
+    mut hovered: Local<Option<Entity>>,
+    mut pressed: Local<Vec<(MouseButton, Entity)>>,
+    pointer_hits: Res<BillboardPointerHits>,
+    buttons: Option<Res<ButtonInput<MouseButton>>>,
+    mut over_events: EventWriter<BillboardPointerOver>,
+    mut out_events: EventWriter<BillboardPointerOut>,
+    mut click_events: EventWriter<BillboardClick>,
+) {
+    let front = pointer_hits.hits.first();
+    let front_entity = front.map(|front| front.hit.entity);
+
+    if *hovered != front_entity {
+        if let Some(entity) = hovered.take() {
+            out_events.send(BillboardPointerOut { entity });
+        }
+        if let Some(&BillboardPointerHit { camera, hit }) = front {
+            over_events.send(BillboardPointerOver { camera, hit });
+        }
+        *hovered = front_entity;
+    }
+
+    let Some(buttons) = buttons else {
+        return;
+    };
+
+    for &button in buttons.get_just_released() {
+        let Some(index) = pressed.iter().position(|&(pressed, _)| pressed == button) else {
+            continue;
+        };
+        let (_, entity) = pressed.swap_remove(index);
+
+        if let Some(&BillboardPointerHit { camera, hit }) =
+            front.filter(|front| front.hit.entity == entity)
+        {
+            click_events.send(BillboardClick {
+                button,
+                camera,
+                hit,
+            });
+        }
+    }
+
+    if let Some(entity) = front_entity {
+        for &button in buttons.get_just_pressed() {
+            pressed.retain(|&(pressed, _)| pressed != button);
+            pressed.push((button, entity));
+        }
+    }
+}
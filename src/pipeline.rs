@@ -1,10 +1,16 @@
-use crate::text::RenderBillboard;
+use crate::batch::text_batches_supported;
+use crate::gpu_culling::{gpu_culling_supported, BillboardGpuCullUniform, BillboardGpuInstance};
+use crate::oit::{
+    BillboardOit, BillboardOit3d, BillboardViewPhase, ACCUM_FORMAT, REVEALAGE_FORMAT,
+};
+use crate::text::{RenderBillboard, RenderBillboardTextGroups, ATTRIBUTE_ATLAS_LAYER};
 use crate::{Billboard, BILLBOARD_SHADER_HANDLE};
 use bevy::asset::AssetId;
 use bevy::core_pipeline::core_3d::Transparent3d;
-use bevy::ecs::query::ROQueryItem;
+use bevy::core_pipeline::prepass::{DepthPrepass, ViewPrepassTextures};
+use bevy::ecs::query::{Has, ROQueryItem};
 use bevy::ecs::system::lifetimeless::{Read, SRes};
-use bevy::ecs::system::{SystemParamItem, SystemState};
+use bevy::ecs::system::{SystemParam, SystemParamItem, SystemState};
 use bevy::log::error;
 use bevy::math::Mat4;
 use bevy::prelude::{
@@ -15,7 +21,7 @@ use bevy::render::extract_component::{ComponentUniforms, DynamicUniformIndex};
 use bevy::render::mesh::{GpuBufferInfo, GpuMesh, MeshVertexBufferLayoutRef, PrimitiveTopology};
 use bevy::render::render_asset::RenderAssets;
 use bevy::render::render_phase::{
-    DrawFunctions, PhaseItemExtraIndex, RenderCommand, RenderCommandResult, SetItemPipeline,
+    DrawFunctions, PhaseItem, RenderCommand, RenderCommandResult, SetItemPipeline,
     TrackedRenderPass, ViewSortedRenderPhases,
 };
 use bevy::render::render_resource::{
@@ -23,10 +29,11 @@ use bevy::render::render_resource::{
     BlendComponent, BlendFactor, BlendOperation, BlendState, BufferBindingType, ColorTargetState,
     ColorWrites, CompareFunction, DepthStencilState, FragmentState, FrontFace, MultisampleState,
     PipelineCache, PolygonMode, PrimitiveState, RenderPipelineDescriptor, SamplerBindingType,
-    ShaderStages, ShaderType, SpecializedMeshPipeline, SpecializedMeshPipelineError,
-    SpecializedMeshPipelines, TextureFormat, TextureSampleType, TextureViewDimension, VertexState,
+    ShaderDefVal, ShaderStages, ShaderType, SpecializedMeshPipeline, SpecializedMeshPipelineError,
+    SpecializedMeshPipelines, SpecializedRenderPipeline, TextureFormat, TextureSampleType,
+    TextureViewDimension, VertexBufferLayout, VertexFormat, VertexState, VertexStepMode,
 };
-use bevy::render::renderer::RenderDevice;
+use bevy::render::renderer::{RenderAdapter, RenderDevice};
 use bevy::render::texture::{BevyDefault, GpuImage};
 use bevy::render::view::{
     ExtractedView, ViewTarget, ViewUniform, ViewUniformOffset, ViewUniforms, VisibleEntities,
@@ -34,9 +41,30 @@ use bevy::render::view::{
 use bevy::sprite::SpriteAssetEvents;
 use bevy::utils;
 
-#[derive(Clone, Copy, ShaderType, Component)]
-pub struct BillboardUniform {
-    pub(crate) transform: Mat4,
+pub use uniform::BillboardUniform;
+
+// `ShaderType` checks the type of every field with a function nested in an anonymous const next
+// to the struct, which is never called. Attributes on the struct or its fields don't reach it, so
+// the allow is on a module holding nothing but the struct.
+#[allow(dead_code)]
+mod uniform {
+    use bevy::math::Mat4;
+    use bevy::prelude::Component;
+    use bevy::render::render_resource::ShaderType;
+
+    #[derive(Clone, Copy, ShaderType, Component)]
+    pub struct BillboardUniform {
+        pub(crate) transform: Mat4,
+        pub(crate) soft_edge: f32,
+        /// Opacity from decluttering.
+        pub(crate) alpha: f32,
+    }
+}
+
+impl BillboardUniform {
+    pub fn transform(&self) -> Mat4 {
+        self.transform
+    }
 }
 
 #[derive(Clone, Copy, Component, Debug)]
@@ -54,6 +82,41 @@ pub struct BillboardImageBindGroups {
     values: utils::HashMap<AssetId<Image>, BindGroup>,
 }
 
+impl BillboardImageBindGroups {
+    pub(crate) fn prepare(
+        &mut self,
+        id: AssetId<Image>,
+        gpu_image: &GpuImage,
+        texture_array: bool,
+        billboard_pipeline: &BillboardPipeline,
+        render_device: &RenderDevice,
+    ) {
+        self.values.entry(id).or_insert_with(|| {
+            // Images are drawn either as a texture or as a texture array, never both
+            let texture_layout = if texture_array {
+                &billboard_pipeline.texture_array_layout
+            } else {
+                &billboard_pipeline.texture_layout
+            };
+
+            render_device.create_bind_group(
+                Some("billboard_texture_bind_group"),
+                texture_layout,
+                &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::TextureView(&gpu_image.texture_view),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::Sampler(&gpu_image.sampler),
+                    },
+                ],
+            )
+        });
+    }
+}
+
 #[derive(Resource)]
 pub struct BillboardBindGroup {
     value: BindGroup,
@@ -78,6 +141,13 @@ bitflags::bitflags! {
         const LOCK_Y             = (1 << 2);
         const LOCK_ROTATION      = (1 << 3);
         const HDR                = (1 << 4);
+        const DEPTH_PREPASS      = (1 << 5);
+        const SOFT_EDGE          = (1 << 6);
+        const OIT                = (1 << 7);
+        const TEXTURE_ARRAY      = (1 << 8);
+        const TEXT_BATCH         = (1 << 9);
+        const GPU_CULLING        = (1 << 10);
+        const INSTANCES          = (1 << 11);
         const MSAA_RESERVED_BITS = Self::MSAA_MASK_BITS << Self::MSAA_SHIFT_BITS;
     }
 }
@@ -94,6 +164,50 @@ impl BillboardPipelineKey {
     pub fn msaa_samples(&self) -> u32 {
         1 << ((self.bits() >> Self::MSAA_SHIFT_BITS) & Self::MSAA_MASK_BITS)
     }
+
+    /// Key of a billboard drawn to a view with these settings.
+    pub fn from_billboard(
+        billboard: &RenderBillboard,
+        view: &ExtractedView,
+        msaa_samples: u32,
+        oit: bool,
+        depth_prepass: bool,
+    ) -> Self {
+        let mut key = Self::from_msaa_samples(msaa_samples);
+
+        if billboard.depth.0 {
+            key |= BillboardPipelineKey::DEPTH;
+        }
+
+        if billboard.lock_axis.is_some_and(|lock| lock.y_axis) {
+            key |= BillboardPipelineKey::LOCK_Y;
+        }
+        if billboard.lock_axis.is_some_and(|lock| lock.rotation) {
+            key |= BillboardPipelineKey::LOCK_ROTATION;
+        }
+
+        if view.hdr {
+            key |= BillboardPipelineKey::HDR;
+        }
+
+        if oit {
+            key |= BillboardPipelineKey::OIT;
+        }
+
+        if depth_prepass {
+            key |= BillboardPipelineKey::DEPTH_PREPASS;
+
+            // Without a distance to fade over, the edge is as hard as without a soft edge
+            if billboard
+                .soft_edge
+                .is_some_and(|soft_edge| soft_edge.0 > 0.0)
+            {
+                key |= BillboardPipelineKey::SOFT_EDGE;
+            }
+        }
+
+        key
+    }
 }
 
 pub fn prepare_billboard_view_bind_groups(
@@ -101,15 +215,35 @@ pub fn prepare_billboard_view_bind_groups(
     render_device: Res<RenderDevice>,
     billboard_pipeline: Res<BillboardPipeline>,
     view_uniforms: Res<ViewUniforms>,
-    views: Query<Entity, With<ExtractedView>>,
+    msaa: Res<Msaa>,
+    views: Query<(Entity, Option<&ViewPrepassTextures>), With<ExtractedView>>,
 ) {
     let Some(binding) = view_uniforms.uniforms.binding() else {
         return;
     };
 
-    for entity in views.iter() {
-        commands.entity(entity).insert(BillboardViewBindGroup {
-            value: render_device.create_bind_group(
+    for (entity, prepass_textures) in views.iter() {
+        let value = match prepass_textures.and_then(|textures| textures.depth_view()) {
+            Some(depth_view) => {
+                let mut key = BillboardPipelineKey::from_msaa_samples(msaa.samples());
+                key |= BillboardPipelineKey::DEPTH_PREPASS;
+
+                render_device.create_bind_group(
+                    Some("billboard_view_bind_group"),
+                    billboard_pipeline.view_layout(key),
+                    &[
+                        BindGroupEntry {
+                            binding: 0,
+                            resource: binding.clone(),
+                        },
+                        BindGroupEntry {
+                            binding: 1,
+                            resource: BindingResource::TextureView(depth_view),
+                        },
+                    ],
+                )
+            }
+            None => render_device.create_bind_group(
                 Some("billboard_view_bind_group"),
                 &billboard_pipeline.view_layout,
                 &[BindGroupEntry {
@@ -117,7 +251,11 @@ pub fn prepare_billboard_view_bind_groups(
                     resource: binding.clone(),
                 }],
             ),
-        });
+        };
+
+        commands
+            .entity(entity)
+            .insert(BillboardViewBindGroup { value });
     }
 }
 
@@ -143,25 +281,66 @@ pub fn prepare_billboard_bind_group(
     });
 }
 
+/// Views billboards are queued for, with the billboards they see.
+pub type BillboardViews = (
+    Entity,
+    &'static ExtractedView,
+    &'static VisibleEntities,
+    Has<DepthPrepass>,
+    Has<BillboardOit>,
+);
+
+/// Render phases billboards are queued in, with their draw functions.
+#[derive(SystemParam)]
+pub struct BillboardPhases<'w> {
+    pub(crate) transparent: ResMut<'w, ViewSortedRenderPhases<Transparent3d>>,
+    pub(crate) oit: ResMut<'w, ViewSortedRenderPhases<BillboardOit3d>>,
+    pub(crate) transparent_draw_functions: Res<'w, DrawFunctions<Transparent3d>>,
+    pub(crate) oit_draw_functions: Res<'w, DrawFunctions<BillboardOit3d>>,
+}
+
+/// Pipelines and bind groups billboards drawn with a mesh are queued with.
+#[derive(SystemParam)]
+pub struct BillboardMeshPipelines<'w> {
+    pub(crate) pipeline_cache: Res<'w, PipelineCache>,
+    pub(crate) pipelines: ResMut<'w, SpecializedMeshPipelines<BillboardPipeline>>,
+    pub(crate) pipeline: Res<'w, BillboardPipeline>,
+    pub(crate) image_bind_groups: ResMut<'w, BillboardImageBindGroups>,
+    pub(crate) render_device: Res<'w, RenderDevice>,
+    pub(crate) msaa: Res<'w, Msaa>,
+}
+
+type RenderBillboardQuery = (
+    &'static BillboardUniform,
+    &'static RenderBillboardMesh,
+    &'static RenderBillboardImage,
+    &'static RenderBillboard,
+);
+
 pub fn queue_billboard_texture(
-    mut views: Query<(Entity, &ExtractedView, &VisibleEntities)>,
-    mut transparent_render_phases: ResMut<ViewSortedRenderPhases<Transparent3d>>,
-    mut pipeline_cache: ResMut<PipelineCache>,
-    mut image_bind_groups: ResMut<BillboardImageBindGroups>,
-    mut billboard_pipelines: ResMut<SpecializedMeshPipelines<BillboardPipeline>>,
-    render_device: Res<RenderDevice>,
-    transparent_draw_functions: Res<DrawFunctions<Transparent3d>>,
-    msaa: Res<Msaa>,
-    billboard_pipeline: Res<BillboardPipeline>,
+    mut views: Query<BillboardViews>,
+    phases: BillboardPhases,
+    mesh_pipelines: BillboardMeshPipelines,
     (gpu_images, gpu_meshes): (Res<RenderAssets<GpuImage>>, Res<RenderAssets<GpuMesh>>),
     events: Res<SpriteAssetEvents>,
-    billboards: Query<(
-        &BillboardUniform,
-        &RenderBillboardMesh,
-        &RenderBillboardImage,
-        &RenderBillboard,
-    )>,
+    billboards: Query<RenderBillboardQuery>,
+    text_groups: Query<&RenderBillboardTextGroups>,
 ) {
+    let BillboardPhases {
+        transparent: mut transparent_render_phases,
+        oit: mut oit_render_phases,
+        transparent_draw_functions,
+        oit_draw_functions,
+    } = phases;
+    let BillboardMeshPipelines {
+        pipeline_cache,
+        pipelines: mut billboard_pipelines,
+        pipeline: billboard_pipeline,
+        mut image_bind_groups,
+        render_device,
+        msaa,
+    } = mesh_pipelines;
+
     // If an image has changed, the GpuImage has (probably) changed
     for event in &events.images {
         match event {
@@ -174,88 +353,71 @@ pub fn queue_billboard_texture(
         };
     }
 
-    for (view_entity, view, visible_entities) in &mut views {
-        let Some(transparent_phase) = transparent_render_phases.get_mut(&view_entity) else {
+    for (view_entity, view, visible_entities, depth_prepass, oit) in &mut views {
+        let Some(mut phase) = BillboardViewPhase::get::<DrawBillboard>(
+            view_entity,
+            oit,
+            &mut transparent_render_phases,
+            &mut oit_render_phases,
+            &transparent_draw_functions,
+            &oit_draw_functions,
+        ) else {
             continue;
         };
 
-        let draw_transparent_billboard = transparent_draw_functions
-            .read()
-            .get_id::<DrawBillboard>()
-            .unwrap();
-
         let rangefinder = view.rangefinder3d();
 
         for visible_entity in visible_entities.iter::<With<Billboard>>() {
-            let Ok((uniform, mesh, image, billboard)) = billboards.get(*visible_entity) else {
-                continue;
-            };
-            let Some(gpu_image) = gpu_images.get(image.id) else {
-                continue;
-            };
-            let Some(gpu_mesh) = gpu_meshes.get(mesh.id) else {
-                continue;
-            };
-
-            let mut key = BillboardPipelineKey::from_msaa_samples(msaa.samples());
+            let text_groups = text_groups
+                .get(*visible_entity)
+                .map_or(&[][..], |groups| &groups.0[..]);
 
-            if billboard.depth.0 {
-                key |= BillboardPipelineKey::DEPTH;
-            }
-
-            if billboard.lock_axis.map_or(false, |lock| lock.y_axis) {
-                key |= BillboardPipelineKey::LOCK_Y;
-            }
-            if billboard.lock_axis.map_or(false, |lock| lock.rotation) {
-                key |= BillboardPipelineKey::LOCK_ROTATION;
-            }
-
-            if view.hdr {
-                key |= BillboardPipelineKey::HDR;
-            }
-
-            let pipeline_id = billboard_pipelines.specialize(
-                &mut pipeline_cache,
-                &billboard_pipeline,
-                key,
-                &gpu_mesh.layout,
-            );
-
-            let pipeline_id = match pipeline_id {
-                Ok(id) => id,
-                Err(err) => {
-                    error!("{err:?}");
+            for entity in std::iter::once(visible_entity).chain(text_groups) {
+                let Ok((uniform, mesh, image, billboard)) = billboards.get(*entity) else {
                     continue;
-                }
-            };
-
-            let distance = rangefinder.distance(&uniform.transform);
-
-            image_bind_groups.values.entry(image.id).or_insert_with(|| {
-                render_device.create_bind_group(
-                    Some("billboard_texture_bind_group"),
-                    &billboard_pipeline.texture_layout,
-                    &[
-                        BindGroupEntry {
-                            binding: 0,
-                            resource: BindingResource::TextureView(&gpu_image.texture_view),
-                        },
-                        BindGroupEntry {
-                            binding: 1,
-                            resource: BindingResource::Sampler(&gpu_image.sampler),
-                        },
-                    ],
-                )
-            });
-
-            transparent_phase.add(Transparent3d {
-                pipeline: pipeline_id,
-                entity: *visible_entity,
-                draw_function: draw_transparent_billboard,
-                batch_range: 0..1,
-                extra_index: PhaseItemExtraIndex::NONE,
-                distance,
-            });
+                };
+                let Some(gpu_image) = gpu_images.get(image.id) else {
+                    continue;
+                };
+                let Some(gpu_mesh) = gpu_meshes.get(mesh.id) else {
+                    continue;
+                };
+
+                let key = BillboardPipelineKey::from_billboard(
+                    billboard,
+                    view,
+                    msaa.samples(),
+                    phase.is_oit(),
+                    depth_prepass,
+                );
+
+                let pipeline_id = billboard_pipelines.specialize(
+                    &pipeline_cache,
+                    &billboard_pipeline,
+                    key,
+                    &gpu_mesh.layout,
+                );
+
+                let pipeline_id = match pipeline_id {
+                    Ok(id) => id,
+                    Err(err) => {
+                        error!("{err:?}");
+                        continue;
+                    }
+                };
+
+                let distance = rangefinder.distance(&uniform.transform) + billboard.sort_bias;
+
+                image_bind_groups.prepare(
+                    image.id,
+                    gpu_image,
+                    gpu_mesh.layout.0.contains(ATTRIBUTE_ATLAS_LAYER),
+                    &billboard_pipeline,
+                    &render_device,
+                );
+
+                phase.add(pipeline_id, *entity, distance);
+            }
         }
     }
 }
@@ -263,35 +425,77 @@ pub fn queue_billboard_texture(
 #[derive(Resource, Clone)]
 pub struct BillboardPipeline {
     view_layout: BindGroupLayout,
+    view_layout_depth_prepass: BindGroupLayout,
+    view_layout_depth_prepass_multisampled: BindGroupLayout,
     billboard_layout: BindGroupLayout,
     texture_layout: BindGroupLayout,
+    texture_array_layout: BindGroupLayout,
+    /// Storage buffer of the billboards in text batches, if the device supports them.
+    pub(crate) text_batch_layout: Option<BindGroupLayout>,
+    /// Instances culled on the GPU and the ones that survived, if the device supports it.
+    pub(crate) gpu_culling_layout: Option<BindGroupLayout>,
+}
+
+impl BillboardPipeline {
+    fn view_layout(&self, key: BillboardPipelineKey) -> &BindGroupLayout {
+        if !key.contains(BillboardPipelineKey::DEPTH_PREPASS) {
+            &self.view_layout
+        } else if key.msaa_samples() > 1 {
+            &self.view_layout_depth_prepass_multisampled
+        } else {
+            &self.view_layout_depth_prepass
+        }
+    }
 }
 
 impl FromWorld for BillboardPipeline {
     fn from_world(world: &mut World) -> Self {
-        let mut system_state: SystemState<(Res<RenderDevice>,)> = SystemState::new(world);
+        let mut system_state: SystemState<(Res<RenderDevice>, Res<RenderAdapter>)> =
+            SystemState::new(world);
+
+        let (render_device, render_adapter) = system_state.get(world);
+
+        let view_entry = BindGroupLayoutEntry {
+            binding: 0,
+            visibility: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Uniform,
+                has_dynamic_offset: true,
+                min_binding_size: Some(ViewUniform::min_size()),
+            },
+            count: None,
+        };
 
-        let (render_device,) = system_state.get(world);
+        let depth_prepass_entry = |multisampled| BindGroupLayoutEntry {
+            binding: 1,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Texture {
+                multisampled,
+                // Depth textures can't be loaded from on the GL backend, see billboard.wgsl
+                sample_type: TextureSampleType::Float { filterable: false },
+                view_dimension: TextureViewDimension::D2,
+            },
+            count: None,
+        };
 
-        let view_layout = render_device.create_bind_group_layout(
-            "billboard_view_layout",
-            &[BindGroupLayoutEntry {
-                binding: 0,
-                visibility: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
-                ty: BindingType::Buffer {
-                    ty: BufferBindingType::Uniform,
-                    has_dynamic_offset: true,
-                    min_binding_size: Some(ViewUniform::min_size()),
-                },
-                count: None,
-            }],
+        let view_layout =
+            render_device.create_bind_group_layout("billboard_view_layout", &[view_entry]);
+
+        let view_layout_depth_prepass = render_device.create_bind_group_layout(
+            "billboard_view_layout_depth_prepass",
+            &[view_entry, depth_prepass_entry(false)],
+        );
+
+        let view_layout_depth_prepass_multisampled = render_device.create_bind_group_layout(
+            "billboard_view_layout_depth_prepass_multisampled",
+            &[view_entry, depth_prepass_entry(true)],
         );
 
         let billboard_layout = render_device.create_bind_group_layout(
             "billboard_layout",
             &[BindGroupLayoutEntry {
                 binding: 0,
-                visibility: ShaderStages::VERTEX,
+                visibility: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
                 ty: BindingType::Buffer {
                     ty: BufferBindingType::Uniform,
                     has_dynamic_offset: true,
@@ -301,16 +505,70 @@ impl FromWorld for BillboardPipeline {
             }],
         );
 
-        let texture_layout = render_device.create_bind_group_layout(
-            "billboard_texture_layout",
-            &[
+        let text_batch_layout = text_batches_supported(&render_device).then(|| {
+            render_device.create_bind_group_layout(
+                "billboard_text_batch_layout",
+                &[BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: Some(<Vec<BillboardUniform>>::min_size()),
+                    },
+                    count: None,
+                }],
+            )
+        });
+
+        let gpu_culling_layout =
+            gpu_culling_supported(&render_device, &render_adapter).then(|| {
+                render_device.create_bind_group_layout(
+                    "billboard_gpu_culling_draw_layout",
+                    &[
+                        BindGroupLayoutEntry {
+                            binding: 0,
+                            visibility: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
+                            ty: BindingType::Buffer {
+                                ty: BufferBindingType::Storage { read_only: true },
+                                has_dynamic_offset: false,
+                                min_binding_size: Some(<Vec<BillboardGpuInstance>>::min_size()),
+                            },
+                            count: None,
+                        },
+                        BindGroupLayoutEntry {
+                            binding: 1,
+                            visibility: ShaderStages::VERTEX,
+                            ty: BindingType::Buffer {
+                                ty: BufferBindingType::Storage { read_only: true },
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                        BindGroupLayoutEntry {
+                            binding: 2,
+                            visibility: ShaderStages::VERTEX,
+                            ty: BindingType::Buffer {
+                                ty: BufferBindingType::Uniform,
+                                has_dynamic_offset: true,
+                                min_binding_size: Some(BillboardGpuCullUniform::min_size()),
+                            },
+                            count: None,
+                        },
+                    ],
+                )
+            });
+
+        let texture_layout_entries = |view_dimension| {
+            [
                 BindGroupLayoutEntry {
                     binding: 0,
                     visibility: ShaderStages::FRAGMENT,
                     ty: BindingType::Texture {
                         multisampled: false,
                         sample_type: TextureSampleType::Float { filterable: true },
-                        view_dimension: TextureViewDimension::D2,
+                        view_dimension,
                     },
                     count: None,
                 },
@@ -320,13 +578,28 @@ impl FromWorld for BillboardPipeline {
                     ty: BindingType::Sampler(SamplerBindingType::Filtering),
                     count: None,
                 },
-            ],
+            ]
+        };
+
+        let texture_layout = render_device.create_bind_group_layout(
+            "billboard_texture_layout",
+            &texture_layout_entries(TextureViewDimension::D2),
+        );
+
+        let texture_array_layout = render_device.create_bind_group_layout(
+            "billboard_texture_array_layout",
+            &texture_layout_entries(TextureViewDimension::D2Array),
         );
 
         Self {
             view_layout,
+            view_layout_depth_prepass,
+            view_layout_depth_prepass_multisampled,
             billboard_layout,
             texture_layout,
+            texture_array_layout,
+            text_batch_layout,
+            gpu_culling_layout,
         }
     }
 }
@@ -340,10 +613,9 @@ impl SpecializedMeshPipeline for BillboardPipeline {
         layout: &MeshVertexBufferLayoutRef,
     ) -> Result<RenderPipelineDescriptor, SpecializedMeshPipelineError> {
         const DEF_VERTEX_COLOR: &str = "VERTEX_COLOR";
-        const DEF_LOCK_Y: &str = "LOCK_Y";
-        const DEF_LOCK_ROTATION: &str = "LOCK_ROTATION";
 
-        let mut shader_defs = Vec::with_capacity(4);
+        let mut key = key;
+        let mut shader_defs = Vec::with_capacity(8);
         let mut attributes = Vec::with_capacity(4);
 
         attributes.push(Mesh::ATTRIBUTE_POSITION.at_shader_location(0));
@@ -356,8 +628,96 @@ impl SpecializedMeshPipeline for BillboardPipeline {
             attributes.push(Mesh::ATTRIBUTE_COLOR.at_shader_location(2));
         }
 
+        if layout.contains(ATTRIBUTE_ATLAS_LAYER) {
+            key |= BillboardPipelineKey::TEXTURE_ARRAY;
+            attributes.push(ATTRIBUTE_ATLAS_LAYER.at_shader_location(3));
+        }
+
         let vertex_buffer_layout = layout.get_layout(&attributes)?;
 
+        Ok(self.descriptor(key, vertex_buffer_layout, shader_defs))
+    }
+}
+
+impl SpecializedRenderPipeline for BillboardPipeline {
+    type Key = BillboardPipelineKey;
+
+    /// Pipeline of billboard instances if the key has [`BillboardPipelineKey::INSTANCES`], with
+    /// vertices laid out like [`BillboardInstanceVertex`](crate::instances::BillboardInstanceVertex),
+    /// or of text batches otherwise, whose vertices are laid out like
+    /// [`BillboardTextBatchVertex`](crate::batch::BillboardTextBatchVertex).
+    fn specialize(&self, key: Self::Key) -> RenderPipelineDescriptor {
+        if key.contains(BillboardPipelineKey::INSTANCES) {
+            let vertex_buffer_layout = VertexBufferLayout::from_vertex_formats(
+                VertexStepMode::Instance,
+                [
+                    VertexFormat::Float32x3,
+                    VertexFormat::Float32x2,
+                    VertexFormat::Float32x4,
+                    VertexFormat::Float32x4,
+                ],
+            );
+
+            return self.descriptor(key, vertex_buffer_layout, vec!["VERTEX_COLOR".into()]);
+        }
+
+        let vertex_buffer_layout = VertexBufferLayout::from_vertex_formats(
+            VertexStepMode::Vertex,
+            [
+                VertexFormat::Float32x3,
+                VertexFormat::Float32x2,
+                VertexFormat::Float32x4,
+                VertexFormat::Uint32,
+                VertexFormat::Uint32,
+            ],
+        );
+
+        self.descriptor(
+            key | BillboardPipelineKey::TEXT_BATCH,
+            vertex_buffer_layout,
+            vec!["VERTEX_COLOR".into()],
+        )
+    }
+}
+
+impl BillboardPipeline {
+    /// Descriptor shared by mesh and text batch pipelines, which only differ in their vertex
+    /// buffer layout and in the shader defs it needs.
+    fn descriptor(
+        &self,
+        key: BillboardPipelineKey,
+        vertex_buffer_layout: VertexBufferLayout,
+        mut shader_defs: Vec<ShaderDefVal>,
+    ) -> RenderPipelineDescriptor {
+        const DEF_LOCK_Y: &str = "LOCK_Y";
+        const DEF_LOCK_ROTATION: &str = "LOCK_ROTATION";
+        const DEF_DEPTH_PREPASS: &str = "DEPTH_PREPASS";
+        const DEF_MULTISAMPLED: &str = "MULTISAMPLED";
+        const DEF_SOFT_EDGE: &str = "SOFT_EDGE";
+        const DEF_OIT: &str = "OIT";
+        const DEF_TEXTURE_ARRAY: &str = "TEXTURE_ARRAY";
+        const DEF_TEXT_BATCH: &str = "TEXT_BATCH";
+        const DEF_GPU_CULLING: &str = "GPU_CULLING";
+        const DEF_BILLBOARD_INDEX: &str = "BILLBOARD_INDEX";
+        const DEF_INSTANCES: &str = "INSTANCES";
+
+        if key.contains(BillboardPipelineKey::TEXTURE_ARRAY) {
+            shader_defs.push(DEF_TEXTURE_ARRAY.into());
+        }
+        if key.contains(BillboardPipelineKey::TEXT_BATCH) {
+            shader_defs.push(DEF_TEXT_BATCH.into());
+        }
+        if key.contains(BillboardPipelineKey::GPU_CULLING) {
+            shader_defs.push(DEF_GPU_CULLING.into());
+        }
+        if key.contains(BillboardPipelineKey::INSTANCES) {
+            shader_defs.push(DEF_INSTANCES.into());
+        }
+        // Billboards read from a storage buffer, by an index passed on to the fragment shader
+        if key.intersects(BillboardPipelineKey::TEXT_BATCH | BillboardPipelineKey::GPU_CULLING) {
+            shader_defs.push(DEF_BILLBOARD_INDEX.into());
+        }
+
         let depth_compare = if key.contains(BillboardPipelineKey::DEPTH) {
             CompareFunction::Greater
         } else {
@@ -371,24 +731,61 @@ impl SpecializedMeshPipeline for BillboardPipeline {
             shader_defs.push(DEF_LOCK_ROTATION.into());
         }
 
-        Ok(RenderPipelineDescriptor {
-            label: Some("billboard_pipeline".into()),
-            layout: vec![
-                self.view_layout.clone(),
-                self.billboard_layout.clone(),
-                self.texture_layout.clone(),
-            ],
-            vertex: VertexState {
-                shader: BILLBOARD_SHADER_HANDLE,
-                entry_point: "vertex".into(),
-                buffers: vec![vertex_buffer_layout],
-                shader_defs: shader_defs.clone(),
-            },
-            fragment: Some(FragmentState {
-                shader: BILLBOARD_SHADER_HANDLE,
-                entry_point: "fragment".into(),
-                shader_defs,
-                targets: vec![Some(ColorTargetState {
+        if key.contains(BillboardPipelineKey::DEPTH_PREPASS) {
+            shader_defs.push(DEF_DEPTH_PREPASS.into());
+
+            if key.msaa_samples() > 1 {
+                shader_defs.push(DEF_MULTISAMPLED.into());
+            }
+            if key.contains(BillboardPipelineKey::SOFT_EDGE) {
+                shader_defs.push(DEF_SOFT_EDGE.into());
+            }
+        }
+
+        let (fragment_entry_point, targets) = if key.contains(BillboardPipelineKey::OIT) {
+            shader_defs.push(DEF_OIT.into());
+
+            (
+                "fragment_oit",
+                vec![
+                    Some(ColorTargetState {
+                        format: ACCUM_FORMAT,
+                        blend: Some(BlendState {
+                            color: BlendComponent {
+                                src_factor: BlendFactor::One,
+                                dst_factor: BlendFactor::One,
+                                operation: BlendOperation::Add,
+                            },
+                            alpha: BlendComponent {
+                                src_factor: BlendFactor::One,
+                                dst_factor: BlendFactor::One,
+                                operation: BlendOperation::Add,
+                            },
+                        }),
+                        write_mask: ColorWrites::ALL,
+                    }),
+                    Some(ColorTargetState {
+                        format: REVEALAGE_FORMAT,
+                        blend: Some(BlendState {
+                            color: BlendComponent {
+                                src_factor: BlendFactor::Zero,
+                                dst_factor: BlendFactor::OneMinusSrc,
+                                operation: BlendOperation::Add,
+                            },
+                            alpha: BlendComponent {
+                                src_factor: BlendFactor::Zero,
+                                dst_factor: BlendFactor::OneMinusSrc,
+                                operation: BlendOperation::Add,
+                            },
+                        }),
+                        write_mask: ColorWrites::ALL,
+                    }),
+                ],
+            )
+        } else {
+            (
+                "fragment",
+                vec![Some(ColorTargetState {
                     format: if key.contains(BillboardPipelineKey::HDR) {
                         ViewTarget::TEXTURE_FORMAT_HDR
                     } else {
@@ -408,6 +805,41 @@ impl SpecializedMeshPipeline for BillboardPipeline {
                     }),
                     write_mask: ColorWrites::ALL,
                 })],
+            )
+        };
+
+        RenderPipelineDescriptor {
+            label: Some("billboard_pipeline".into()),
+            layout: vec![
+                self.view_layout(key).clone(),
+                if key.contains(BillboardPipelineKey::TEXT_BATCH) {
+                    self.text_batch_layout
+                        .clone()
+                        .expect("Text batches should be supported")
+                } else if key.contains(BillboardPipelineKey::GPU_CULLING) {
+                    self.gpu_culling_layout
+                        .clone()
+                        .expect("GPU culling should be supported")
+                } else {
+                    self.billboard_layout.clone()
+                },
+                if key.contains(BillboardPipelineKey::TEXTURE_ARRAY) {
+                    self.texture_array_layout.clone()
+                } else {
+                    self.texture_layout.clone()
+                },
+            ],
+            vertex: VertexState {
+                shader: BILLBOARD_SHADER_HANDLE,
+                entry_point: "vertex".into(),
+                buffers: vec![vertex_buffer_layout],
+                shader_defs: shader_defs.clone(),
+            },
+            fragment: Some(FragmentState {
+                shader: BILLBOARD_SHADER_HANDLE,
+                entry_point: fragment_entry_point.into(),
+                shader_defs,
+                targets,
             }),
             primitive: PrimitiveState {
                 topology: PrimitiveTopology::TriangleList,
@@ -431,18 +863,18 @@ impl SpecializedMeshPipeline for BillboardPipeline {
                 alpha_to_coverage_enabled: false,
             },
             push_constant_ranges: vec![],
-        })
+        }
     }
 }
 
 pub struct SetBillboardViewBindGroup<const I: usize>;
-impl<const I: usize> RenderCommand<Transparent3d> for SetBillboardViewBindGroup<I> {
+impl<P: PhaseItem, const I: usize> RenderCommand<P> for SetBillboardViewBindGroup<I> {
     type Param = ();
     type ViewQuery = (Read<ViewUniformOffset>, Read<BillboardViewBindGroup>);
     type ItemQuery = ();
 
     fn render<'w>(
-        _item: &Transparent3d,
+        _item: &P,
         (view_uniform, billboard_mesh_bind_group): ROQueryItem<'w, Self::ViewQuery>,
         _item_query: Option<ROQueryItem<'w, Self::ItemQuery>>,
         _param: SystemParamItem<'w, '_, Self::Param>,
@@ -455,13 +887,13 @@ impl<const I: usize> RenderCommand<Transparent3d> for SetBillboardViewBindGroup<
 }
 
 pub struct SetBillboardBindGroup<const I: usize>;
-impl<const I: usize> RenderCommand<Transparent3d> for SetBillboardBindGroup<I> {
+impl<P: PhaseItem, const I: usize> RenderCommand<P> for SetBillboardBindGroup<I> {
     type Param = SRes<BillboardBindGroup>;
     type ViewQuery = ();
     type ItemQuery = Read<DynamicUniformIndex<BillboardUniform>>;
 
     fn render<'w>(
-        _item: &Transparent3d,
+        _item: &P,
         _view: ROQueryItem<'w, Self::ViewQuery>,
         billboard_index: Option<ROQueryItem<'w, Self::ItemQuery>>,
         billboard_bind_group: SystemParamItem<'w, '_, Self::Param>,
@@ -480,13 +912,13 @@ impl<const I: usize> RenderCommand<Transparent3d> for SetBillboardBindGroup<I> {
 }
 
 pub struct SetBillboardTextureBindGroup<const I: usize>;
-impl<const I: usize> RenderCommand<Transparent3d> for SetBillboardTextureBindGroup<I> {
+impl<P: PhaseItem, const I: usize> RenderCommand<P> for SetBillboardTextureBindGroup<I> {
     type Param = SRes<BillboardImageBindGroups>;
     type ViewQuery = ();
     type ItemQuery = Read<RenderBillboardImage>;
 
     fn render<'w>(
-        _item: &Transparent3d,
+        _item: &P,
         _view: ROQueryItem<'w, Self::ViewQuery>,
         billboard_texture: Option<ROQueryItem<'w, Self::ItemQuery>>,
         images: SystemParamItem<'w, '_, Self::Param>,
@@ -506,13 +938,13 @@ impl<const I: usize> RenderCommand<Transparent3d> for SetBillboardTextureBindGro
 }
 
 pub struct DrawBillboardMesh;
-impl RenderCommand<Transparent3d> for DrawBillboardMesh {
+impl<P: PhaseItem> RenderCommand<P> for DrawBillboardMesh {
     type Param = SRes<RenderAssets<GpuMesh>>;
     type ViewQuery = ();
     type ItemQuery = Read<RenderBillboardMesh>;
 
     fn render<'w>(
-        _item: &Transparent3d,
+        _item: &P,
         _view: ROQueryItem<'w, Self::ViewQuery>,
         mesh: Option<ROQueryItem<'w, Self::ItemQuery>>,
         meshes: SystemParamItem<'w, '_, Self::Param>,
@@ -1,65 +1,251 @@
+use crate::batch::{
+    extract_billboard_text_batch_meshes, prepare_billboard_text_batches,
+    queue_billboard_text_batches, text_batches_supported, BillboardTextBatchMeshes,
+    BillboardTextBatches, DrawBillboardTextBatch, ExtractedBillboardTextBatches,
+};
+use crate::declutter::{
+    declutter_billboards, BillboardDeclutter, BillboardDeclutterPriority, BillboardDeclutterState,
+};
+use crate::gpu_culling::{
+    extract_billboard_gpu_instances, gpu_culling_supported, prepare_billboard_gpu_instances,
+    queue_billboard_gpu_instances, BillboardGpuCulling, BillboardGpuCullingNode,
+    BillboardGpuCullingPass, BillboardGpuCullingPipeline, BillboardGpuInstances,
+    DrawBillboardGpuCulled, ExtractedBillboardGpuInstances, GpuCullingSupported,
+};
+use crate::instances::{
+    extract_billboard_instances, prepare_billboard_instance_buffers, queue_billboard_instances,
+    BillboardInstanceBuffers, BillboardInstances, DrawBillboardInstanced,
+    ExtractedBillboardInstances,
+};
+use crate::oit::{
+    extract_billboard_oit_phases, prepare_billboard_oit_resolve, prepare_billboard_oit_textures,
+    BillboardOit, BillboardOit3d, BillboardOitNode, BillboardOitPass, BillboardOitResolvePipeline,
+};
 use crate::pipeline::{
     prepare_billboard_bind_group, prepare_billboard_view_bind_groups, queue_billboard_texture,
     BillboardImageBindGroups, BillboardPipeline, BillboardUniform, DrawBillboard,
 };
-use crate::text::{extract_billboard_text, update_billboard_text_layout};
+use crate::text::{
+    extract_billboard_text, update_billboard_text_layout, BillboardTextError,
+    BillboardTextMeshStats, BillboardTextSettings,
+};
 use crate::texture::extract_billboard_texture;
+use crate::visibility::{calculate_billboard_bounds, filter_billboard_cameras, BillboardCameras};
 use crate::{
-    Billboard, BillboardMeshHandle, BillboardTextBounds, BillboardTextureHandle,
-    BILLBOARD_SHADER_HANDLE,
+    Billboard, BillboardMeshHandle, BillboardSoftEdge, BillboardSortBias, BillboardTextBounds,
+    BillboardTextureHandle, BILLBOARD_GPU_CULLING_SHADER_HANDLE,
+    BILLBOARD_OIT_RESOLVE_SHADER_HANDLE, BILLBOARD_SHADER_HANDLE,
 };
+use bevy::core_pipeline::core_3d::graph::{Core3d, Node3d};
 use bevy::prelude::*;
 use bevy::render::camera::CameraUpdateSystem;
-use bevy::render::extract_component::UniformComponentPlugin;
-use bevy::render::render_phase::AddRenderCommand;
-use bevy::render::render_resource::SpecializedMeshPipelines;
+use bevy::render::extract_component::{ExtractComponentPlugin, UniformComponentPlugin};
+use bevy::render::render_graph::{RenderGraphApp, ViewNodeRunner};
+use bevy::render::render_phase::{AddRenderCommand, DrawFunctions, ViewSortedRenderPhases};
+use bevy::render::render_resource::{SpecializedMeshPipelines, SpecializedRenderPipelines};
+use bevy::render::renderer::{RenderAdapter, RenderDevice};
 use bevy::render::view::check_visibility;
-use bevy::render::view::VisibilitySystems::CheckVisibility;
+use bevy::render::view::VisibilitySystems::{self, CheckVisibility};
 use bevy::render::{RenderApp, RenderSet};
+use bevy::transform::TransformSystem;
 use bevy::{asset::load_internal_asset, core_pipeline::core_3d::Transparent3d, render::Render};
 
 pub struct BillboardPlugin;
 
 impl Plugin for BillboardPlugin {
     fn build(&self, app: &mut App) {
-        load_internal_asset!(
-            app,
-            BILLBOARD_SHADER_HANDLE,
-            "shader/billboard.wgsl",
-            Shader::from_wgsl
-        );
-
-        app.add_plugins(UniformComponentPlugin::<BillboardUniform>::default())
+        app.add_event::<BillboardTextError>()
+            .init_resource::<BillboardTextSettings>()
+            .init_resource::<BillboardTextMeshStats>()
             .register_type::<BillboardMeshHandle>()
             .register_type::<BillboardTextureHandle>()
             .register_type::<BillboardTextBounds>()
+            .register_type::<BillboardTextSettings>()
+            .register_type::<BillboardTextMeshStats>()
+            .register_type::<BillboardSoftEdge>()
+            .register_type::<BillboardSortBias>()
+            .register_type::<BillboardOit>()
+            .register_type::<BillboardCameras>()
+            .register_type::<BillboardGpuCulling>()
+            .register_type::<BillboardInstances>()
+            .register_type::<BillboardDeclutter>()
+            .register_type::<BillboardDeclutterPriority>()
+            .register_type::<BillboardDeclutterState>()
             .add_systems(
                 PostUpdate,
                 (
-                    update_billboard_text_layout.ambiguous_with(CameraUpdateSystem),
+                    // Prioritizing texts needs their transforms, and their visibility from the
+                    // last update, before it's reset
+                    update_billboard_text_layout
+                        .after(TransformSystem::TransformPropagate)
+                        .before(VisibilitySystems::VisibilityPropagate)
+                        .ambiguous_with(CameraUpdateSystem),
+                    calculate_billboard_bounds
+                        .in_set(VisibilitySystems::CalculateBounds)
+                        .after(update_billboard_text_layout),
                     check_visibility::<With<Billboard>>.in_set(CheckVisibility),
+                    filter_billboard_cameras
+                        .in_set(CheckVisibility)
+                        .after(check_visibility::<With<Billboard>>),
+                    declutter_billboards
+                        .in_set(CheckVisibility)
+                        .after(filter_billboard_cameras),
                 ),
             );
+
+        // Without a renderer (e.g. a dedicated server) only the main world part is needed, so
+        // text layout keeps working for shared gameplay code.
+        if app.get_sub_app(RenderApp).is_none() {
+            return;
+        }
+
+        load_internal_asset!(
+            app,
+            BILLBOARD_SHADER_HANDLE,
+            "shader/billboard.wgsl",
+            Shader::from_wgsl
+        );
+        load_internal_asset!(
+            app,
+            BILLBOARD_OIT_RESOLVE_SHADER_HANDLE,
+            "shader/billboard_oit_resolve.wgsl",
+            Shader::from_wgsl
+        );
+        load_internal_asset!(
+            app,
+            BILLBOARD_GPU_CULLING_SHADER_HANDLE,
+            "shader/billboard_gpu_culling.wgsl",
+            Shader::from_wgsl
+        );
+
+        app.add_plugins((
+            UniformComponentPlugin::<BillboardUniform>::default(),
+            ExtractComponentPlugin::<BillboardOit>::default(),
+        ));
     }
 
     fn finish(&self, app: &mut App) {
-        app.sub_app_mut(RenderApp)
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+
+        let text_batches = ExtractedBillboardTextBatches {
+            supported: text_batches_supported(render_app.world().resource::<RenderDevice>()),
+            ..default()
+        };
+
+        let gpu_instances = ExtractedBillboardGpuInstances {
+            supported: gpu_culling_supported(
+                render_app.world().resource::<RenderDevice>(),
+                render_app.world().resource::<RenderAdapter>(),
+            ),
+            ..default()
+        };
+        let gpu_culling = gpu_instances.supported;
+
+        render_app
+            .insert_resource(text_batches)
+            .insert_resource(gpu_instances)
+            .init_resource::<BillboardGpuInstances>()
+            .init_resource::<ExtractedBillboardInstances>()
+            .init_resource::<BillboardInstanceBuffers>()
+            .init_resource::<BillboardTextBatchMeshes>()
+            .init_resource::<BillboardTextBatches>()
+            .init_resource::<DrawFunctions<BillboardOit3d>>()
+            .init_resource::<ViewSortedRenderPhases<BillboardOit3d>>()
             .add_render_command::<Transparent3d, DrawBillboard>()
+            .add_render_command::<BillboardOit3d, DrawBillboard>()
+            .add_render_command::<Transparent3d, DrawBillboardTextBatch>()
+            .add_render_command::<BillboardOit3d, DrawBillboardTextBatch>()
+            .add_render_command::<Transparent3d, DrawBillboardGpuCulled>()
+            .add_render_command::<BillboardOit3d, DrawBillboardGpuCulled>()
+            .add_render_command::<Transparent3d, DrawBillboardInstanced>()
+            .add_render_command::<BillboardOit3d, DrawBillboardInstanced>()
             .init_resource::<BillboardPipeline>()
             .init_resource::<SpecializedMeshPipelines<BillboardPipeline>>()
+            .init_resource::<SpecializedRenderPipelines<BillboardPipeline>>()
+            .init_resource::<BillboardOitResolvePipeline>()
+            .init_resource::<SpecializedRenderPipelines<BillboardOitResolvePipeline>>()
             .init_resource::<BillboardImageBindGroups>()
             .add_systems(
                 ExtractSchedule,
-                (extract_billboard_text, extract_billboard_texture),
+                (
+                    extract_billboard_text,
+                    extract_billboard_text_batch_meshes.after(extract_billboard_text),
+                    extract_billboard_texture,
+                    extract_billboard_gpu_instances,
+                    extract_billboard_instances,
+                    extract_billboard_oit_phases,
+                ),
+            )
+            .add_systems(
+                Render,
+                // Batches reuse the image bind groups invalidated by `queue_billboard_texture`
+                (
+                    queue_billboard_texture,
+                    queue_billboard_text_batches,
+                    queue_billboard_gpu_instances,
+                    queue_billboard_instances,
+                )
+                    .chain()
+                    .in_set(RenderSet::Queue),
             )
-            .add_systems(Render, queue_billboard_texture.in_set(RenderSet::Queue))
             .add_systems(
                 Render,
                 prepare_billboard_bind_group.in_set(RenderSet::PrepareBindGroups),
             )
+            .add_systems(
+                Render,
+                prepare_billboard_text_batches.in_set(RenderSet::PrepareBindGroups),
+            )
+            .add_systems(
+                Render,
+                prepare_billboard_gpu_instances.in_set(RenderSet::PrepareBindGroups),
+            )
+            .add_systems(
+                Render,
+                prepare_billboard_instance_buffers.in_set(RenderSet::PrepareResources),
+            )
             .add_systems(
                 Render,
                 prepare_billboard_view_bind_groups.in_set(RenderSet::PrepareBindGroups),
+            )
+            .add_systems(
+                Render,
+                prepare_billboard_oit_textures.in_set(RenderSet::PrepareResources),
+            )
+            .add_systems(
+                Render,
+                prepare_billboard_oit_resolve.in_set(RenderSet::PrepareBindGroups),
+            )
+            .add_render_graph_node::<ViewNodeRunner<BillboardOitNode>>(Core3d, BillboardOitPass)
+            .add_render_graph_edges(
+                Core3d,
+                (
+                    Node3d::MainTransparentPass,
+                    BillboardOitPass,
+                    Node3d::EndMainPass,
+                ),
             );
+
+        // Culls before the main passes that draw the survivors
+        if gpu_culling {
+            render_app
+                .init_resource::<BillboardGpuCullingPipeline>()
+                .add_render_graph_node::<ViewNodeRunner<BillboardGpuCullingNode>>(
+                    Core3d,
+                    BillboardGpuCullingPass,
+                )
+                .add_render_graph_edges(
+                    Core3d,
+                    (
+                        Node3d::EndPrepasses,
+                        BillboardGpuCullingPass,
+                        Node3d::StartMainPass,
+                    ),
+                );
+        }
+
+        app.insert_resource(GpuCullingSupported(gpu_culling));
     }
 }
@@ -0,0 +1,140 @@
+use crate::declutter::BillboardDeclutterState;
+use crate::instances::BillboardInstances;
+use crate::math::BillboardBasis;
+use crate::text::BillboardTextHandles;
+use crate::utils::calculate_billboard_uniform;
+use crate::{Billboard, BillboardLockAxis, BillboardMeshHandle};
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy::render::mesh::VertexAttributeValues;
+use bevy::render::view::VisibleEntities;
+
+type ScreenBillboardQuery = (
+    &'static GlobalTransform,
+    &'static Transform,
+    Option<&'static BillboardLockAxis>,
+    Option<&'static BillboardMeshHandle>,
+    Option<&'static BillboardTextHandles>,
+    Option<&'static BillboardInstances>,
+    Option<&'static BillboardDeclutterState>,
+);
+
+/// Rectangles billboards cover on the screen, projected the same way as in the vertex shader.
+///
+/// Rectangles are in logical pixels of the viewport of the camera, from its top left corner,
+/// like [`Camera::world_to_viewport`]. They can extend past the viewport.
+#[derive(SystemParam)]
+pub struct BillboardScreenRects<'w, 's> {
+    cameras: Query<
+        'w,
+        's,
+        (
+            &'static Camera,
+            &'static GlobalTransform,
+            &'static VisibleEntities,
+        ),
+    >,
+    billboards: Query<'w, 's, ScreenBillboardQuery, With<Billboard>>,
+    meshes: Res<'w, Assets<Mesh>>,
+}
+
+impl BillboardScreenRects<'_, '_> {
+    /// Rectangle of `billboard` on the screen of `camera`. `None` if its mesh isn't loaded, or
+    /// part of it is behind the camera or past its far plane.
+    pub fn get(&self, camera: Entity, billboard: Entity) -> Option<Rect> {
+        let (camera, camera_transform, _) = self.cameras.get(camera).ok()?;
+        self.project(camera, camera_transform, billboard, true)
+    }
+
+    /// Rectangles of the billboards `camera` saw in the last update.
+    pub fn iter(&self, camera: Entity) -> impl Iterator<Item = (Entity, Rect)> + '_ {
+        self.iter_with(camera, true)
+    }
+
+    /// Rectangles of the billboards `camera` sees, where they would be without decluttering.
+    pub(crate) fn iter_undecluttered(
+        &self,
+        camera: Entity,
+    ) -> impl Iterator<Item = (Entity, Rect)> + '_ {
+        self.iter_with(camera, false)
+    }
+
+    fn iter_with(
+        &self,
+        camera: Entity,
+        decluttered: bool,
+    ) -> impl Iterator<Item = (Entity, Rect)> + '_ {
+        self.cameras.get(camera).ok().into_iter().flat_map(
+            move |(camera, camera_transform, visible_entities)| {
+                visible_entities
+                    .get::<With<Billboard>>()
+                    .iter()
+                    .filter_map(move |&billboard| {
+                        let rect =
+                            self.project(camera, camera_transform, billboard, decluttered)?;
+                        Some((billboard, rect))
+                    })
+            },
+        )
+    }
+
+    fn project(
+        &self,
+        camera: &Camera,
+        camera_transform: &GlobalTransform,
+        billboard: Entity,
+        decluttered: bool,
+    ) -> Option<Rect> {
+        let (global_transform, transform, lock_axis, mesh, text, instances, declutter) =
+            self.billboards.get(billboard).ok()?;
+        let declutter = declutter.filter(|_| decluttered);
+
+        let model =
+            calculate_billboard_uniform(global_transform, transform, lock_axis, None, declutter)
+                .transform();
+        let basis = BillboardBasis::new(camera_transform, lock_axis);
+
+        // Vertices as (offset, position in the mesh), turned the same way as in the vertex shader
+        let vertices: Vec<(Vec3, Vec2)> = if let Some(instances) = instances {
+            instances
+                .instances
+                .iter()
+                .flat_map(|instance| {
+                    [Vec2::ZERO, Vec2::X, Vec2::ONE, Vec2::Y]
+                        .map(|corner| (instance.position, (corner - 0.5) * instance.size))
+                })
+                .collect()
+        } else {
+            let handles: Vec<_> = match text {
+                Some(text) => text.iter().map(|group| group.mesh()).collect(),
+                None => vec![&mesh?.0],
+            };
+
+            let mut vertices = Vec::new();
+            for handle in handles {
+                let Some(VertexAttributeValues::Float32x3(positions)) =
+                    self.meshes.get(handle)?.attribute(Mesh::ATTRIBUTE_POSITION)
+                else {
+                    return None;
+                };
+
+                vertices.extend(
+                    positions
+                        .iter()
+                        .map(|&[x, y, _]| (Vec3::ZERO, Vec2::new(x, y))),
+                );
+            }
+            vertices
+        };
+
+        let mut points = vertices.into_iter().map(|(offset, position)| {
+            let world_position = model.transform_point3(offset + basis.local_position(position));
+            camera.world_to_viewport(camera_transform, world_position)
+        });
+
+        let first = points.next()??;
+        points.try_fold(Rect::from_corners(first, first), |rect, point| {
+            Some(rect.union_point(point?))
+        })
+    }
+}
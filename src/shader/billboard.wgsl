@@ -1,35 +1,106 @@
+// Matches the start of bevy's ViewUniform, the rest of the fields are unused
 struct View {
-    view_proj: mat4x4<f32>,
-    inverse_view_proj: mat4x4<f32>,
-    view: mat4x4<f32>,
-    inverse_view: mat4x4<f32>,
-    projection: mat4x4<f32>,
-    inverse_projection: mat4x4<f32>,
+    clip_from_world: mat4x4<f32>,
+    unjittered_clip_from_world: mat4x4<f32>,
+    world_from_clip: mat4x4<f32>,
+    world_from_view: mat4x4<f32>,
+    view_from_world: mat4x4<f32>,
+    clip_from_view: mat4x4<f32>,
+    view_from_clip: mat4x4<f32>,
     world_position: vec3<f32>,
+    exposure: f32,
     viewport: vec4<f32>,
 };
 
 struct Billboard {
     model: mat4x4<f32>,
+    soft_edge: f32,
+    // Opacity from decluttering
+    alpha: f32,
 }
 
+#ifdef GPU_CULLING
+struct BillboardInstance {
+    billboard: Billboard,
+    max_distance: f32,
+}
+
+// Matches the culling uniform of billboard_gpu_culling.wgsl
+struct Cull {
+    frustum: array<vec4<f32>, 5>,
+    camera_position: vec3<f32>,
+    mesh_radius: f32,
+    instance_offset: u32,
+    instance_count: u32,
+    visible_offset: u32,
+    indirect_index: u32,
+}
+#endif
+
 @group(0) @binding(0)
 var<uniform> view: View;
 
+// Bound as a float texture, as GLSL can't load from depth textures
+#ifdef DEPTH_PREPASS
+#ifdef MULTISAMPLED
+@group(0) @binding(1)
+var depth_prepass_texture: texture_multisampled_2d<f32>;
+#else
+@group(0) @binding(1)
+var depth_prepass_texture: texture_2d<f32>;
+#endif
+#endif
+
+#ifdef TEXT_BATCH
+@group(1) @binding(0)
+var<storage, read> billboards: array<Billboard>;
+#else
+#ifdef GPU_CULLING
+@group(1) @binding(0)
+var<storage, read> instances: array<BillboardInstance>;
+@group(1) @binding(1)
+var<storage, read> visible_instances: array<u32>;
+@group(1) @binding(2)
+var<uniform> cull: Cull;
+#else
 @group(1) @binding(0)
 var<uniform> billboard: Billboard;
+#endif
+#endif
 
+#ifdef TEXTURE_ARRAY
+@group(2) @binding(0)
+var billboard_texture: texture_2d_array<f32>;
+#else
 @group(2) @binding(0)
 var billboard_texture: texture_2d<f32>;
+#endif
 @group(2) @binding(1)
 var billboard_sampler: sampler;
 
 struct Vertex {
+#ifdef INSTANCES
+    @builtin(vertex_index) index: u32,
+    // Center of the instance relative to the billboard, its size and the uv rect of its texture
+    @location(0) instance_position: vec3<f32>,
+    @location(1) instance_size: vec2<f32>,
+    @location(3) instance_uv_rect: vec4<f32>,
+#else
     @location(0) position: vec3<f32>,
     @location(1) uv: vec2<f32>,
+#endif
 #ifdef VERTEX_COLOR
     @location(2) color: vec4<f32>,
 #endif
+#ifdef TEXTURE_ARRAY
+    @location(3) atlas_layer: u32,
+#endif
+#ifdef TEXT_BATCH
+    @location(4) billboard_index: u32,
+#endif
+#ifdef GPU_CULLING
+    @builtin(instance_index) instance_index: u32,
+#endif
 };
 struct VertexOutput {
     @builtin(position) position: vec4<f32>,
@@ -37,48 +108,158 @@ struct VertexOutput {
 #ifdef VERTEX_COLOR
     @location(1) color: vec4<f32>,
 #endif
+#ifdef TEXTURE_ARRAY
+    @location(2) @interpolate(flat) atlas_layer: u32,
+#endif
+#ifdef BILLBOARD_INDEX
+    @location(3) @interpolate(flat) billboard_index: u32,
+#endif
 };
 
 @vertex
 fn vertex(vertex: Vertex) -> VertexOutput {
+#ifdef TEXT_BATCH
+    let billboard_index = vertex.billboard_index;
+    let billboard = billboards[billboard_index];
+#endif
+#ifdef GPU_CULLING
+    // Only the instances that survived culling are drawn
+    let billboard_index = visible_instances[cull.visible_offset + vertex.instance_index];
+    let billboard = instances[billboard_index].billboard;
+#endif
+
+#ifdef INSTANCES
+    // Two triangles of a quad around the center of the instance
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, 1.0),
+    );
+    let corner = corners[vertex.index];
+    let local_position = vec3<f32>((corner - 0.5) * vertex.instance_size, 0.0);
+    let offset = vertex.instance_position;
+    let uv = mix(vertex.instance_uv_rect.xy, vertex.instance_uv_rect.zw, vec2<f32>(corner.x, 1.0 - corner.y));
+#else
+    let local_position = vertex.position;
+    let offset = vec3<f32>(0.0);
+    let uv = vertex.uv;
+#endif
+
 #ifdef LOCK_ROTATION
-    let vertex_position = vec4<f32>(-vertex.position.x, vertex.position.y, vertex.position.z, 1.0);
-    let position = view.view_proj * billboard.model * vertex_position;
+    let vertex_position = vec4<f32>(offset + vec3<f32>(-local_position.x, local_position.y, local_position.z), 1.0);
+    let position = view.clip_from_world * billboard.model * vertex_position;
 #else
-    let camera_right = normalize(vec3<f32>(view.view_proj.x.x, view.view_proj.y.x, view.view_proj.z.x));
+    // Orientation comes from the camera transform alone, so the projection (orthographic,
+    // non-square or off-center) can't skew or scale the billboard
+    let camera_right = normalize(view.world_from_view[0].xyz);
 #ifdef LOCK_Y
     let camera_up = vec3<f32>(0.0, 1.0, 0.0);
 #else
-    let camera_up = normalize(vec3<f32>(view.view_proj.x.y, view.view_proj.y.y, view.view_proj.z.y));
+    let camera_up = normalize(view.world_from_view[1].xyz);
 #endif
 
-    let world_space = camera_right * vertex.position.x + camera_up * vertex.position.y;
-    let position = view.view_proj * billboard.model * vec4<f32>(world_space, 1.0);
+    let world_space = camera_right * local_position.x + camera_up * local_position.y;
+    let position = view.clip_from_world * billboard.model * vec4<f32>(offset + world_space, 1.0);
 #endif
 
     var out: VertexOutput;
     out.position = position;
-    out.uv = vertex.uv;
+    out.uv = uv;
 #ifdef VERTEX_COLOR
     out.color = vertex.color;
 #endif
+#ifdef TEXTURE_ARRAY
+    out.atlas_layer = vertex.atlas_layer;
+#endif
+#ifdef BILLBOARD_INDEX
+    out.billboard_index = billboard_index;
+#endif
 
     return out;
 }
 
 struct Fragment {
+    @builtin(position) frag_coord: vec4<f32>,
     @location(0) uv: vec2<f32>,
 #ifdef VERTEX_COLOR
     @location(1) color: vec4<f32>,
 #endif
+#ifdef TEXTURE_ARRAY
+    @location(2) @interpolate(flat) atlas_layer: u32,
+#endif
+#ifdef BILLBOARD_INDEX
+    @location(3) @interpolate(flat) billboard_index: u32,
+#endif
 };
 
-@fragment
-fn fragment(fragment: Fragment) -> @location(0) vec4<f32> {
-    let color = textureSample(billboard_texture, billboard_sampler, fragment.uv);
+// Converts a depth buffer value to a positive distance along the view direction, mirrored on the
+// CPU by `math::view_distance`
+fn view_distance(depth: f32) -> f32 {
+    let view_position = view.view_from_clip * vec4<f32>(0.0, 0.0, depth, 1.0);
+    return -view_position.z / view_position.w;
+}
+
+fn billboard_color(fragment: Fragment) -> vec4<f32> {
+#ifdef TEXTURE_ARRAY
+    var color = textureSample(billboard_texture, billboard_sampler, fragment.uv, fragment.atlas_layer);
+#else
+    var color = textureSample(billboard_texture, billboard_sampler, fragment.uv);
+#endif
 #ifdef VERTEX_COLOR
-    return color * fragment.color;
+    color = color * fragment.color;
+#endif
+
+#ifdef TEXT_BATCH
+    let fragment_billboard = billboards[fragment.billboard_index];
 #else
+#ifdef GPU_CULLING
+    let fragment_billboard = instances[fragment.billboard_index].billboard;
+#else
+    let fragment_billboard = billboard;
+#endif
+#endif
+    color.a = color.a * fragment_billboard.alpha;
+
+#ifdef SOFT_EDGE
+    let soft_edge = fragment_billboard.soft_edge;
+    let scene_depth = textureLoad(depth_prepass_texture, vec2<i32>(fragment.frag_coord.xy), 0).r;
+    let gap = view_distance(scene_depth) - view_distance(fragment.frag_coord.z);
+    color.a = color.a * saturate(gap / soft_edge);
+#endif
+
     return color;
+}
+
+@fragment
+fn fragment(fragment: Fragment) -> @location(0) vec4<f32> {
+    return billboard_color(fragment);
+}
+
+#ifdef OIT
+struct OitOutput {
+    @location(0) accum: vec4<f32>,
+    @location(1) revealage: f32,
+};
+
+// Weighted blended order-independent transparency, weight function from equation 9 of
+// https://jcgt.org/published/0002/02/09/
+@fragment
+fn fragment_oit(fragment: Fragment) -> OitOutput {
+    let color = billboard_color(fragment);
+    let distance = view_distance(fragment.frag_coord.z);
+    let weight = color.a * clamp(
+        10.0 / (1e-5 + pow(distance / 5.0, 2.0) + pow(distance / 200.0, 6.0)),
+        1e-2,
+        3e3,
+    );
+
+    var out: OitOutput;
+    out.accum = vec4<f32>(color.rgb * color.a, color.a) * weight;
+    out.revealage = color.a;
+
+    return out;
+}
 #endif
-}
\ No newline at end of file
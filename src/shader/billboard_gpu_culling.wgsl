@@ -0,0 +1,71 @@
+struct Billboard {
+    model: mat4x4<f32>,
+    soft_edge: f32,
+    // Opacity from decluttering
+    alpha: f32,
+}
+
+struct BillboardInstance {
+    billboard: Billboard,
+    max_distance: f32,
+}
+
+struct Cull {
+    // Every half space of the view frustum but the far one, as normal and distance
+    frustum: array<vec4<f32>, 5>,
+    camera_position: vec3<f32>,
+    mesh_radius: f32,
+    instance_offset: u32,
+    instance_count: u32,
+    visible_offset: u32,
+    indirect_index: u32,
+}
+
+// Indexed and non-indexed draws both have their instance count second
+struct DrawIndirect {
+    count: u32,
+    instance_count: atomic<u32>,
+    first: u32,
+    base_vertex: i32,
+    first_instance: u32,
+}
+
+@group(0) @binding(0)
+var<storage, read> instances: array<BillboardInstance>;
+@group(0) @binding(1)
+var<storage, read_write> visible_instances: array<u32>;
+@group(0) @binding(2)
+var<storage, read_write> indirect: array<DrawIndirect>;
+@group(0) @binding(3)
+var<uniform> cull: Cull;
+
+// Mirrored on the CPU by `BillboardGpuCullUniform::is_visible`
+@compute @workgroup_size(64)
+fn cull_instances(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= cull.instance_count {
+        return;
+    }
+
+    let index = cull.instance_offset + id.x;
+    let instance = instances[index];
+    let model = instance.billboard.model;
+    let center = model[3].xyz;
+
+    if distance(center, cull.camera_position) > instance.max_distance {
+        return;
+    }
+
+    // Bounding sphere of the mesh facing the camera in any direction
+    let scale = max(length(model[0].xyz), max(length(model[1].xyz), length(model[2].xyz)));
+    let radius = cull.mesh_radius * scale;
+
+    for (var i = 0u; i < 5u; i += 1u) {
+        let half_space = cull.frustum[i];
+        if dot(half_space.xyz, center) + half_space.w + radius <= 0.0 {
+            return;
+        }
+    }
+
+    let slot = atomicAdd(&indirect[cull.indirect_index].instance_count, 1u);
+    visible_instances[cull.visible_offset + slot] = index;
+}
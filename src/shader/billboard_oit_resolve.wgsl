@@ -0,0 +1,22 @@
+#import bevy_core_pipeline::fullscreen_vertex_shader::FullscreenVertexOutput
+
+@group(0) @binding(0)
+var accum_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var revealage_texture: texture_2d<f32>;
+
+@fragment
+fn fragment(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
+    let coords = vec2<i32>(in.position.xy);
+    let revealage = textureLoad(revealage_texture, coords, 0).r;
+
+    // Nothing was drawn to this pixel
+    if revealage >= 1.0 {
+        discard;
+    }
+
+    let accum = textureLoad(accum_texture, coords, 0);
+    let color = accum.rgb / clamp(accum.a, 1e-4, 5e4);
+
+    return vec4<f32>(color, revealage);
+}
@@ -1,17 +1,29 @@
-use crate::pipeline::{RenderBillboardImage, RenderBillboardMesh};
-use crate::utils::calculate_billboard_uniform;
-use crate::{BillboardDepth, BillboardLockAxis};
-use bevy::color::palettes;
+use crate::batch::{ExtractedBatchedText, ExtractedBillboardTextBatches};
+use crate::declutter::BillboardDeclutterState;
+use crate::pipeline::{BillboardUniform, RenderBillboardImage, RenderBillboardMesh};
+use crate::utils::{calculate_billboard_uniform, ExtractedBillboards};
+use crate::{BillboardDepth, BillboardLockAxis, BillboardSoftEdge, BillboardSortBias};
+use ab_glyph::{point, Font as _, GlyphId, ScaleFont as _};
+use bevy::asset::LoadState;
+use bevy::ecs::system::SystemParam;
+use bevy::math::FloatOrd;
 use bevy::prelude::*;
-use bevy::render::mesh::{Indices, PrimitiveTopology};
+use bevy::render::mesh::VertexAttributeValues;
+use bevy::render::mesh::{Indices, MeshVertexAttribute, PrimitiveTopology};
 use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::render_resource::{
+    Extent3d, TextureDimension, TextureViewDescriptor, TextureViewDimension, VertexFormat,
+};
 use bevy::render::Extract;
 use bevy::sprite::Anchor;
+use bevy::tasks::{ComputeTaskPool, ParallelSlice};
 use bevy::text::{
-    BreakLineOn, FontAtlasSets, PositionedGlyph, Text2dBounds, TextPipeline, TextSettings,
-    YAxisOrientation,
+    BreakLineOn, FontAtlasSets, GlyphAtlasInfo, GlyphBrush, PositionedGlyph, SubpixelOffset,
+    Text2dBounds, TextLayoutInfo, TextSettings, YAxisOrientation,
 };
-use bevy::utils::{HashMap, HashSet};
+use bevy::utils::{Duration, HashMap, HashSet, Instant};
+use glyph_brush_layout::ab_glyph::PxScale;
+use glyph_brush_layout::{FontId, SectionGlyph, SectionText};
 use smallvec::SmallVec;
 
 // Uses this as reference
@@ -21,6 +33,99 @@ use smallvec::SmallVec;
 #[reflect(Component)]
 pub struct BillboardTextBounds(pub Text2dBounds);
 
+/// Layer of the texture array a glyph is in, for texts drawn with
+/// [`BillboardTextSettings::texture_arrays`].
+pub const ATTRIBUTE_ATLAS_LAYER: MeshVertexAttribute =
+    MeshVertexAttribute::new("BillboardAtlasLayer", 1876923440, VertexFormat::Uint32);
+
+/// Sent when the text of a billboard couldn't be laid out, after retrying according to
+/// [`BillboardTextSettings::retry`]. The text is laid out with the fallback font instead, if
+/// there is one.
+#[derive(Event, Debug)]
+pub struct BillboardTextError {
+    pub entity: Entity,
+    pub error: TextError,
+}
+
+/// How long to wait for the fonts of a billboard text to load. Retrying stops early when a font
+/// fails to load.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Reflect)]
+pub enum BillboardTextRetry {
+    /// Retry every update until the fonts are loaded.
+    #[default]
+    UntilLoaded,
+    /// Retry for at most this many updates.
+    Updates(u32),
+}
+
+impl BillboardTextRetry {
+    fn should_retry(self, attempts: u32) -> bool {
+        match self {
+            BillboardTextRetry::UntilLoaded => true,
+            BillboardTextRetry::Updates(updates) => attempts < updates,
+        }
+    }
+}
+
+/// How many texts [`update_billboard_text_layout`] lays out in an update, so spawning thousands
+/// of texts at once doesn't stall a frame. The rest wait for the next updates, visible texts
+/// first and then the ones nearest to a camera.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Reflect)]
+pub enum BillboardTextBudget {
+    #[default]
+    Unlimited,
+    /// Lay out at most this many texts per update.
+    Texts(usize),
+    /// Stop laying out texts once this much time was spent on them in an update.
+    Time(Duration),
+}
+
+impl BillboardTextBudget {
+    /// At least one text is laid out every update, so layout always makes progress.
+    fn is_spent(self, laid_out: usize, start: Instant) -> bool {
+        match self {
+            BillboardTextBudget::Unlimited => false,
+            BillboardTextBudget::Texts(texts) => laid_out >= texts.max(1),
+            BillboardTextBudget::Time(time) => laid_out > 0 && start.elapsed() >= time,
+        }
+    }
+}
+
+#[derive(Resource, Clone, Debug, Default, Reflect)]
+#[reflect(Resource)]
+pub struct BillboardTextSettings {
+    pub retry: BillboardTextRetry,
+    pub budget: BillboardTextBudget,
+    /// Font every section is laid out with when the text can't be laid out with its own fonts.
+    /// It should already be loaded by then.
+    pub fallback_font: Option<Handle<Font>>,
+    /// Draws texts spanning several font atlases (fonts, sizes or full atlas pages) as a single
+    /// mesh, sampling a texture array of those atlases. The array is a copy of the atlases, made
+    /// again when a text using it is laid out, so this trades memory and copies for draw calls.
+    pub texture_arrays: bool,
+    /// Draws all the visible texts sharing a font atlas with a single draw call per camera,
+    /// instead of a draw call per text. A batch is sorted by its farthest text, so batched texts
+    /// don't interleave with other billboards by distance. Needs storage buffers, without them
+    /// (WebGL2) texts are drawn one by one.
+    pub batching: bool,
+    /// Shapes texts and builds their meshes on the thread of the layout system, instead of
+    /// spreading them over the compute task pool, e.g. when other systems keep the pool busy.
+    pub serial_layout: bool,
+}
+
+/// Running count of the meshes [`update_billboard_text_layout`] generated, to keep an eye on
+/// mesh asset churn from changing text.
+#[derive(Resource, Copy, Clone, Debug, Default, Reflect)]
+#[reflect(Resource)]
+pub struct BillboardTextMeshStats {
+    /// Meshes added as new assets.
+    pub allocated: u64,
+    /// Meshes written over an existing asset of the same text.
+    pub reused: u64,
+    /// Texts whose changed glyphs were swapped in their meshes, without a full relayout.
+    pub patched: u64,
+}
+
 // TODO: Maybe use something like { Single(Group), Multi(SmallVec<[Group; 1]>) }, benchmark it
 #[derive(Component, Clone, Debug, Deref, DerefMut, Default)]
 pub struct BillboardTextHandles(pub SmallVec<[BillboardTextHandleGroup; 1]>);
@@ -31,221 +136,1162 @@ pub struct BillboardTextHandleGroup {
     image: Handle<Image>,
 }
 
+impl BillboardTextHandleGroup {
+    pub fn mesh(&self) -> &Handle<Mesh> {
+        &self.mesh
+    }
+
+    pub fn image(&self) -> &Handle<Image> {
+        &self.image
+    }
+}
+
+type BillboardTextQuery = (
+    Entity,
+    &'static ViewVisibility,
+    Ref<'static, GlobalTransform>,
+    Ref<'static, Transform>,
+    &'static BillboardTextHandles,
+    &'static BillboardDepth,
+    Option<&'static BillboardLockAxis>,
+    Option<&'static BillboardSoftEdge>,
+    Option<&'static BillboardSortBias>,
+    Option<Ref<'static, BillboardDeclutterState>>,
+);
+
 pub fn extract_billboard_text(
     mut commands: Commands,
     mut previous_len: Local<usize>,
-    billboard_text_query: Extract<
-        Query<(
-            Entity,
-            &ViewVisibility,
-            &GlobalTransform,
-            &Transform,
-            &BillboardTextHandles,
-            &BillboardDepth,
-            Option<&BillboardLockAxis>,
-        )>,
-    >,
+    mut extracted: Local<ExtractedBillboards<(BillboardUniform, RenderBillboard)>>,
+    mut text_batches: ResMut<ExtractedBillboardTextBatches>,
+    settings: Extract<Res<BillboardTextSettings>>,
+    billboard_text_query: Extract<Query<BillboardTextQuery>>,
 ) {
     let mut batch = Vec::with_capacity(*previous_len);
+    let batching = settings.batching && text_batches.supported;
+    text_batches.texts.clear();
 
-    for (entity, visibility, global_transform, transform, handles, &depth, lock_axis) in
-        &billboard_text_query
+    for (
+        entity,
+        visibility,
+        global_transform,
+        transform,
+        handles,
+        &depth,
+        lock_axis,
+        soft_edge,
+        sort_bias,
+        declutter,
+    ) in &billboard_text_query
     {
+        let billboard = RenderBillboard {
+            depth,
+            lock_axis: lock_axis.copied(),
+            soft_edge: soft_edge.copied(),
+            sort_bias: sort_bias.map_or(0.0, |bias| bias.0),
+        };
+        let &(uniform, billboard) = extracted.get_or_extract(
+            entity,
+            |(_, extracted)| {
+                global_transform.is_changed()
+                    || transform.is_changed()
+                    || declutter
+                        .as_ref()
+                        .is_some_and(|declutter| declutter.is_changed())
+                    || *extracted != billboard
+            },
+            || {
+                let uniform = calculate_billboard_uniform(
+                    &global_transform,
+                    &transform,
+                    lock_axis,
+                    soft_edge,
+                    declutter.as_deref(),
+                );
+                (uniform, billboard)
+            },
+        );
+
         if !visibility.get() {
             continue;
         }
 
-        let uniform = calculate_billboard_uniform(global_transform, transform, lock_axis);
-
-        for handle_group in handles.iter() {
-            batch.push((
+        if batching {
+            text_batches.texts.insert(
                 entity,
-                (
+                ExtractedBatchedText {
                     uniform,
-                    RenderBillboardMesh {
-                        id: handle_group.mesh.id(),
-                    },
-                    RenderBillboardImage {
-                        id: handle_group.image.id(),
-                    },
-                    RenderBillboard {
-                        depth,
-                        lock_axis: lock_axis.copied(),
-                    },
-                ),
-            ));
+                    billboard,
+                    groups: handles
+                        .iter()
+                        .map(|group| (group.mesh.id(), group.image.id()))
+                        .collect(),
+                },
+            );
+            continue;
         }
+
+        let mut groups = handles.iter().map(|handle_group| {
+            (
+                uniform,
+                RenderBillboardMesh {
+                    id: handle_group.mesh.id(),
+                },
+                RenderBillboardImage {
+                    id: handle_group.image.id(),
+                },
+                billboard,
+            )
+        });
+        let Some(first_group) = groups.next() else {
+            continue;
+        };
+
+        // An entity only has one mesh and image, so the other groups are entities of their own
+        let other_groups = groups.map(|group| commands.spawn(group).id()).collect();
+
+        batch.push((
+            entity,
+            (first_group, RenderBillboardTextGroups(other_groups)),
+        ));
     }
 
+    extracted.finish_frame();
     *previous_len = batch.len();
     commands.insert_or_spawn_batch(batch);
 }
 
+/// Settings, assets and `bevy_text` resources [`update_billboard_text_layout`] lays out texts
+/// and builds their meshes with.
+#[derive(SystemParam)]
+pub struct BillboardTextLayoutResources<'w> {
+    settings: Res<'w, BillboardTextSettings>,
+    stats: ResMut<'w, BillboardTextMeshStats>,
+    asset_server: Res<'w, AssetServer>,
+    fonts: Res<'w, Assets<Font>>,
+    images: ResMut<'w, Assets<Image>>,
+    meshes: ResMut<'w, Assets<Mesh>>,
+    texture_atlases: ResMut<'w, Assets<TextureAtlasLayout>>,
+    font_atlas_sets: ResMut<'w, FontAtlasSets>,
+    text_settings: Res<'w, TextSettings>,
+}
+
+type BillboardTextLayoutQuery = (
+    Entity,
+    Ref<'static, Text>,
+    Ref<'static, BillboardTextBounds>,
+    Ref<'static, Anchor>,
+    &'static mut BillboardTextHandles,
+);
+
 pub fn update_billboard_text_layout(
-    mut queue: Local<HashSet<Entity>>,
-    mut images: ResMut<Assets<Image>>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    fonts: Res<Assets<Font>>,
-    text_settings: Res<TextSettings>,
-    mut texture_atlases: ResMut<Assets<TextureAtlasLayout>>,
-    mut font_atlas_set_storage: ResMut<FontAtlasSets>,
-    mut text_pipeline: ResMut<TextPipeline>,
-    mut text_query: Query<(
-        Entity,
-        Ref<Text>,
-        Ref<BillboardTextBounds>,
-        Ref<Anchor>,
-        &mut BillboardTextHandles,
-    )>,
+    mut retries: Local<HashMap<Entity, u32>>,
+    (mut layouts, mut atlas_arrays): (
+        Local<HashMap<Entity, BillboardTextLayout>>,
+        Local<BillboardTextAtlasArrays>,
+    ),
+    mut removed_texts: RemovedComponents<BillboardTextHandles>,
+    mut text_errors: EventWriter<BillboardTextError>,
+    resources: BillboardTextLayoutResources,
+    mut text_query: Query<BillboardTextLayoutQuery>,
+    priority: BillboardTextPriority,
 ) {
-    const SCALE_FACTOR: f32 = 1.0;
+    let BillboardTextLayoutResources {
+        settings,
+        mut stats,
+        asset_server,
+        fonts,
+        mut images,
+        mut meshes,
+        mut texture_atlases,
+        font_atlas_sets: mut font_atlas_set_storage,
+        text_settings,
+    } = resources;
 
-    for (entity, text, bounds, anchor, mut billboard_text_handles) in &mut text_query {
-        if text.is_changed() || bounds.is_changed() || anchor.is_changed() || queue.remove(&entity)
-        {
-            let text_bounds = Vec2::new(
-                if text.linebreak_behavior == BreakLineOn::NoWrap {
-                    f32::INFINITY
-                } else {
-                    bounds.size.x
-                },
-                bounds.size.y,
-            );
+    for entity in removed_texts.read() {
+        retries.remove(&entity);
+        layouts.remove(&entity);
+    }
 
-            let info = match text_pipeline.queue_text(
-                &fonts,
-                &text.sections,
-                SCALE_FACTOR,
-                text.justify,
-                text.linebreak_behavior,
-                text_bounds,
-                &mut font_atlas_set_storage,
-                &mut texture_atlases,
-                &mut images,
-                text_settings.as_ref(),
-                YAxisOrientation::BottomToTop,
-            ) {
-                Err(TextError::NoSuchFont) => {
-                    error!("Missing font (could still be loading)");
-                    queue.insert(entity);
+    // Texts that changed, or are retried or deferred from a previous update, with their attempts
+    let mut pending: Vec<_> = text_query
+        .iter()
+        .filter_map(|(entity, text, bounds, anchor, _)| {
+            let changed = text.is_changed() || bounds.is_changed() || anchor.is_changed();
+
+            match (retries.remove(&entity), changed) {
+                (_, true) => Some((entity, 0)),
+                (Some(attempts), false) => Some((entity, attempts)),
+                (None, false) => None,
+            }
+        })
+        .collect();
+
+    if settings.budget != BillboardTextBudget::Unlimited {
+        priority.sort(&mut pending);
+    }
+
+    let mut report = |entity: Entity, error: TextError| {
+        error!("Failed to lay out billboard text of {entity}: {error}.");
+        text_errors.send(BillboardTextError { entity, error });
+    };
+
+    // Glyphs are shaped in parallel, and then added to the font atlases one text at a time as
+    // the atlases are shared between texts. A time budget is checked again once every thread
+    // shaped a text.
+    let shaped_together = match settings.budget {
+        BillboardTextBudget::Time(_) if settings.serial_layout => 1,
+        BillboardTextBudget::Time(_) => ComputeTaskPool::get().thread_num().max(1),
+        _ => usize::MAX,
+    };
+    let mut laid_out = Vec::new();
+    let mut index = 0;
+    let start = Instant::now();
+
+    loop {
+        let mut to_shape = Vec::new();
+
+        while let Some(&(entity, attempts)) = pending.get(index) {
+            // Only texts that are laid out count, retried and patched ones are cheap
+            if settings
+                .budget
+                .is_spent(laid_out.len() + to_shape.len(), start)
+                || to_shape.len() == shaped_together
+            {
+                break;
+            }
+            index += 1;
+
+            let Ok((entity, text, bounds, anchor, mut billboard_text_handles)) =
+                text_query.get_mut(entity)
+            else {
+                continue;
+            };
+
+            let layout = layouts.remove(&entity);
+
+            // Only the characters of the text changed, so its glyphs may be patched in place
+            if let Some(mut layout) =
+                layout.filter(|_| !bounds.is_changed() && !anchor.is_changed())
+            {
+                if layout.patch(
+                    &text,
+                    &billboard_text_handles,
+                    &fonts,
+                    &font_atlas_set_storage,
+                    &texture_atlases,
+                    &mut meshes,
+                ) {
+                    // The meshes were patched in place, their bounds have to be updated all the
+                    // same
+                    billboard_text_handles.set_changed();
+                    layouts.insert(entity, layout);
+                    stats.patched += 1;
                     continue;
                 }
-                Err(err @ TextError::FailedToAddGlyph(_)) => {
-                    panic!("Fatal error when processing text: {err}.");
+            }
+
+            let font_missing = text
+                .sections
+                .iter()
+                .any(|section| !fonts.contains(&section.style.font));
+
+            let fallback_font = if font_missing {
+                let font_failed = text.sections.iter().any(|section| {
+                    matches!(
+                        asset_server.get_load_state(section.style.font.id()),
+                        Some(LoadState::Failed(_))
+                    )
+                });
+
+                // The font could still be loading
+                if !font_failed && settings.retry.should_retry(attempts) {
+                    retries.insert(entity, attempts + 1);
+                    continue;
                 }
-                Ok(info) => info,
+
+                report(entity, TextError::NoSuchFont);
+
+                match settings
+                    .fallback_font
+                    .as_ref()
+                    .filter(|font| fonts.contains(*font))
+                {
+                    Some(font) => Some(font.clone()),
+                    None => {
+                        billboard_text_handles.clear();
+                        continue;
+                    }
+                }
+            } else {
+                None
+            };
+
+            to_shape.push(TextToShape {
+                entity,
+                attempts,
+                fallback_font,
+                bounds: Vec2::new(
+                    if text.linebreak_behavior == BreakLineOn::NoWrap {
+                        f32::INFINITY
+                    } else {
+                        bounds.size.x
+                    },
+                    bounds.size.y,
+                ),
+                anchor: -(anchor.as_vec() + 0.5),
+            });
+        }
+
+        if to_shape.is_empty() {
+            break;
+        }
+
+        // Shaping only reads the fonts, which are cheap to clone into the brush of every text
+        let texts = &text_query;
+        let fonts = &*fonts;
+        let shaped = par_map(&to_shape, settings.serial_layout, |to_shape| {
+            let (_, text, ..) = texts.get(to_shape.entity).ok()?;
+            Some(ShapedText::new(
+                &text,
+                to_shape.fallback_font.as_ref(),
+                fonts,
+                to_shape.bounds,
+            ))
+        });
+
+        let mut failed = Vec::new();
+
+        for (to_shape, shaped) in to_shape.into_iter().zip(shaped) {
+            let (Ok((entity, text, ..)), Some(shaped)) = (text_query.get(to_shape.entity), shaped)
+            else {
+                continue;
             };
 
-            let text_anchor = -(anchor.as_vec() + 0.5);
-            let alignment_translation = info.logical_size * text_anchor;
-
-            let length = info.glyphs.len();
-            let mut textures = HashMap::new();
-
-            for glyph in &info.glyphs {
-                // TODO: Maybe with clever caching, could be possible to get rid of or_insert_with,
-                // TODO: though I don't know how much of a gain it would be. Just keeping this as a note.
-                let entry = textures
-                    .entry(glyph.atlas_info.texture.clone_weak())
-                    .or_insert_with(|| {
-                        (
-                            Vec::with_capacity(length),
-                            (
-                                texture_atlases
-                                    .get(&glyph.atlas_info.texture_atlas)
-                                    .expect("Atlas should exist"),
-                                glyph.atlas_info.texture.clone_weak(),
-                            ),
-                        )
+            let mut place = |shaped: Result<ShapedText, TextError>| {
+                shaped?.place(
+                    &text.sections,
+                    fonts,
+                    &mut font_atlas_set_storage,
+                    &mut texture_atlases,
+                    &mut images,
+                    &text_settings,
+                )
+            };
+
+            let (info, fallback) = match place(shaped) {
+                Ok(info) => (info, to_shape.fallback_font.is_some()),
+                Err(error) => {
+                    report(entity, error);
+
+                    // Already shaped with the fallback font, or laid out with it right away
+                    let fallback = settings
+                        .fallback_font
+                        .as_ref()
+                        .filter(|_| to_shape.fallback_font.is_none())
+                        .and_then(|font| {
+                            place(ShapedText::new(&text, Some(font), fonts, to_shape.bounds)).ok()
+                        });
+
+                    match fallback {
+                        Some(info) => (info, true),
+                        None => {
+                            failed.push(entity);
+                            continue;
+                        }
+                    }
+                }
+            };
+
+            laid_out.push(LaidOutText {
+                entity,
+                glyphs: info.glyphs,
+                colors: text
+                    .sections
+                    .iter()
+                    .map(|section| section.style.color.to_linear().to_f32_array())
+                    .collect(),
+                alignment_translation: info.logical_size * to_shape.anchor,
+                fallback,
+                attempts: to_shape.attempts,
+            });
+        }
+
+        for entity in failed {
+            if let Ok((_, _, _, _, mut billboard_text_handles)) = text_query.get_mut(entity) {
+                billboard_text_handles.clear();
+            }
+        }
+    }
+
+    // Texts left over by the budget are laid out in the next updates
+    for &(entity, attempts) in &pending[index..] {
+        retries.insert(entity, attempts);
+
+        // Deferred texts can't tell their bounds or anchor changed anymore
+        if let Ok((_, _, bounds, anchor, _)) = text_query.get(entity) {
+            if bounds.is_changed() || anchor.is_changed() {
+                layouts.remove(&entity);
+            }
+        }
+    }
+
+    if laid_out.is_empty() {
+        return;
+    }
+
+    // Meshes only depend on the glyphs of their own text, so they are built in parallel
+    let texture_atlases = &*texture_atlases;
+    let text_meshes = par_map(&laid_out, settings.serial_layout, |text| {
+        build_text_meshes(text, texture_atlases, settings.texture_arrays)
+    });
+
+    // Arrays are copied at most once an update, after all the glyphs were added to the atlases
+    let mut copied_arrays = HashSet::new();
+
+    for (laid_out, text_meshes) in laid_out.into_iter().zip(text_meshes) {
+        let Ok((entity, text, _, _, mut billboard_text_handles)) =
+            text_query.get_mut(laid_out.entity)
+        else {
+            continue;
+        };
+
+        let text_meshes = text_meshes.and_then(|text_meshes| {
+            if text_meshes.layers.is_empty() {
+                return Some((text_meshes, None));
+            }
+
+            let array = atlas_arrays.get(&text_meshes.layers, &mut images, &mut copied_arrays)?;
+            Some((text_meshes, Some(array)))
+        });
+
+        // The atlases of the glyphs were only just added to, if they are gone anyway the text
+        // is laid out again like when its font is loading
+        let Some((text_meshes, array)) = text_meshes else {
+            warn!("Missing font atlas for the billboard text of {entity}.");
+            if settings.retry.should_retry(laid_out.attempts) {
+                retries.insert(entity, laid_out.attempts + 1);
+            } else {
+                billboard_text_handles.clear();
+            }
+            continue;
+        };
+
+        let group_count = text_meshes.groups.len();
+
+        for (group_index, (mesh, mut texture)) in text_meshes.groups.into_iter().enumerate() {
+            if let Some(array) = &array {
+                texture = array.clone();
+            }
+
+            // Overwrite the previous mesh of the group instead of adding a new asset every time
+            // the text changes
+            match billboard_text_handles.get_mut(group_index) {
+                Some(group) => {
+                    meshes.insert(&group.mesh, mesh);
+                    group.image = texture;
+                    stats.reused += 1;
+                }
+                None => {
+                    billboard_text_handles.push(BillboardTextHandleGroup {
+                        mesh: meshes.add(mesh),
+                        image: texture,
                     });
+                    stats.allocated += 1;
+                }
+            }
+        }
+
+        billboard_text_handles.truncate(group_count);
+
+        // Layouts with the fallback font aren't patched, the fonts of the text itself could have
+        // loaded by the next change. Neither are layouts in a texture array, their new glyphs
+        // could be missing from the copy of the atlases.
+        if !laid_out.fallback && text_meshes.layers.is_empty() {
+            layouts.insert(
+                entity,
+                BillboardTextLayout {
+                    sections: text.sections.clone(),
+                    justify: text.justify,
+                    linebreak_behavior: text.linebreak_behavior,
+                    alignment_translation: laid_out.alignment_translation,
+                    glyphs: laid_out.glyphs.into_iter().zip(text_meshes.quads).collect(),
+                },
+            );
+        }
+    }
+
+    if !copied_arrays.is_empty() {
+        atlas_arrays.remove_unused(&images);
+    }
+}
 
-                entry.0.push(glyph.clone());
+/// Whether bevy_text keeps a glyph per subpixel offset of the pen in its atlases (its
+/// `subpixel_glyph_atlas` feature), instead of snapping glyphs to whole pixels.
+fn subpixel_glyph_atlas() -> bool {
+    SubpixelOffset::from(point(0.5, 0.5)) != SubpixelOffset::from(point(0.0, 0.0))
+}
+
+/// Order texts are laid out in when the [`BillboardTextBudget`] is limited: visible texts first,
+/// then the ones nearest to an active camera.
+#[derive(SystemParam)]
+pub struct BillboardTextPriority<'w, 's> {
+    texts: Query<'w, 's, (&'static GlobalTransform, &'static ViewVisibility)>,
+    cameras: Query<'w, 's, (&'static GlobalTransform, &'static Camera)>,
+}
+
+impl BillboardTextPriority<'_, '_> {
+    fn sort<T>(&self, pending: &mut [(Entity, T)]) {
+        let cameras: Vec<_> = self
+            .cameras
+            .iter()
+            .filter(|(_, camera)| camera.is_active)
+            .map(|(transform, _)| transform.translation())
+            .collect();
+
+        pending.sort_by_cached_key(|(entity, _)| {
+            let Ok((transform, visibility)) = self.texts.get(*entity) else {
+                return (true, FloatOrd(f32::INFINITY));
+            };
+
+            let distance = cameras
+                .iter()
+                .map(|camera| camera.distance_squared(transform.translation()))
+                .fold(f32::INFINITY, f32::min);
+
+            (!visibility.get(), FloatOrd(distance))
+        });
+    }
+}
+
+/// A text waiting to be shaped this update.
+struct TextToShape {
+    entity: Entity,
+    attempts: u32,
+    /// Font every section is shaped with when its own fonts aren't loaded.
+    fallback_font: Option<Handle<Font>>,
+    bounds: Vec2,
+    /// Anchor of the text, as a fraction of its size to move it by.
+    anchor: Vec2,
+}
+
+/// Glyphs of a text positioned by `glyph_brush_layout` but not yet added to the font atlases,
+/// which is the part of [`TextPipeline::queue_text`](bevy::text::TextPipeline::queue_text) that
+/// can run in parallel. It repeats what `queue_text` does privately, the text usage tests compare
+/// its glyphs with the ones of `queue_text` for every justification and line break.
+struct ShapedText {
+    /// Font of every section, in order.
+    brush: GlyphBrush,
+    glyphs: Vec<SectionGlyph>,
+    size: Vec2,
+    h_anchor: f32,
+}
+
+impl ShapedText {
+    fn new(
+        text: &Text,
+        fallback_font: Option<&Handle<Font>>,
+        fonts: &Assets<Font>,
+        bounds: Vec2,
+    ) -> Result<Self, TextError> {
+        let mut brush = GlyphBrush::default();
+        let mut scaled_fonts = Vec::with_capacity(text.sections.len());
+
+        for section in &text.sections {
+            let handle = fallback_font.unwrap_or(&section.style.font);
+            let font = fonts.get(handle).ok_or(TextError::NoSuchFont)?;
+            brush.add_font(handle.id(), font.font.clone());
+            scaled_fonts.push(font.font.as_scaled(section.style.font_size));
+        }
+
+        let glyphs = brush.compute_glyphs(
+            &section_texts(&text.sections),
+            bounds,
+            text.justify,
+            text.linebreak_behavior,
+        )?;
+
+        // See `TextPipeline::queue_text` and `compute_text_bounds`
+        let size = glyphs
+            .iter()
+            .map(|glyph| {
+                let scaled_font = scaled_fonts[glyph.section_index];
+                let position = glyph.glyph.position;
+                Rect::new(
+                    position.x,
+                    0.0,
+                    position.x + scaled_font.h_advance(glyph.glyph.id),
+                    position.y - scaled_font.descent(),
+                )
+            })
+            .reduce(|bounds, glyph| bounds.union(glyph))
+            .map_or(Vec2::ZERO, |bounds| bounds.size());
+
+        let h_limit = if bounds.x.is_finite() {
+            bounds.x
+        } else {
+            size.x
+        };
+        let h_anchor = match text.justify {
+            JustifyText::Left => 0.0,
+            JustifyText::Center => h_limit * 0.5,
+            JustifyText::Right => h_limit,
+        }
+        .floor();
+
+        Ok(Self {
+            brush,
+            glyphs,
+            size,
+            h_anchor,
+        })
+    }
+
+    /// Adds the glyphs to the font atlases, placing them where
+    /// [`TextPipeline::queue_text`](bevy::text::TextPipeline::queue_text) would.
+    fn place(
+        self,
+        sections: &[TextSection],
+        fonts: &Assets<Font>,
+        font_atlas_sets: &mut FontAtlasSets,
+        texture_atlases: &mut Assets<TextureAtlasLayout>,
+        images: &mut Assets<Image>,
+        text_settings: &TextSettings,
+    ) -> Result<TextLayoutInfo, TextError> {
+        let glyphs = self.brush.process_glyphs(
+            self.glyphs,
+            &section_texts(sections),
+            font_atlas_sets,
+            fonts,
+            texture_atlases,
+            images,
+            text_settings,
+            YAxisOrientation::BottomToTop,
+            self.h_anchor,
+        )?;
+
+        Ok(TextLayoutInfo {
+            glyphs,
+            logical_size: self.size,
+        })
+    }
+}
+
+/// Sections to lay out with a [`GlyphBrush`] holding the font of every section in order, at a
+/// scale factor of 1.
+fn section_texts(sections: &[TextSection]) -> Vec<SectionText<'_>> {
+    sections
+        .iter()
+        .enumerate()
+        .map(|(index, section)| SectionText {
+            text: &section.value,
+            scale: PxScale::from(section.style.font_size),
+            font_id: FontId(index),
+        })
+        .collect()
+}
+
+/// Maps `items` on the compute task pool, or on this thread with
+/// [`BillboardTextSettings::serial_layout`].
+fn par_map<T: Sync, R: Send + 'static>(
+    items: &[T],
+    serial: bool,
+    f: impl Fn(&T) -> R + Send + Sync,
+) -> Vec<R> {
+    if serial {
+        return items.iter().map(f).collect();
+    }
+
+    items
+        .par_splat_map(ComputeTaskPool::get(), None, |_, items| {
+            items.iter().map(&f).collect::<Vec<_>>()
+        })
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+/// A text whose glyphs were laid out this update, waiting for its meshes.
+struct LaidOutText {
+    entity: Entity,
+    glyphs: Vec<PositionedGlyph>,
+    /// Linear color of every section.
+    colors: Vec<[f32; 4]>,
+    alignment_translation: Vec2,
+    fallback: bool,
+    attempts: u32,
+}
+
+struct TextMeshes {
+    /// A mesh per font atlas texture the glyphs are in.
+    groups: Vec<(Mesh, Handle<Image>)>,
+    /// Group and quad of every glyph.
+    quads: Vec<(usize, usize)>,
+    /// Font atlas textures of the layers of the texture array the single group is drawn with,
+    /// empty when there is a group per atlas instead.
+    layers: Vec<Handle<Image>>,
+}
+
+/// Meshes of a laid out text, `None` if the atlas of one of its glyphs is missing.
+fn build_text_meshes(
+    text: &LaidOutText,
+    texture_atlases: &Assets<TextureAtlasLayout>,
+    texture_arrays: bool,
+) -> Option<TextMeshes> {
+    let length = text.glyphs.len();
+    let mut textures = Vec::new();
+    let mut quads = Vec::with_capacity(length);
+
+    for glyph in &text.glyphs {
+        // Groups are kept in the order their atlas first appears in, so the meshes of a
+        // text always come out (and draw) in the same order.
+        let index = match textures
+            .iter()
+            .position(|(_, (_, texture))| *texture == glyph.atlas_info.texture)
+        {
+            Some(index) => index,
+            None => {
+                textures.push((
+                    Vec::with_capacity(length),
+                    (
+                        texture_atlases.get(&glyph.atlas_info.texture_atlas)?,
+                        glyph.atlas_info.texture.clone_weak(),
+                    ),
+                ));
+                textures.len() - 1
             }
+        };
+
+        textures[index].0.push(glyph);
+        quads.push((index, textures[index].0.len() - 1));
+    }
+
+    // Layers of a texture array all have the same size
+    let layered = texture_arrays
+        && textures.len() > 1
+        && textures
+            .iter()
+            .all(|(_, (atlas, _))| atlas.size == textures[0].1 .0.size);
+
+    if !layered {
+        let groups = textures
+            .into_iter()
+            .map(|(glyphs, (atlas, texture))| {
+                let glyphs = glyphs.into_iter().map(|glyph| (glyph, atlas, None));
+                (glyph_mesh(text, glyphs, length), texture)
+            })
+            .collect();
+
+        return Some(TextMeshes {
+            groups,
+            quads,
+            layers: Vec::new(),
+        });
+    }
+
+    let mut offsets = Vec::with_capacity(textures.len());
+    let mut offset = 0;
+    for (glyphs, _) in &textures {
+        offsets.push(offset);
+        offset += glyphs.len();
+    }
+
+    let glyphs = textures
+        .iter()
+        .enumerate()
+        .flat_map(|(layer, (glyphs, (atlas, _)))| {
+            glyphs
+                .iter()
+                .map(move |glyph| (*glyph, *atlas, Some(layer as u32)))
+        });
+    let mesh = glyph_mesh(text, glyphs, length);
+
+    Some(TextMeshes {
+        groups: vec![(mesh, Handle::default())],
+        quads: quads
+            .into_iter()
+            .map(|(group, quad)| (0, offsets[group] + quad))
+            .collect(),
+        layers: textures
+            .into_iter()
+            .map(|(_, (_, texture))| texture)
+            .collect(),
+    })
+}
 
-            billboard_text_handles.clear();
+/// Mesh of the quads of `glyphs`, with the texture array layer of each one if it has any.
+fn glyph_mesh<'a>(
+    text: &LaidOutText,
+    glyphs: impl Iterator<Item = (&'a PositionedGlyph, &'a TextureAtlasLayout, Option<u32>)>,
+    capacity: usize,
+) -> Mesh {
+    let mut positions = Vec::with_capacity(capacity * 4);
+    let mut uvs = Vec::with_capacity(capacity * 4);
+    let mut colors = Vec::with_capacity(capacity * 4);
+    let mut layers = Vec::new();
+    let mut indices = Vec::with_capacity(capacity * 6);
 
-            for (glyphs, (atlas, texture)) in textures.into_values() {
-                let mut positions = Vec::with_capacity(info.glyphs.len() * 4);
-                let mut uvs = Vec::with_capacity(info.glyphs.len() * 4);
-                let mut colors = Vec::with_capacity(info.glyphs.len() * 4);
-                let mut indices = Vec::with_capacity(info.glyphs.len() * 6);
+    for (glyph, atlas, layer) in glyphs {
+        let index = positions.len() as u32;
+        let (glyph_positions, glyph_uvs) = glyph_quad(glyph, atlas, text.alignment_translation);
 
-                let mut color = palettes::css::WHITE.to_f32_array();
-                let mut current_section = usize::MAX;
+        positions.extend(glyph_positions);
+        uvs.extend(glyph_uvs);
 
-                for PositionedGlyph {
-                    position,
-                    size,
-                    atlas_info,
-                    section_index,
-                    ..
-                } in glyphs
+        let color = text.colors[glyph.section_index];
+        colors.extend([color, color, color, color]);
+
+        if let Some(layer) = layer {
+            layers.extend([layer, layer, layer, layer]);
+        }
+
+        indices.extend([index, index + 2, index + 1, index, index + 3, index + 2]);
+    }
+
+    let mut mesh = Mesh::new(
+        PrimitiveTopology::TriangleList,
+        RenderAssetUsages::default(),
+    );
+
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+
+    if !layers.is_empty() {
+        mesh.insert_attribute(ATTRIBUTE_ATLAS_LAYER, layers);
+    }
+
+    mesh.insert_indices(Indices::U32(indices));
+
+    mesh
+}
+
+/// Texture arrays of the font atlases texts drawn with [`BillboardTextSettings::texture_arrays`]
+/// span. Texts hold the arrays, these are only weak handles to share them between texts.
+#[derive(Default)]
+pub struct BillboardTextAtlasArrays {
+    arrays: HashMap<Vec<AssetId<Image>>, Handle<Image>>,
+}
+
+impl BillboardTextAtlasArrays {
+    /// Texture array of the `atlases`, copying them into it unless that was done already this
+    /// update. `None` if none of the atlases exist.
+    fn get(
+        &mut self,
+        atlases: &[Handle<Image>],
+        images: &mut Assets<Image>,
+        copied: &mut HashSet<AssetId<Image>>,
+    ) -> Option<Handle<Image>> {
+        let key: Vec<_> = atlases.iter().map(Handle::id).collect();
+
+        match self
+            .arrays
+            .get(&key)
+            .and_then(|array| images.get_strong_handle(array.id()))
+        {
+            Some(array) => {
+                if !copied.contains(&array.id()) {
+                    let image = stack_atlases(atlases, images)?;
+                    images.insert(&array, image);
+                    copied.insert(array.id());
+                }
+
+                Some(array)
+            }
+            None => {
+                let array = images.add(stack_atlases(atlases, images)?);
+                copied.insert(array.id());
+                self.arrays.insert(key, array.clone_weak());
+                Some(array)
+            }
+        }
+    }
+
+    fn remove_unused(&mut self, images: &Assets<Image>) {
+        self.arrays.retain(|_, array| images.contains(array.id()));
+    }
+}
+
+fn stack_atlases(atlases: &[Handle<Image>], images: &Assets<Image>) -> Option<Image> {
+    let layers: Vec<_> = atlases.iter().map(|atlas| images.get(atlas)).collect();
+    let first = layers.iter().flatten().next()?;
+    let layer_size = first.data.len();
+
+    let mut data = Vec::with_capacity(layer_size * layers.len());
+    for layer in &layers {
+        match layer {
+            Some(layer) => data.extend_from_slice(&layer.data),
+            // Keeps the layers of the other atlases in place
+            None => data.resize(data.len() + layer_size, 0),
+        }
+    }
+
+    let mut image = Image::new(
+        Extent3d {
+            depth_or_array_layers: layers.len() as u32,
+            ..first.texture_descriptor.size
+        },
+        TextureDimension::D2,
+        data,
+        first.texture_descriptor.format,
+        RenderAssetUsages::default(),
+    );
+    image.texture_view_descriptor = Some(TextureViewDescriptor {
+        dimension: Some(TextureViewDimension::D2Array),
+        ..default()
+    });
+
+    Some(image)
+}
+
+/// Vertex positions and UVs of a glyph quad, in the vertex order of the text meshes.
+fn glyph_quad(
+    glyph: &PositionedGlyph,
+    atlas: &TextureAtlasLayout,
+    translation: Vec2,
+) -> ([[f32; 3]; 4], [[f32; 2]; 4]) {
+    let position = glyph.position + translation;
+
+    let half_size = glyph.size / 2.0;
+    let top_left = position - half_size;
+    let bottom_right = position + half_size;
+
+    let URect { min, max } = atlas.textures[glyph.atlas_info.glyph_index];
+    let atlas_size = atlas.size.as_vec2();
+    let min = min.as_vec2() / atlas_size;
+    let max = max.as_vec2() / atlas_size;
+
+    (
+        [
+            [top_left.x, top_left.y, 0.0],
+            [top_left.x, bottom_right.y, 0.0],
+            [bottom_right.x, bottom_right.y, 0.0],
+            [bottom_right.x, top_left.y, 0.0],
+        ],
+        [
+            [min.x, max.y],
+            [min.x, min.y],
+            [max.x, min.y],
+            [max.x, max.y],
+        ],
+    )
+}
+
+/// A text as it was last laid out, so glyphs can be swapped in place when only some of its
+/// characters change (counters, timers, damage numbers).
+pub struct BillboardTextLayout {
+    sections: Vec<TextSection>,
+    justify: JustifyText,
+    linebreak_behavior: BreakLineOn,
+    alignment_translation: Vec2,
+    /// Glyphs with the group and quad of the mesh they are in.
+    glyphs: Vec<(PositionedGlyph, (usize, usize))>,
+}
+
+impl BillboardTextLayout {
+    /// Swaps the glyphs of the changed characters in the text meshes. Only done when no other
+    /// glyph would move, i.e. the sections and styles are the same and every changed character
+    /// is an ASCII letter or digit with the same advance and kerning as the one it replaces
+    /// (e.g. digits of fonts with tabular figures). The new glyphs also need to be in the same
+    /// font atlas already. Returns `false` when the text needs a full relayout instead.
+    fn patch(
+        &mut self,
+        text: &Text,
+        handles: &BillboardTextHandles,
+        fonts: &Assets<Font>,
+        font_atlas_sets: &FontAtlasSets,
+        texture_atlases: &Assets<TextureAtlasLayout>,
+        meshes: &mut Assets<Mesh>,
+    ) -> bool {
+        let Some(swaps) = self.swapped_glyphs(text, fonts, font_atlas_sets, texture_atlases) else {
+            return false;
+        };
+
+        for (glyph_index, glyph) in swaps {
+            let (group, quad) = self.glyphs[glyph_index].1;
+
+            let Some(atlas) = texture_atlases.get(&glyph.atlas_info.texture_atlas) else {
+                return false;
+            };
+            let Some(mesh) = handles
+                .get(group)
+                .and_then(|group| meshes.get_mut(&group.mesh))
+            else {
+                return false;
+            };
+
+            let (positions, uvs) = glyph_quad(&glyph, atlas, self.alignment_translation);
+            let vertices = quad * 4..quad * 4 + 4;
+
+            if let Some(VertexAttributeValues::Float32x3(mesh_positions)) =
+                mesh.attribute_mut(Mesh::ATTRIBUTE_POSITION)
+            {
+                mesh_positions[vertices.clone()].copy_from_slice(&positions);
+            }
+            if let Some(VertexAttributeValues::Float32x2(mesh_uvs)) =
+                mesh.attribute_mut(Mesh::ATTRIBUTE_UV_0)
+            {
+                mesh_uvs[vertices].copy_from_slice(&uvs);
+            }
+
+            self.glyphs[glyph_index].0 = glyph;
+        }
+
+        self.sections.clone_from(&text.sections);
+
+        true
+    }
+
+    /// The new glyphs of the changed characters, by index into `glyphs`, placed the way
+    /// [`ShapedText::place`] would have placed them.
+    fn swapped_glyphs(
+        &self,
+        text: &Text,
+        fonts: &Assets<Font>,
+        font_atlas_sets: &FontAtlasSets,
+        texture_atlases: &Assets<TextureAtlasLayout>,
+    ) -> Option<Vec<(usize, PositionedGlyph)>> {
+        // With subpixel glyph atlases glyphs are outlined at their fractional pen position, which
+        // the layout doesn't keep
+        if subpixel_glyph_atlas()
+            || text.justify != self.justify
+            || text.linebreak_behavior != self.linebreak_behavior
+            || text.sections.len() != self.sections.len()
+        {
+            return None;
+        }
+
+        let mut swaps = Vec::new();
+
+        for (section_index, (section, previous)) in
+            text.sections.iter().zip(&self.sections).enumerate()
+        {
+            if section.style.font != previous.style.font
+                || section.style.font_size != previous.style.font_size
+                || section.style.color != previous.style.color
+                || section.value.len() != previous.value.len()
+            {
+                return None;
+            }
+
+            if section.value == previous.value {
+                continue;
+            }
+
+            let font = fonts.get(&section.style.font)?;
+            let font_size = section.style.font_size;
+            let scaled_font = font.font.as_scaled(font_size);
+            let font_atlases = font_atlas_sets
+                .get(&section.style.font)?
+                .iter()
+                .find_map(|(size, atlases)| (size.0 == font_size).then_some(atlases))?;
+
+            let (new, old) = (section.value.as_bytes(), previous.value.as_bytes());
+
+            for byte_index in 0..new.len() {
+                if new[byte_index] == old[byte_index] {
+                    continue;
+                }
+
+                // Characters next to another section could be kerned against it
+                let is_last = byte_index + 1 == new.len();
+                if !new[byte_index].is_ascii_alphanumeric()
+                    || !old[byte_index].is_ascii_alphanumeric()
+                    || (byte_index == 0 && section_index > 0)
+                    || (is_last && section_index + 1 < text.sections.len())
                 {
-                    let index = positions.len() as u32;
-                    let position = position + alignment_translation;
-
-                    let half_size = size / 2.0;
-                    let top_left = position - half_size;
-                    let bottom_right = position + half_size;
-
-                    positions.extend([
-                        [top_left.x, top_left.y, 0.0],
-                        [top_left.x, bottom_right.y, 0.0],
-                        [bottom_right.x, bottom_right.y, 0.0],
-                        [bottom_right.x, top_left.y, 0.0],
-                    ]);
-
-                    let URect { min, max } = atlas.textures[atlas_info.glyph_index];
-                    let atlas_size = atlas.size.as_vec2();
-                    let min = min.as_vec2() / atlas_size;
-                    let max = max.as_vec2() / atlas_size;
-
-                    uvs.extend([
-                        [min.x, max.y],
-                        [min.x, min.y],
-                        [max.x, min.y],
-                        [max.x, max.y],
-                    ]);
-
-                    if section_index != current_section {
-                        color = text.sections[section_index]
-                            .style
-                            .color
-                            .to_linear()
-                            .to_f32_array();
-                        current_section = section_index;
-                    }
+                    return None;
+                }
 
-                    colors.extend([color, color, color, color]);
+                let new_id = scaled_font.glyph_id(new[byte_index] as char);
+                let old_id = scaled_font.glyph_id(old[byte_index] as char);
 
-                    indices.extend([index, index + 2, index + 1, index, index + 3, index + 2]);
+                let kerning = |value: &str, id: GlyphId| {
+                    let before = value[..byte_index].chars().next_back();
+                    let after = value[byte_index + 1..].chars().next();
+                    (
+                        before.map(|c| scaled_font.kern(scaled_font.glyph_id(c), id)),
+                        after.map(|c| scaled_font.kern(id, scaled_font.glyph_id(c))),
+                    )
+                };
+
+                if scaled_font.h_advance(new_id) != scaled_font.h_advance(old_id)
+                    || kerning(&section.value, new_id) != kerning(&previous.value, old_id)
+                {
+                    return None;
                 }
 
-                let mut mesh = Mesh::new(
-                    PrimitiveTopology::TriangleList,
-                    RenderAssetUsages::default(),
-                );
+                let (glyph_index, (old_glyph, _)) =
+                    self.glyphs.iter().enumerate().find(|(_, (glyph, _))| {
+                        glyph.section_index == section_index && glyph.byte_index == byte_index
+                    })?;
 
-                mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
-                mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
-                mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+                // Without subpixel glyph atlases, `GlyphBrush::process_glyphs` outlines glyphs
+                // with the pen at x 0 and on a whole pixel y, then moves them by the pen x
+                // rounded to a whole pixel. Both glyphs share the pen, so the offset between
+                // them is the same as with the pen at the origin
+                let pen = point(0.0, 0.0);
 
-                mesh.insert_indices(Indices::U32(indices));
+                let (glyph_index_in_atlas, atlas) = font_atlases.iter().find_map(|atlas| {
+                    atlas
+                        .get_glyph_index(new_id, pen.into())
+                        .map(|index| (index, atlas))
+                })?;
 
-                billboard_text_handles.push(BillboardTextHandleGroup {
-                    mesh: meshes.add(mesh),
-                    image: texture,
-                });
+                if atlas.texture != old_glyph.atlas_info.texture {
+                    return None;
+                }
+
+                let size = texture_atlases
+                    .get(&atlas.texture_atlas)?
+                    .textures
+                    .get(glyph_index_in_atlas)?
+                    .size()
+                    .as_vec2();
+
+                let bounds = |id: GlyphId| {
+                    font.font
+                        .outline_glyph(id.with_scale_and_position(font_size, pen))
+                        .map(|outline| outline.px_bounds())
+                };
+                let (new_bounds, old_bounds) = (bounds(new_id)?, bounds(old_id)?);
+
+                // See `GlyphBrush::process_glyphs`
+                let offset = Vec2::new(
+                    (new_bounds.min.x + size.x / 2.0) - (old_bounds.min.x + old_glyph.size.x / 2.0),
+                    (old_bounds.max.y - old_glyph.size.y / 2.0) - (new_bounds.max.y - size.y / 2.0),
+                );
+
+                swaps.push((
+                    glyph_index,
+                    PositionedGlyph {
+                        position: old_glyph.position + offset,
+                        size,
+                        atlas_info: GlyphAtlasInfo {
+                            texture_atlas: atlas.texture_atlas.clone_weak(),
+                            texture: atlas.texture.clone_weak(),
+                            glyph_index: glyph_index_in_atlas,
+                        },
+                        section_index,
+                        byte_index,
+                    },
+                ));
             }
         }
+
+        Some(swaps)
     }
 }
 
-// TODO: Use EntityHash with EntityHashMap in 0.12 for extracted.
-// The related code is removed, but this todo is helpful for future.
+/// Render entities drawing the atlas groups of a text billboard after the first one, which the
+/// billboard entity draws itself.
+#[derive(Component, Default)]
+pub struct RenderBillboardTextGroups(pub SmallVec<[Entity; 1]>);
 
-#[derive(Component)]
+#[derive(Clone, Copy, Component, PartialEq)]
 pub struct RenderBillboard {
     pub depth: BillboardDepth,
     pub lock_axis: Option<BillboardLockAxis>,
+    pub soft_edge: Option<BillboardSoftEdge>,
+    pub sort_bias: f32,
 }
@@ -1,36 +1,47 @@
 use bevy::{
     ecs::{
+        change_detection::{DetectChanges, Ref},
         entity::Entity,
-        system::{Commands, Local, Query},
+        system::{Commands, Local, Query, Res},
     },
     render::{view::ViewVisibility, Extract},
     transform::components::{GlobalTransform, Transform},
 };
 
 use crate::{
-    pipeline::{RenderBillboardImage, RenderBillboardMesh},
+    declutter::BillboardDeclutterState,
+    gpu_culling::{ExtractedBillboardGpuInstances, GpuCulled},
+    pipeline::{BillboardUniform, RenderBillboardImage, RenderBillboardMesh},
     text::RenderBillboard,
-    utils::calculate_billboard_uniform,
-    BillboardDepth, BillboardLockAxis, BillboardMeshHandle, BillboardTextureHandle,
+    utils::{calculate_billboard_uniform, ExtractedBillboards},
+    BillboardDepth, BillboardLockAxis, BillboardMeshHandle, BillboardSoftEdge, BillboardSortBias,
+    BillboardTextureHandle,
 };
 
+type BillboardTextureQuery = (
+    Entity,
+    &'static ViewVisibility,
+    Ref<'static, GlobalTransform>,
+    Ref<'static, Transform>,
+    &'static BillboardMeshHandle,
+    &'static BillboardTextureHandle,
+    &'static BillboardDepth,
+    Option<&'static BillboardLockAxis>,
+    Option<&'static BillboardSoftEdge>,
+    Option<&'static BillboardSortBias>,
+    Option<Ref<'static, BillboardDeclutterState>>,
+);
+
 pub fn extract_billboard_texture(
     mut commands: Commands,
     mut previous_len: Local<usize>,
-    billboard_text_query: Extract<
-        Query<(
-            Entity,
-            &ViewVisibility,
-            &GlobalTransform,
-            &Transform,
-            &BillboardMeshHandle,
-            &BillboardTextureHandle,
-            &BillboardDepth,
-            Option<&BillboardLockAxis>,
-        )>,
-    >,
+    mut extracted: Local<ExtractedBillboards<(BillboardUniform, RenderBillboard)>>,
+    gpu_instances: Option<Res<ExtractedBillboardGpuInstances>>,
+    billboard_text_query: Extract<Query<BillboardTextureQuery>>,
+    gpu_culled_query: Extract<Query<(), GpuCulled>>,
 ) {
     let mut batch = Vec::with_capacity(*previous_len);
+    let gpu_culling = gpu_instances.is_some_and(|gpu_instances| gpu_instances.supported);
 
     for (
         entity,
@@ -41,13 +52,47 @@ pub fn extract_billboard_texture(
         billboard_texture,
         &depth,
         lock_axis,
+        soft_edge,
+        sort_bias,
+        declutter,
     ) in &billboard_text_query
     {
-        if !visibility.get() {
+        // Extracted by `extract_billboard_gpu_instances` instead
+        if gpu_culling && gpu_culled_query.contains(entity) {
             continue;
         }
 
-        let uniform = calculate_billboard_uniform(global_transform, transform, lock_axis);
+        let billboard = RenderBillboard {
+            depth,
+            lock_axis: lock_axis.copied(),
+            soft_edge: soft_edge.copied(),
+            sort_bias: sort_bias.map_or(0.0, |bias| bias.0),
+        };
+        let &(uniform, billboard) = extracted.get_or_extract(
+            entity,
+            |(_, extracted)| {
+                global_transform.is_changed()
+                    || transform.is_changed()
+                    || declutter
+                        .as_ref()
+                        .is_some_and(|declutter| declutter.is_changed())
+                    || *extracted != billboard
+            },
+            || {
+                let uniform = calculate_billboard_uniform(
+                    &global_transform,
+                    &transform,
+                    lock_axis,
+                    soft_edge,
+                    declutter.as_deref(),
+                );
+                (uniform, billboard)
+            },
+        );
+
+        if !visibility.get() {
+            continue;
+        }
 
         batch.push((
             entity,
@@ -59,14 +104,12 @@ pub fn extract_billboard_texture(
                 RenderBillboardImage {
                     id: billboard_texture.0.id(),
                 },
-                RenderBillboard {
-                    depth,
-                    lock_axis: lock_axis.copied(),
-                },
+                billboard,
             ),
         ));
     }
 
+    extracted.finish_frame();
     *previous_len = batch.len();
     commands.insert_or_spawn_batch(batch);
 }
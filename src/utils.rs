@@ -1,9 +1,14 @@
 use bevy::{
+    ecs::entity::{Entity, EntityHashMap},
     math::Mat4,
     transform::components::{GlobalTransform, Transform},
+    utils::hashbrown::hash_map::Entry,
 };
 
-use crate::{pipeline::BillboardUniform, BillboardLockAxis};
+use crate::{
+    declutter::BillboardDeclutterState, pipeline::BillboardUniform, BillboardLockAxis,
+    BillboardSoftEdge,
+};
 
 // TODO: Maybe add scale as uniform to shader and do this in shader?
 pub fn compute_matrix_without_rotation(
@@ -23,12 +28,68 @@ pub fn calculate_billboard_uniform(
     global_transform: &GlobalTransform,
     transform: &Transform,
     lock_axis: Option<&BillboardLockAxis>,
+    soft_edge: Option<&BillboardSoftEdge>,
+    declutter: Option<&BillboardDeclutterState>,
 ) -> BillboardUniform {
-    let transform = if lock_axis.is_some() {
+    let mut transform = if lock_axis.is_some() {
         global_transform.compute_matrix()
     } else {
         compute_matrix_without_rotation(global_transform, transform)
     };
 
-    BillboardUniform { transform }
+    if let Some(declutter) = declutter {
+        transform.w_axis += declutter.offset.extend(0.0);
+    }
+
+    BillboardUniform {
+        transform,
+        soft_edge: soft_edge.map_or(0.0, |soft_edge| soft_edge.0),
+        alpha: declutter.map_or(1.0, |declutter| declutter.alpha),
+    }
+}
+
+/// Render components of billboards from earlier extractions. The render world is cleared every
+/// frame, so they are spawned again, but only recomputed for the billboards that changed.
+pub struct ExtractedBillboards<T> {
+    frame: u32,
+    values: EntityHashMap<(u32, T)>,
+}
+
+impl<T> Default for ExtractedBillboards<T> {
+    fn default() -> Self {
+        Self {
+            frame: 0,
+            values: EntityHashMap::default(),
+        }
+    }
+}
+
+impl<T> ExtractedBillboards<T> {
+    /// Returns the render components of `entity` from an earlier extraction, or extracts them
+    /// if there are none yet or `changed` says they are outdated.
+    pub fn get_or_extract(
+        &mut self,
+        entity: Entity,
+        changed: impl FnOnce(&T) -> bool,
+        extract: impl FnOnce() -> T,
+    ) -> &T {
+        match self.values.entry(entity) {
+            Entry::Occupied(entry) => {
+                let (seen, value) = entry.into_mut();
+                *seen = self.frame;
+                if changed(value) {
+                    *value = extract();
+                }
+                value
+            }
+            Entry::Vacant(entry) => &entry.insert((self.frame, extract())).1,
+        }
+    }
+
+    /// Forgets the billboards that weren't extracted this frame, e.g. despawned ones.
+    pub fn finish_frame(&mut self) {
+        let frame = self.frame;
+        self.values.retain(|_, (seen, _)| *seen == frame);
+        self.frame = frame.wrapping_add(1);
+    }
 }
@@ -0,0 +1,220 @@
+use crate::declutter::BillboardDeclutterState;
+use crate::gpu_culling::{GpuCulled, GpuCullingSupported};
+use crate::instances::BillboardInstances;
+use crate::text::BillboardTextHandles;
+use crate::{Billboard, BillboardLockAxis, BillboardMeshHandle};
+use bevy::ecs::entity::EntityHashSet;
+use bevy::prelude::*;
+use bevy::render::mesh::VertexAttributeValues;
+use bevy::render::primitives::Aabb;
+use bevy::render::view::{NoFrustumCulling, VisibleEntities};
+use bevy::utils::HashSet;
+
+/// Restricts the cameras a billboard is rendered to, for cases `RenderLayers` can't express.
+/// Billboards without it are rendered to every camera sharing a render layer with them.
+#[derive(Clone, Component, Debug, Default, Reflect, Deref, DerefMut)]
+#[reflect(Component)]
+pub struct BillboardCameras(pub Vec<Entity>);
+
+/// Removes billboards from the views their [`BillboardCameras`] leave out, and hides the ones
+/// left in no view, as `check_visibility` already marked them visible.
+pub fn filter_billboard_cameras(
+    mut views: Query<(Entity, &mut VisibleEntities)>,
+    mut billboards: Query<(Entity, &BillboardCameras, &mut ViewVisibility), With<Billboard>>,
+    mut seen: Local<EntityHashSet>,
+) {
+    if billboards.is_empty() {
+        return;
+    }
+
+    seen.clear();
+    for (view, mut visible_entities) in &mut views {
+        visible_entities
+            .get_mut::<With<Billboard>>()
+            .retain(|entity| {
+                let Ok((_, cameras, _)) = billboards.get(*entity) else {
+                    return true;
+                };
+                let allowed = cameras.contains(&view);
+                if allowed {
+                    seen.insert(*entity);
+                }
+                allowed
+            });
+    }
+
+    for (entity, _, mut view_visibility) in &mut billboards {
+        if view_visibility.get() && !seen.contains(&entity) {
+            *view_visibility = ViewVisibility::HIDDEN;
+        }
+    }
+}
+
+/// Distance of the farthest vertex of a mesh from its origin.
+pub(crate) fn mesh_radius(mesh: &Mesh) -> Option<f32> {
+    let Some(VertexAttributeValues::Float32x3(positions)) =
+        mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+    else {
+        return None;
+    };
+
+    // Billboards turn to face the camera, so any orientation of the mesh has to fit
+    Some(
+        positions
+            .iter()
+            .map(|&position| Vec3::from(position).length())
+            .fold(0.0, f32::max),
+    )
+}
+
+type BillboardBoundsQuery = (
+    Entity,
+    Ref<'static, GlobalTransform>,
+    Ref<'static, Transform>,
+    Option<Ref<'static, BillboardMeshHandle>>,
+    Option<Ref<'static, BillboardTextHandles>>,
+    Option<Ref<'static, BillboardInstances>>,
+    Option<Ref<'static, BillboardLockAxis>>,
+    Option<Ref<'static, BillboardDeclutterState>>,
+    Has<Aabb>,
+);
+
+/// Gives billboards an [`Aabb`] so they are frustum culled. The box holds the billboard in any
+/// orientation it can turn to face a camera in, so it doesn't depend on the cameras.
+///
+/// `check_visibility` scales the box by the global transform, while billboards without a locked
+/// axis are rendered with their local scale, so the box is sized for whichever is larger, and
+/// grown by how far decluttering moved the billboard. Billboards with [`NoFrustumCulling`] are
+/// left alone, and so are billboards culled on the GPU, which are left without a box so that
+/// `check_visibility` doesn't cull them as well.
+pub fn calculate_billboard_bounds(
+    mut commands: Commands,
+    mut mesh_events: EventReader<AssetEvent<Mesh>>,
+    meshes: Res<Assets<Mesh>>,
+    gpu_culling: Option<Res<GpuCullingSupported>>,
+    billboards: Query<BillboardBoundsQuery, (With<Billboard>, Without<NoFrustumCulling>)>,
+    gpu_culled: Query<(), GpuCulled>,
+) {
+    let gpu_culling = gpu_culling.is_some_and(|supported| supported.0);
+
+    let changed_meshes: HashSet<_> = mesh_events
+        .read()
+        .filter_map(|event| match event {
+            AssetEvent::Added { id } | AssetEvent::Modified { id } => Some(*id),
+            _ => None,
+        })
+        .collect();
+
+    for (
+        entity,
+        global_transform,
+        transform,
+        mesh,
+        text,
+        instances,
+        lock_axis,
+        declutter,
+        has_aabb,
+    ) in &billboards
+    {
+        if gpu_culling && gpu_culled.contains(entity) {
+            if has_aabb {
+                commands.entity(entity).remove::<Aabb>();
+            }
+            continue;
+        }
+
+        // Billboards without a box yet, or any longer, e.g. no longer culled on the GPU
+        let placement_changed = !has_aabb
+            || global_transform.is_changed()
+            || transform.is_changed()
+            || lock_axis.as_ref().is_some_and(|lock| lock.is_changed())
+            || declutter.as_ref().is_some_and(|state| state.is_changed());
+
+        let aabb = if let Some(instances) = instances {
+            if !placement_changed && !instances.is_changed() {
+                continue;
+            }
+            let lock_rotation = lock_axis.as_ref().is_some_and(|lock| lock.rotation);
+            instances_aabb(&instances, lock_rotation)
+        } else if let Some(text) = text {
+            // Relaid out and patched texts mark their handles changed
+            let changed = text.is_changed()
+                || text
+                    .iter()
+                    .any(|group| changed_meshes.contains(&group.mesh().id()));
+            if !placement_changed && !changed {
+                continue;
+            }
+            let radius = text
+                .iter()
+                .filter_map(|group| meshes.get(group.mesh()).and_then(mesh_radius))
+                .reduce(f32::max);
+            radius.map(radius_aabb)
+        } else if let Some(mesh) = mesh {
+            if !placement_changed && !mesh.is_changed() && !changed_meshes.contains(&mesh.0.id()) {
+                continue;
+            }
+            meshes.get(&mesh.0).and_then(mesh_radius).map(radius_aabb)
+        } else {
+            continue;
+        };
+
+        let Some(aabb) = aabb else {
+            continue;
+        };
+
+        let global_scale = global_transform
+            .compute_transform()
+            .scale
+            .abs()
+            .min_element();
+        if global_scale == 0.0 {
+            // Nothing to scale the box back from, the billboard is drawn without culling instead
+            commands.entity(entity).remove::<Aabb>();
+            continue;
+        }
+
+        // Sizes in world space are divided by the global scale `check_visibility` applies
+        let scale = if lock_axis.is_some() {
+            1.0
+        } else {
+            transform.scale.abs().max_element() / global_scale
+        };
+        let offset = declutter.map_or(0.0, |state| state.offset().length()) / global_scale;
+
+        commands.entity(entity).insert(Aabb {
+            center: aabb.center * scale,
+            half_extents: aabb.half_extents * scale + offset,
+        });
+    }
+}
+
+fn radius_aabb(radius: f32) -> Aabb {
+    Aabb::from_min_max(Vec3::splat(-radius), Vec3::splat(radius))
+}
+
+fn instances_aabb(instances: &BillboardInstances, lock_rotation: bool) -> Option<Aabb> {
+    // The quad of an instance can turn around its center
+    let extents = instances
+        .instances
+        .iter()
+        .map(|instance| (instance.position, instance.size.length() / 2.0));
+
+    if lock_rotation {
+        let (min, max) = extents.fold(
+            (Vec3::splat(f32::INFINITY), Vec3::splat(f32::NEG_INFINITY)),
+            |(min, max), (position, radius)| {
+                (min.min(position - radius), max.max(position + radius))
+            },
+        );
+        (min.cmple(max).all()).then(|| Aabb::from_min_max(min, max))
+    } else {
+        // Without a locked rotation, instances are placed without the rotation of the entity,
+        // while the bounds are rotated with it
+        extents
+            .map(|(position, radius)| position.length() + radius)
+            .reduce(f32::max)
+            .map(radius_aabb)
+    }
+}
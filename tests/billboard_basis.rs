@@ -0,0 +1,89 @@
+use bevy::math::Vec3Swizzles;
+use bevy::prelude::*;
+use bevy::render::camera::CameraProjection;
+use bevy_mod_billboard::math::BillboardBasis;
+use bevy_mod_billboard::BillboardLockAxis;
+
+const EPSILON: f32 = 1e-5;
+
+fn isometric_camera() -> GlobalTransform {
+    GlobalTransform::from(Transform::from_xyz(10., 10., 10.).looking_at(Vec3::ZERO, Vec3::Y))
+}
+
+#[test]
+fn basis_follows_camera_orientation() {
+    let camera = GlobalTransform::from(Transform::from_rotation(Quat::from_rotation_y(
+        std::f32::consts::FRAC_PI_2,
+    )));
+
+    let basis = BillboardBasis::new(&camera, None);
+
+    assert!(basis.right.abs_diff_eq(Vec3::NEG_Z, EPSILON));
+    assert!(basis.up.abs_diff_eq(Vec3::Y, EPSILON));
+}
+
+#[test]
+fn basis_ignores_camera_scale() {
+    let camera = GlobalTransform::from(
+        Transform::from_xyz(10., 10., 10.)
+            .looking_at(Vec3::ZERO, Vec3::Y)
+            .with_scale(Vec3::new(3., 0.5, 2.)),
+    );
+
+    let basis = BillboardBasis::new(&camera, None);
+
+    assert!((basis.right.length() - 1.).abs() < EPSILON);
+    assert!((basis.up.length() - 1.).abs() < EPSILON);
+    assert!(basis.right.dot(basis.up).abs() < EPSILON);
+}
+
+#[test]
+fn basis_respects_lock_axis() {
+    let camera = isometric_camera();
+
+    let lock_y = BillboardLockAxis {
+        y_axis: true,
+        rotation: false,
+    };
+    let basis = BillboardBasis::new(&camera, Some(&lock_y));
+    assert_eq!(basis.up, Vec3::Y);
+    assert!(basis.right.y.abs() < EPSILON);
+
+    let lock_rotation = BillboardLockAxis {
+        y_axis: false,
+        rotation: true,
+    };
+    let basis = BillboardBasis::new(&camera, Some(&lock_rotation));
+    assert_eq!(basis.right, Vec3::NEG_X);
+    assert_eq!(basis.up, Vec3::Y);
+}
+
+#[test]
+fn billboard_is_square_and_upright_under_orthographic_projection() {
+    let (width, height) = (1600., 900.);
+
+    let mut projection = OrthographicProjection::default();
+    projection.update(width, height);
+
+    let camera = isometric_camera();
+    let clip_from_world = projection.get_clip_from_view() * camera.compute_matrix().inverse();
+
+    let basis = BillboardBasis::new(&camera, None);
+    let to_screen = |vertex: Vec2| {
+        let ndc = clip_from_world.project_point3(basis.local_position(vertex));
+        ndc.xy() * Vec2::new(width, height) / 2.
+    };
+
+    let bottom_left = to_screen(Vec2::new(-1., -1.));
+    let bottom_right = to_screen(Vec2::new(1., -1.));
+    let top_left = to_screen(Vec2::new(-1., 1.));
+
+    // Edges stay aligned to the screen
+    assert!((bottom_left.y - bottom_right.y).abs() < EPSILON);
+    assert!((bottom_left.x - top_left.x).abs() < EPSILON);
+
+    // A square quad stays square in pixels, whatever the aspect ratio of the projection
+    let size = Vec2::new(bottom_right.x - bottom_left.x, top_left.y - bottom_left.y);
+    assert!((size.x - size.y).abs() < 1e-3);
+    assert!(size.x > 0.);
+}
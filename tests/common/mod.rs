@@ -0,0 +1,274 @@
+//! Headless apps for the integration tests. Neither a window nor a display is needed, and the
+//! render app only runs on a software fallback adapter, so the tests work on CI machines.
+
+#![allow(dead_code)]
+
+use bevy::app::{PluginGroupBuilder, PluginsState};
+use bevy::log::LogPlugin;
+use bevy::prelude::*;
+use bevy::render::camera::RenderTarget;
+use bevy::render::pipelined_rendering::PipelinedRenderingPlugin;
+use bevy::render::render_asset::RenderAssets;
+use bevy::render::render_resource::{
+    BufferDescriptor, BufferUsages, CommandEncoderDescriptor, Extent3d, ImageCopyBuffer,
+    ImageDataLayout, Maintain, MapMode, TextureDescriptor, TextureDimension, TextureFormat,
+    TextureUsages,
+};
+use bevy::render::renderer::{
+    initialize_renderer, RenderDevice, RenderInstance, RenderQueue, WgpuWrapper,
+};
+use bevy::render::settings::{RenderCreation, WgpuSettings};
+use bevy::render::texture::GpuImage;
+use bevy::render::view::VisibleEntities;
+use bevy::render::{Render, RenderApp, RenderPlugin, RenderSet};
+use bevy::tasks::block_on;
+use bevy::window::ExitCondition;
+use bevy_mod_billboard::prelude::*;
+use bevy_mod_billboard::Billboard;
+use std::sync::Arc;
+use std::time::Duration;
+
+const MAX_UPDATES: usize = 500;
+
+// Normally done by `App::run`, the tests drive the app with `App::update` instead
+fn finish_plugins(app: &mut App) {
+    while app.plugins_state() == PluginsState::Adding {
+        std::thread::yield_now();
+    }
+
+    app.finish();
+    app.cleanup();
+}
+
+fn headless_plugins(render_creation: RenderCreation) -> PluginGroupBuilder {
+    let plugins = DefaultPlugins.build();
+
+    #[cfg(feature = "bevy_winit")]
+    let plugins = plugins.disable::<bevy::winit::WinitPlugin>();
+
+    plugins
+        .disable::<LogPlugin>()
+        // Extract and render in the same update, so the render world can be inspected after it
+        .disable::<PipelinedRenderingPlugin>()
+        .set(WindowPlugin {
+            primary_window: None,
+            exit_condition: ExitCondition::DontExit,
+            close_when_requested: false,
+        })
+        .set(RenderPlugin {
+            render_creation,
+            synchronous_pipeline_compilation: true,
+        })
+}
+
+/// An app without a render app, like a dedicated server, for asserting on the main world (text
+/// layout, mesh generation, visibility).
+pub fn main_world_app() -> App {
+    let mut app = App::new();
+
+    app.add_plugins(headless_plugins(
+        WgpuSettings {
+            backends: None,
+            ..default()
+        }
+        .into(),
+    ))
+    .add_plugins(BillboardPlugin);
+
+    finish_plugins(&mut app);
+
+    app
+}
+
+/// An app running [`BillboardPlugin`] on a software fallback adapter, for asserting on
+/// extraction and queueing. Returns `None` when the machine has no fallback adapter, in which
+/// case the calling test should be skipped.
+pub fn render_app() -> Option<App> {
+    let instance = wgpu::Instance::default();
+    let request_adapter_options = wgpu::RequestAdapterOptions {
+        force_fallback_adapter: true,
+        ..default()
+    };
+
+    if block_on(instance.request_adapter(&request_adapter_options)).is_none() {
+        eprintln!("No fallback adapter available, skipping render test");
+        return None;
+    }
+
+    let (device, queue, adapter_info, adapter) = block_on(initialize_renderer(
+        &instance,
+        &WgpuSettings::default(),
+        &request_adapter_options,
+    ));
+
+    let mut app = App::new();
+
+    app.add_plugins((
+        headless_plugins(RenderCreation::Manual(
+            device,
+            queue,
+            adapter_info,
+            adapter,
+            RenderInstance(Arc::new(WgpuWrapper::new(instance))),
+        )),
+        BillboardPlugin,
+    ));
+
+    finish_plugins(&mut app);
+
+    Some(app)
+}
+
+/// Copy of the render world components of type `C` as of the last update, as the render world
+/// entities themselves are cleared at the end of every frame.
+#[derive(Resource)]
+pub struct CapturedRenderComponents<C: Component + Clone>(pub Vec<(Entity, C)>);
+
+pub fn capture_render_components<C: Component + Clone>(app: &mut App) {
+    fn capture<C: Component + Clone>(
+        mut captured: ResMut<CapturedRenderComponents<C>>,
+        query: Query<(Entity, &C)>,
+    ) {
+        captured.0 = query
+            .iter()
+            .map(|(entity, component)| (entity, component.clone()))
+            .collect();
+    }
+
+    app.sub_app_mut(RenderApp)
+        .insert_resource(CapturedRenderComponents::<C>(Vec::new()))
+        .add_systems(Render, capture::<C>.in_set(RenderSet::PhaseSort));
+}
+
+pub fn captured_render_components<C: Component + Clone>(app: &App) -> &[(Entity, C)] {
+    &app.sub_app(RenderApp)
+        .world()
+        .resource::<CapturedRenderComponents<C>>()
+        .0
+}
+
+/// A 2x2 billboard at `transform`, for tests running the billboard systems on a bare [`World`].
+pub fn spawn_billboard(world: &mut World, transform: Transform) -> Entity {
+    world.init_resource::<Assets<Mesh>>();
+    let mesh = world
+        .resource_mut::<Assets<Mesh>>()
+        .add(Rectangle::new(2., 2.));
+
+    world
+        .spawn((
+            Billboard,
+            BillboardMeshHandle(mesh),
+            transform,
+            GlobalTransform::from(transform),
+        ))
+        .id()
+}
+
+/// A camera at `transform` that sees `billboards`, as if visibility had been checked already.
+pub fn spawn_camera_seeing(
+    world: &mut World,
+    camera: Camera,
+    transform: Transform,
+    billboards: &[Entity],
+) -> Entity {
+    let mut visible_entities = VisibleEntities::default();
+    visible_entities
+        .get_mut::<With<Billboard>>()
+        .extend_from_slice(billboards);
+
+    world
+        .spawn((camera, GlobalTransform::from(transform), visible_entities))
+        .id()
+}
+
+/// Image a camera can render to instead of a window.
+pub fn render_target(images: &mut Assets<Image>) -> RenderTarget {
+    let size = Extent3d {
+        width: 64,
+        height: 64,
+        ..default()
+    };
+
+    let mut image = Image {
+        texture_descriptor: TextureDescriptor {
+            label: None,
+            size,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::Bgra8UnormSrgb,
+            mip_level_count: 1,
+            sample_count: 1,
+            usage: TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_SRC
+                | TextureUsages::COPY_DST
+                | TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        },
+        ..default()
+    };
+    image.resize(size);
+
+    RenderTarget::Image(images.add(image))
+}
+
+/// Pixels a camera rendered to an image from [`render_target`] in the last update, as rows of
+/// BGRA bytes.
+pub fn read_render_target(app: &App, image: &Handle<Image>) -> Vec<u8> {
+    let render_world = app.sub_app(RenderApp).world();
+    let render_device = render_world.resource::<RenderDevice>();
+    let gpu_image = render_world
+        .resource::<RenderAssets<GpuImage>>()
+        .get(image)
+        .expect("The render target should be prepared");
+
+    // Rows of 64 pixels are already aligned the way buffer copies need them
+    let bytes_per_row = gpu_image.size.x * 4;
+    assert_eq!(bytes_per_row % wgpu::COPY_BYTES_PER_ROW_ALIGNMENT, 0);
+
+    let buffer = render_device.create_buffer(&BufferDescriptor {
+        label: None,
+        size: (bytes_per_row * gpu_image.size.y) as u64,
+        usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor::default());
+    encoder.copy_texture_to_buffer(
+        gpu_image.texture.as_image_copy(),
+        ImageCopyBuffer {
+            buffer: &buffer,
+            layout: ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(bytes_per_row),
+                rows_per_image: None,
+            },
+        },
+        gpu_image.texture.size(),
+    );
+    render_world
+        .resource::<RenderQueue>()
+        .submit([encoder.finish()]);
+
+    let slice = buffer.slice(..);
+    slice.map_async(MapMode::Read, |result| {
+        result.expect("The render target should be readable");
+    });
+    render_device.poll(Maintain::Wait);
+
+    let pixels = slice.get_mapped_range().to_vec();
+    pixels
+}
+
+/// Updates the app until the condition holds, giving assets time to load in between.
+pub fn update_until(app: &mut App, mut condition: impl FnMut(&mut App) -> bool) {
+    for _ in 0..MAX_UPDATES {
+        app.update();
+
+        if condition(app) {
+            return;
+        }
+
+        std::thread::sleep(Duration::from_millis(1));
+    }
+
+    panic!("Condition wasn't met after {MAX_UPDATES} updates");
+}
@@ -0,0 +1,104 @@
+mod common;
+
+use bevy::prelude::*;
+use bevy::render::MainWorld;
+use bevy_mod_billboard::pipeline::BillboardUniform;
+use bevy_mod_billboard::prelude::*;
+use bevy_mod_billboard::texture::extract_billboard_texture;
+use bevy_mod_billboard::BillboardLockAxis;
+use common::main_world_app;
+
+/// Extracts from the [`MainWorld`] into the cleared render world, like every frame.
+fn extract(render_world: &mut World, system: &mut impl System<In = (), Out = ()>) {
+    render_world.clear_entities();
+    system.run((), render_world);
+}
+
+fn extracted_transform(render_world: &World, entity: Entity) -> Option<Mat4> {
+    render_world
+        .get::<BillboardUniform>(entity)
+        .map(|uniform| uniform.transform())
+}
+
+#[test]
+fn only_changed_billboards_are_extracted_again() {
+    let mut app = main_world_app();
+
+    let mut spawn_billboard = |translation| {
+        app.world_mut()
+            .spawn(BillboardTextureBundle {
+                transform: Transform::from_translation(translation)
+                    .with_rotation(Quat::from_rotation_z(1.0)),
+                ..default()
+            })
+            .id()
+    };
+    let moving = spawn_billboard(Vec3::X);
+    let locked = spawn_billboard(Vec3::Y);
+    let despawned = spawn_billboard(Vec3::Z);
+    let still = spawn_billboard(Vec3::ONE);
+    app.world_mut()
+        .entity_mut(locked)
+        .insert(BillboardLockAxis::default());
+
+    // Computes the global transforms
+    app.update();
+
+    // Visible without a camera
+    let world = app.world_mut();
+    for mut view_visibility in world.query::<&mut ViewVisibility>().iter_mut(world) {
+        view_visibility.set();
+    }
+
+    let mut main_world = MainWorld::default();
+    std::mem::swap(&mut *main_world, app.world_mut());
+    let mut render_world = World::new();
+    render_world.insert_resource(main_world);
+    // Not registered, as the render world is cleared every frame
+    let mut system = IntoSystem::into_system(extract_billboard_texture);
+    system.initialize(&mut render_world);
+
+    extract(&mut render_world, &mut system);
+    assert_eq!(
+        extracted_transform(&render_world, moving),
+        Some(Mat4::from_translation(Vec3::X))
+    );
+    assert_eq!(
+        extracted_transform(&render_world, locked),
+        Some(Mat4::from_rotation_translation(
+            Quat::from_rotation_z(1.0),
+            Vec3::Y
+        ))
+    );
+
+    {
+        let mut main_world = render_world.resource_mut::<MainWorld>();
+        *main_world.get_mut::<GlobalTransform>(moving).unwrap() =
+            GlobalTransform::from_translation(Vec3::NEG_X);
+        main_world.entity_mut(locked).remove::<BillboardLockAxis>();
+        main_world.despawn(despawned);
+
+        // Unchanged as far as extraction can tell
+        main_world
+            .entity_mut(still)
+            .get_mut::<GlobalTransform>()
+            .unwrap()
+            .bypass_change_detection()
+            .clone_from(&GlobalTransform::IDENTITY);
+    }
+
+    extract(&mut render_world, &mut system);
+    assert_eq!(
+        extracted_transform(&render_world, moving),
+        Some(Mat4::from_translation(Vec3::NEG_X))
+    );
+    assert_eq!(
+        extracted_transform(&render_world, locked),
+        Some(Mat4::from_translation(Vec3::Y))
+    );
+    assert_eq!(extracted_transform(&render_world, despawned), None);
+    assert_eq!(
+        extracted_transform(&render_world, still),
+        Some(Mat4::from_translation(Vec3::ONE))
+    );
+}
@@ -0,0 +1,63 @@
+use bevy::prelude::*;
+use bevy::render::camera::CameraProjection;
+use bevy_mod_billboard::gpu_culling::BillboardGpuCullUniform;
+
+const MESH_RADIUS: f32 = 0.5;
+
+/// Culling for a camera at z = 10 looking down -z, with a horizontal field of view of 90°.
+fn cull() -> BillboardGpuCullUniform {
+    let camera = Transform::from_xyz(0., 0., 10.).looking_at(Vec3::ZERO, Vec3::Y);
+    let projection = PerspectiveProjection {
+        fov: std::f32::consts::FRAC_PI_2,
+        aspect_ratio: 1.0,
+        near: 0.1,
+        far: 100.0,
+    };
+    let clip_from_world = projection.get_clip_from_view() * camera.compute_matrix().inverse();
+
+    BillboardGpuCullUniform::new(&clip_from_world, camera.translation, MESH_RADIUS)
+}
+
+fn model(translation: Vec3, scale: f32) -> Mat4 {
+    Mat4::from_scale_rotation_translation(Vec3::splat(scale), Quat::IDENTITY, translation)
+}
+
+#[test]
+fn instances_outside_the_frustum_are_culled() {
+    let cull = cull();
+
+    assert!(cull.is_visible(&model(Vec3::ZERO, 1.0), f32::INFINITY));
+    // Behind the camera, and past the left, right, bottom and top planes at z = 0
+    assert!(!cull.is_visible(&model(Vec3::new(0., 0., 12.), 1.0), f32::INFINITY));
+    assert!(!cull.is_visible(&model(Vec3::new(-12., 0., 0.), 1.0), f32::INFINITY));
+    assert!(!cull.is_visible(&model(Vec3::new(12., 0., 0.), 1.0), f32::INFINITY));
+    assert!(!cull.is_visible(&model(Vec3::new(0., -12., 0.), 1.0), f32::INFINITY));
+    assert!(!cull.is_visible(&model(Vec3::new(0., 12., 0.), 1.0), f32::INFINITY));
+}
+
+#[test]
+fn far_plane_is_left_to_max_distance() {
+    let cull = cull();
+    let beyond_far_plane = model(Vec3::new(0., 0., -200.), 1.0);
+
+    assert!(cull.is_visible(&beyond_far_plane, f32::INFINITY));
+    assert!(!cull.is_visible(&beyond_far_plane, 100.0));
+
+    let at_five = model(Vec3::new(0., 0., 5.), 1.0);
+    assert!(cull.is_visible(&at_five, 5.5));
+    assert!(!cull.is_visible(&at_five, 4.5));
+}
+
+#[test]
+fn mesh_radius_is_scaled_by_the_largest_axis() {
+    let cull = cull();
+    // The right plane passes through x = 10 at z = 0, at 45° to the x axis
+    let outside = Vec3::new(10. + 2.0_f32.sqrt() * 0.75, 0., 0.);
+
+    assert!(!cull.is_visible(&model(outside, 1.0), f32::INFINITY));
+    assert!(cull.is_visible(&model(outside, 2.0), f32::INFINITY));
+
+    let stretched =
+        Mat4::from_scale_rotation_translation(Vec3::new(1., 1., 2.), Quat::IDENTITY, outside);
+    assert!(cull.is_visible(&stretched, f32::INFINITY));
+}
@@ -0,0 +1,278 @@
+mod common;
+
+use bevy::ecs::system::SystemState;
+use bevy::prelude::*;
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy_mod_billboard::instances::{BillboardInstance, BillboardInstances};
+use bevy_mod_billboard::picking::{
+    send_billboard_pointer_events, BillboardClick, BillboardPointerHit, BillboardPointerHits,
+    BillboardPointerOut, BillboardPointerOver, BillboardRayHit, BillboardRaycast,
+    BillboardRaycastSettings,
+};
+use bevy_mod_billboard::{Billboard, BillboardLockAxis, BillboardTextureHandle};
+use common::{spawn_billboard, spawn_camera_seeing};
+
+const EPSILON: f32 = 1e-4;
+
+fn picking_world() -> World {
+    let mut world = World::new();
+    world.init_resource::<Assets<Mesh>>();
+    world.init_resource::<Assets<Image>>();
+    world.init_resource::<Assets<TextureAtlasLayout>>();
+    world
+}
+
+fn spawn_camera(world: &mut World, transform: Transform, billboards: &[Entity]) -> Entity {
+    spawn_camera_seeing(world, Camera::default(), transform, billboards)
+}
+
+/// A 2x2 texture billboard at `transform`, whose texture is transparent on its left half.
+fn spawn_textured_billboard(world: &mut World, transform: Transform) -> Entity {
+    let image = world.resource_mut::<Assets<Image>>().add(Image::new(
+        Extent3d {
+            width: 2,
+            height: 1,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        vec![255, 255, 255, 0, 255, 255, 255, 255],
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::all(),
+    ));
+
+    let billboard = spawn_billboard(world, transform);
+    world
+        .entity_mut(billboard)
+        .insert(BillboardTextureHandle(image));
+    billboard
+}
+
+fn cast_ray(
+    world: &mut World,
+    camera: Entity,
+    ray: Ray3d,
+    settings: BillboardRaycastSettings,
+) -> Vec<(Entity, f32, Vec2, Option<usize>)> {
+    let mut state = SystemState::<BillboardRaycast>::new(world);
+    state
+        .get(world)
+        .cast_ray(camera, ray, &settings)
+        .into_iter()
+        .map(|hit| (hit.entity, hit.distance, hit.uv, hit.instance))
+        .collect()
+}
+
+#[test]
+fn rays_hit_billboards_facing_the_camera() {
+    let mut world = picking_world();
+
+    // Turned away from the camera, which doesn't matter as it faces the camera when rendered
+    let billboard = spawn_textured_billboard(
+        &mut world,
+        Transform::from_rotation(Quat::from_rotation_y(std::f32::consts::FRAC_PI_2)),
+    );
+    let camera_transform = Transform::from_xyz(0., 0., 5.).looking_at(Vec3::ZERO, Vec3::Y);
+    let camera = spawn_camera(&mut world, camera_transform, &[billboard]);
+
+    let ray = Ray3d::new(Vec3::new(0.5, 0.5, 5.), Vec3::NEG_Z);
+    let hits = cast_ray(&mut world, camera, ray, default());
+
+    assert_eq!(hits.len(), 1);
+    let (entity, distance, uv, instance) = hits[0];
+    assert_eq!(entity, billboard);
+    assert!((distance - 5.).abs() < EPSILON);
+    assert!(uv.abs_diff_eq(Vec2::new(0.75, 0.25), EPSILON));
+    assert_eq!(instance, None);
+
+    let miss = Ray3d::new(Vec3::new(1.5, 0., 5.), Vec3::NEG_Z);
+    assert!(cast_ray(&mut world, camera, miss, default()).is_empty());
+}
+
+#[test]
+fn rays_respect_locked_rotation() {
+    let mut world = picking_world();
+
+    let transform = Transform::from_rotation(Quat::from_rotation_y(std::f32::consts::FRAC_PI_2));
+    let locked = spawn_textured_billboard(&mut world, transform);
+    world.entity_mut(locked).insert(BillboardLockAxis {
+        y_axis: false,
+        rotation: true,
+    });
+
+    let camera_transform = Transform::from_xyz(0., 0., 5.).looking_at(Vec3::ZERO, Vec3::Y);
+    let camera = spawn_camera(&mut world, camera_transform, &[locked]);
+
+    // Seen edge on, the billboard can't be hit from the front, only from the side
+    let front = Ray3d::new(Vec3::new(0.5, 0.5, 5.), Vec3::NEG_Z);
+    assert!(cast_ray(&mut world, camera, front, default()).is_empty());
+
+    let side = Ray3d::new(Vec3::new(5., 0.5, 0.5), Vec3::NEG_X);
+    let hits = cast_ray(&mut world, camera, side, default());
+    assert_eq!(hits.len(), 1);
+    assert!((hits[0].1 - 5.).abs() < EPSILON);
+}
+
+#[test]
+fn transparent_texels_are_skipped_with_an_alpha_threshold() {
+    let mut world = picking_world();
+
+    let billboard = spawn_textured_billboard(&mut world, Transform::IDENTITY);
+    let camera_transform = Transform::from_xyz(0., 0., 5.).looking_at(Vec3::ZERO, Vec3::Y);
+    let camera = spawn_camera(&mut world, camera_transform, &[billboard]);
+
+    let settings = BillboardRaycastSettings {
+        alpha_threshold: Some(0.5),
+    };
+    let transparent = Ray3d::new(Vec3::new(-0.5, 0., 5.), Vec3::NEG_Z);
+    let opaque = Ray3d::new(Vec3::new(0.5, 0., 5.), Vec3::NEG_Z);
+
+    assert_eq!(
+        cast_ray(&mut world, camera, transparent, default()).len(),
+        1
+    );
+    assert!(cast_ray(&mut world, camera, transparent, settings).is_empty());
+    assert_eq!(cast_ray(&mut world, camera, opaque, settings).len(), 1);
+}
+
+#[test]
+fn rays_hit_the_nearest_instance() {
+    let mut world = picking_world();
+
+    let instances = BillboardInstances {
+        instances: vec![
+            BillboardInstance {
+                position: Vec3::new(0., 0., -2.),
+                ..default()
+            },
+            BillboardInstance {
+                position: Vec3::new(0., 0., 1.),
+                ..default()
+            },
+            BillboardInstance {
+                position: Vec3::new(3., 0., 0.),
+                ..default()
+            },
+        ],
+        atlas: None,
+    };
+    let billboard = world
+        .spawn((
+            Billboard,
+            instances,
+            Transform::IDENTITY,
+            GlobalTransform::IDENTITY,
+        ))
+        .id();
+
+    let camera_transform = Transform::from_xyz(0., 0., 5.).looking_at(Vec3::ZERO, Vec3::Y);
+    let camera = spawn_camera(&mut world, camera_transform, &[billboard]);
+
+    let ray = Ray3d::new(Vec3::new(0., 0., 5.), Vec3::NEG_Z);
+    let hits = cast_ray(&mut world, camera, ray, default());
+
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].0, billboard);
+    assert!((hits[0].1 - 4.).abs() < EPSILON);
+    assert_eq!(hits[0].3, Some(1));
+}
+
+fn pointer_app() -> App {
+    let mut app = App::new();
+    app.add_event::<BillboardPointerOver>()
+        .add_event::<BillboardPointerOut>()
+        .add_event::<BillboardClick>()
+        .init_resource::<BillboardPointerHits>()
+        .init_resource::<ButtonInput<MouseButton>>()
+        .add_systems(Update, send_billboard_pointer_events);
+    app
+}
+
+fn point_at(app: &mut App, entities: &[Entity]) {
+    let camera = Entity::PLACEHOLDER;
+    app.world_mut().resource_mut::<BillboardPointerHits>().hits = entities
+        .iter()
+        .enumerate()
+        .map(|(index, &entity)| BillboardPointerHit {
+            camera,
+            hit: BillboardRayHit {
+                entity,
+                distance: index as f32 + 1.,
+                position: Vec3::ZERO,
+                uv: Vec2::ZERO,
+                instance: None,
+            },
+        })
+        .collect();
+}
+
+fn update_with_button(app: &mut App, press: Option<bool>) {
+    let mut buttons = app.world_mut().resource_mut::<ButtonInput<MouseButton>>();
+    buttons.clear();
+    match press {
+        Some(true) => buttons.press(MouseButton::Left),
+        Some(false) => buttons.release(MouseButton::Left),
+        None => {}
+    }
+    app.update();
+}
+
+fn drain<E: Event + Clone>(app: &mut App) -> Vec<E> {
+    app.world_mut()
+        .resource_mut::<Events<E>>()
+        .drain()
+        .collect()
+}
+
+#[test]
+fn pointer_events_follow_the_front_billboard() {
+    let mut app = pointer_app();
+    let front = app.world_mut().spawn_empty().id();
+    let back = app.world_mut().spawn_empty().id();
+
+    point_at(&mut app, &[front, back]);
+    update_with_button(&mut app, None);
+    let over = drain::<BillboardPointerOver>(&mut app);
+    assert_eq!(over.len(), 1);
+    assert_eq!(over[0].hit.entity, front);
+
+    // Still hovered, nothing new
+    update_with_button(&mut app, None);
+    assert!(drain::<BillboardPointerOver>(&mut app).is_empty());
+
+    point_at(&mut app, &[back]);
+    update_with_button(&mut app, None);
+    assert_eq!(
+        drain::<BillboardPointerOut>(&mut app),
+        vec![BillboardPointerOut { entity: front }]
+    );
+    assert_eq!(drain::<BillboardPointerOver>(&mut app)[0].hit.entity, back);
+
+    point_at(&mut app, &[]);
+    update_with_button(&mut app, None);
+    assert_eq!(
+        drain::<BillboardPointerOut>(&mut app),
+        vec![BillboardPointerOut { entity: back }]
+    );
+}
+
+#[test]
+fn clicks_need_press_and_release_on_the_same_billboard() {
+    let mut app = pointer_app();
+    let first = app.world_mut().spawn_empty().id();
+    let second = app.world_mut().spawn_empty().id();
+
+    point_at(&mut app, &[first]);
+    update_with_button(&mut app, Some(true));
+    update_with_button(&mut app, Some(false));
+    let clicks = drain::<BillboardClick>(&mut app);
+    assert_eq!(clicks.len(), 1);
+    assert_eq!(clicks[0].button, MouseButton::Left);
+    assert_eq!(clicks[0].hit.entity, first);
+
+    // Dragged off to another billboard before releasing
+    update_with_button(&mut app, Some(true));
+    point_at(&mut app, &[second]);
+    update_with_button(&mut app, Some(false));
+    assert!(drain::<BillboardClick>(&mut app).is_empty());
+}
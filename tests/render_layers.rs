@@ -0,0 +1,183 @@
+use bevy::ecs::system::RunSystemOnce;
+use bevy::prelude::*;
+use bevy::render::camera::CameraProjection;
+use bevy::render::primitives::{Aabb, Frustum};
+use bevy::render::view::{check_visibility, NoFrustumCulling, RenderLayers, VisibleEntities};
+use bevy::tasks::{ComputeTaskPool, TaskPool};
+use bevy_mod_billboard::visibility::{
+    calculate_billboard_bounds, filter_billboard_cameras, BillboardCameras,
+};
+use bevy_mod_billboard::{Billboard, BillboardMeshHandle};
+
+fn spawn_camera(world: &mut World, layers: RenderLayers) -> Entity {
+    let transform = Transform::from_xyz(0., 0., 5.).looking_at(Vec3::ZERO, Vec3::Y);
+    let clip_from_world = PerspectiveProjection::default().get_clip_from_view()
+        * transform.compute_matrix().inverse();
+
+    world
+        .spawn((
+            Camera::default(),
+            VisibleEntities::default(),
+            Frustum::from_clip_from_world(&clip_from_world),
+            GlobalTransform::from(transform),
+            layers,
+        ))
+        .id()
+}
+
+fn spawn_billboard(world: &mut World, bundle: impl Bundle) -> Entity {
+    world
+        .spawn((
+            Billboard,
+            Transform::IDENTITY,
+            GlobalTransform::IDENTITY,
+            InheritedVisibility::VISIBLE,
+            ViewVisibility::default(),
+            bundle,
+        ))
+        .id()
+}
+
+fn update_visibility(world: &mut World) {
+    // check_visibility iterates in parallel
+    ComputeTaskPool::get_or_init(TaskPool::default);
+
+    world.run_system_once(check_visibility::<With<Billboard>>);
+    world.run_system_once(filter_billboard_cameras);
+}
+
+fn visible_billboards(world: &World, camera: Entity) -> Vec<Entity> {
+    let visible_entities = world.get::<VisibleEntities>(camera).unwrap();
+    let mut entities = visible_entities.get::<With<Billboard>>().to_vec();
+    entities.sort();
+    entities
+}
+
+#[test]
+fn billboards_respect_render_layers_per_camera() {
+    let mut world = World::new();
+
+    let main_camera = spawn_camera(&mut world, RenderLayers::layer(0));
+    let minimap_camera = spawn_camera(&mut world, RenderLayers::layer(1));
+    let both_camera = spawn_camera(&mut world, RenderLayers::from_layers(&[0, 1]));
+
+    let default_layer = spawn_billboard(&mut world, ());
+    let minimap_layer = spawn_billboard(&mut world, RenderLayers::layer(1));
+    let every_layer = spawn_billboard(&mut world, RenderLayers::from_layers(&[0, 1]));
+
+    update_visibility(&mut world);
+
+    assert_eq!(
+        visible_billboards(&world, main_camera),
+        vec![default_layer, every_layer]
+    );
+    assert_eq!(
+        visible_billboards(&world, minimap_camera),
+        vec![minimap_layer, every_layer]
+    );
+    assert_eq!(
+        visible_billboards(&world, both_camera),
+        vec![default_layer, minimap_layer, every_layer]
+    );
+}
+
+#[test]
+fn billboard_cameras_whitelist_filters_views() {
+    let mut world = World::new();
+
+    let main_camera = spawn_camera(&mut world, RenderLayers::layer(0));
+    let picture_camera = spawn_camera(&mut world, RenderLayers::layer(0));
+
+    let everywhere = spawn_billboard(&mut world, ());
+    let picture_only = spawn_billboard(&mut world, BillboardCameras(vec![picture_camera]));
+    let nowhere = spawn_billboard(&mut world, BillboardCameras::default());
+
+    update_visibility(&mut world);
+
+    assert_eq!(visible_billboards(&world, main_camera), vec![everywhere]);
+    assert_eq!(
+        visible_billboards(&world, picture_camera),
+        vec![everywhere, picture_only]
+    );
+    assert!(!visible_billboards(&world, main_camera).contains(&nowhere));
+    assert!(!visible_billboards(&world, picture_camera).contains(&nowhere));
+
+    // Billboards no allowed camera sees are hidden, so they aren't extracted or laid out
+    let view_visibility = |entity| world.get::<ViewVisibility>(entity).unwrap().get();
+    assert!(view_visibility(everywhere));
+    assert!(view_visibility(picture_only));
+    assert!(!view_visibility(nowhere));
+}
+
+#[test]
+fn off_screen_billboards_are_frustum_culled() {
+    let mut world = World::new();
+    world.init_resource::<Assets<Mesh>>();
+    world.init_resource::<Events<AssetEvent<Mesh>>>();
+
+    let camera = spawn_camera(&mut world, RenderLayers::default());
+    let mesh = BillboardMeshHandle(
+        world
+            .resource_mut::<Assets<Mesh>>()
+            .add(Rectangle::new(2., 2.)),
+    );
+
+    let on_screen = spawn_billboard(&mut world, mesh.clone());
+    let off_screen = spawn_billboard(&mut world, mesh.clone());
+    let not_culled = spawn_billboard(&mut world, (mesh.clone(), NoFrustumCulling));
+    for entity in [off_screen, not_culled] {
+        world
+            .entity_mut(entity)
+            .insert(GlobalTransform::from_xyz(100., 0., 0.));
+    }
+
+    world.run_system_once(calculate_billboard_bounds);
+    update_visibility(&mut world);
+
+    // Any orientation of the quad fits in the bounds
+    let aabb = world.get::<Aabb>(on_screen).unwrap();
+    assert_eq!(aabb.half_extents, Vec3::splat(2f32.sqrt()).into());
+    assert!(world.get::<Aabb>(not_culled).is_none());
+
+    assert_eq!(
+        visible_billboards(&world, camera),
+        vec![on_screen, not_culled]
+    );
+}
+
+#[test]
+fn bounds_fit_billboards_rendered_larger_than_their_global_scale() {
+    let mut world = World::new();
+    world.init_resource::<Assets<Mesh>>();
+    world.init_resource::<Events<AssetEvent<Mesh>>>();
+
+    let camera = spawn_camera(&mut world, RenderLayers::default());
+    let mesh = BillboardMeshHandle(
+        world
+            .resource_mut::<Assets<Mesh>>()
+            .add(Rectangle::new(2., 2.)),
+    );
+
+    // A child of an entity scaled down is still rendered with its own scale, so it reaches into
+    // the view from just past its edge
+    let parent = world
+        .spawn((
+            Transform::from_scale(Vec3::splat(0.1)),
+            GlobalTransform::from_scale(Vec3::splat(0.1)),
+        ))
+        .id();
+    let billboard = spawn_billboard(&mut world, mesh);
+    world
+        .entity_mut(billboard)
+        .insert(GlobalTransform::from(
+            Transform::from_xyz(3., 0., 0.).with_scale(Vec3::splat(0.1)),
+        ))
+        .set_parent(parent);
+
+    world.run_system_once(calculate_billboard_bounds);
+    update_visibility(&mut world);
+
+    let aabb = world.get::<Aabb>(billboard).unwrap();
+    assert!(Vec3::from(aabb.half_extents).abs_diff_eq(Vec3::splat(2f32.sqrt() / 0.1), 1e-4));
+    assert_eq!(visible_billboards(&world, camera), vec![billboard]);
+}
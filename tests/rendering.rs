@@ -0,0 +1,973 @@
+mod common;
+
+use bevy::core_pipeline::core_3d::Transparent3d;
+use bevy::core_pipeline::prepass::DepthPrepass;
+use bevy::core_pipeline::tonemapping::{DebandDither, Tonemapping};
+use bevy::prelude::*;
+use bevy::render::camera::{RenderTarget, ScalingMode, Viewport};
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::render_phase::{
+    CachedRenderPipelinePhaseItem, SortedPhaseItem, ViewSortedRenderPhases,
+};
+use bevy::render::render_resource::{
+    CachedPipelineState, Extent3d, PipelineCache, PipelineDescriptor, TextureDimension,
+    TextureFormat,
+};
+use bevy::render::renderer::RenderAdapterInfo;
+use bevy::render::view::RenderLayers;
+use bevy::render::{Render, RenderApp, RenderSet};
+use bevy_mod_billboard::batch::{
+    BillboardTextBatch, BillboardTextBatches, ExtractedBillboardTextBatches,
+};
+use bevy_mod_billboard::gpu_culling::ExtractedBillboardGpuInstances;
+use bevy_mod_billboard::instances::ExtractedBillboardInstances;
+use bevy_mod_billboard::oit::BillboardOit3d;
+use bevy_mod_billboard::pipeline::{RenderBillboardImage, RenderBillboardMesh};
+use bevy_mod_billboard::prelude::*;
+use bevy_mod_billboard::text::BillboardTextHandles;
+use common::{
+    capture_render_components, captured_render_components, read_render_target, render_app,
+    render_target, update_until,
+};
+use wgpu::Backend;
+
+fn queued_billboards(app: &App, camera: Entity) -> Vec<Entity> {
+    let render_world = app.sub_app(RenderApp).world();
+
+    render_world
+        .resource::<ViewSortedRenderPhases<Transparent3d>>()
+        .get(&camera)
+        .map(|phase| phase.items.iter().map(|item| item.entity).collect())
+        .unwrap_or_default()
+}
+
+/// Whether a billboard is queued for `camera` with a compiled pipeline, so its shader variant
+/// is valid.
+fn pipeline_ready(app: &App, camera: Entity) -> bool {
+    phase_pipeline_ready::<Transparent3d>(app, camera)
+}
+
+fn phase_pipeline_ready<P: SortedPhaseItem + CachedRenderPipelinePhaseItem>(
+    app: &App,
+    camera: Entity,
+) -> bool {
+    let render_world = app.sub_app(RenderApp).world();
+    let pipeline_cache = render_world.resource::<PipelineCache>();
+
+    render_world
+        .resource::<ViewSortedRenderPhases<P>>()
+        .get(&camera)
+        .is_some_and(|phase| {
+            phase.items.iter().any(|item| {
+                pipeline_cache
+                    .get_render_pipeline(item.cached_pipeline())
+                    .is_some()
+            })
+        })
+}
+
+fn spawn_camera(world: &mut World) -> Entity {
+    let target = render_target(&mut world.resource_mut::<Assets<Image>>());
+
+    world
+        .spawn(Camera3dBundle {
+            camera: Camera {
+                target,
+                ..default()
+            },
+            transform: Transform::from_xyz(0., 0., 5.).looking_at(Vec3::ZERO, Vec3::Y),
+            ..default()
+        })
+        .id()
+}
+
+#[test]
+fn texture_billboards_are_extracted_and_queued() {
+    let Some(mut app) = render_app() else {
+        return;
+    };
+
+    let world = app.world_mut();
+    let camera = spawn_camera(world);
+    let mesh = world
+        .resource_mut::<Assets<Mesh>>()
+        .add(Rectangle::from_size(Vec2::ONE));
+    let image = world.resource_mut::<Assets<Image>>().add(Image::default());
+
+    let visible = world
+        .spawn(BillboardTextureBundle {
+            mesh: BillboardMeshHandle(mesh.clone()),
+            texture: BillboardTextureHandle(image.clone()),
+            ..default()
+        })
+        .id();
+    let hidden = world
+        .spawn(BillboardTextureBundle {
+            mesh: BillboardMeshHandle(mesh.clone()),
+            texture: BillboardTextureHandle(image.clone()),
+            visibility: Visibility::Hidden,
+            ..default()
+        })
+        .id();
+
+    capture_render_components::<RenderBillboardMesh>(&mut app);
+    capture_render_components::<RenderBillboardImage>(&mut app);
+
+    update_until(&mut app, |app| !queued_billboards(app, camera).is_empty());
+
+    let meshes = captured_render_components::<RenderBillboardMesh>(&app);
+    let images = captured_render_components::<RenderBillboardImage>(&app);
+
+    // Only the visible billboard is extracted
+    assert_eq!(meshes.len(), 1);
+    assert_eq!(meshes[0].0, visible);
+    assert_eq!(meshes[0].1.id, mesh.id());
+    assert_eq!(images.len(), 1);
+    assert_eq!(images[0].1.id, image.id());
+
+    assert_eq!(queued_billboards(&app, camera), vec![visible]);
+    assert!(!queued_billboards(&app, camera).contains(&hidden));
+}
+
+#[test]
+fn sort_bias_orders_billboards_at_the_same_depth() {
+    let Some(mut app) = render_app() else {
+        return;
+    };
+
+    let world = app.world_mut();
+    let camera = spawn_camera(world);
+    let mesh = world
+        .resource_mut::<Assets<Mesh>>()
+        .add(Rectangle::from_size(Vec2::ONE));
+    let image = world.resource_mut::<Assets<Image>>().add(Image::default());
+
+    let mut spawn_billboard = |bias| {
+        world
+            .spawn((
+                BillboardTextureBundle {
+                    mesh: BillboardMeshHandle(mesh.clone()),
+                    texture: BillboardTextureHandle(image.clone()),
+                    ..default()
+                },
+                BillboardSortBias(bias),
+            ))
+            .id()
+    };
+    // Spawned on top first, so the order doesn't come from the entities
+    let above = spawn_billboard(1.0);
+    let below = spawn_billboard(-1.0);
+    let unbiased = spawn_billboard(0.0);
+
+    update_until(&mut app, |app| queued_billboards(app, camera).len() == 3);
+
+    // Drawn back to front, the highest bias last
+    assert_eq!(
+        queued_billboards(&app, camera),
+        vec![below, unbiased, above]
+    );
+}
+
+#[test]
+fn texture_billboards_are_queued_only_for_the_cameras_allowed_to_see_them() {
+    let Some(mut app) = render_app() else {
+        return;
+    };
+
+    let world = app.world_mut();
+    let main_camera = spawn_camera(world);
+    let minimap_camera = spawn_camera(world);
+    world
+        .entity_mut(minimap_camera)
+        .insert(RenderLayers::layer(1));
+    world.get_mut::<Camera>(minimap_camera).unwrap().order = 1;
+
+    let mesh = world
+        .resource_mut::<Assets<Mesh>>()
+        .add(Rectangle::from_size(Vec2::ONE));
+    let image = world.resource_mut::<Assets<Image>>().add(Image::default());
+    let mut spawn_billboard = |layers: RenderLayers, cameras: Option<BillboardCameras>| {
+        let mut billboard = world.spawn((
+            BillboardTextureBundle {
+                mesh: BillboardMeshHandle(mesh.clone()),
+                texture: BillboardTextureHandle(image.clone()),
+                ..default()
+            },
+            layers,
+        ));
+        if let Some(cameras) = cameras {
+            billboard.insert(cameras);
+        }
+        billboard.id()
+    };
+
+    let both_layers = RenderLayers::from_layers(&[0, 1]);
+    let main_only = spawn_billboard(RenderLayers::layer(0), None);
+    let minimap_only = spawn_billboard(RenderLayers::layer(1), None);
+    let main_whitelisted = spawn_billboard(
+        both_layers.clone(),
+        Some(BillboardCameras(vec![main_camera])),
+    );
+    let nowhere = spawn_billboard(both_layers, Some(BillboardCameras::default()));
+
+    update_until(&mut app, |app| {
+        !queued_billboards(app, main_camera).is_empty()
+            && !queued_billboards(app, minimap_camera).is_empty()
+    });
+
+    let mut main_queued = queued_billboards(&app, main_camera);
+    main_queued.sort();
+    assert_eq!(main_queued, vec![main_only, main_whitelisted]);
+    assert_eq!(queued_billboards(&app, minimap_camera), vec![minimap_only]);
+    assert!(!main_queued.contains(&nowhere));
+}
+
+#[test]
+fn billboards_are_square_through_a_non_square_orthographic_view() {
+    let Some(mut app) = render_app() else {
+        return;
+    };
+
+    let world = app.world_mut();
+    let target = render_target(&mut world.resource_mut::<Assets<Image>>());
+    let RenderTarget::Image(image) = target.clone() else {
+        unreachable!("Render targets are images");
+    };
+
+    // An isometric view, 8 pixels per unit, twice as wide as it is tall
+    let camera = world
+        .spawn(Camera3dBundle {
+            camera: Camera {
+                target,
+                viewport: Some(Viewport {
+                    physical_position: UVec2::ZERO,
+                    physical_size: UVec2::new(64, 32),
+                    ..default()
+                }),
+                clear_color: ClearColorConfig::Custom(Color::BLACK),
+                ..default()
+            },
+            projection: OrthographicProjection {
+                scaling_mode: ScalingMode::FixedVertical(4.0),
+                ..default()
+            }
+            .into(),
+            transform: Transform::from_xyz(10., 10., 10.).looking_at(Vec3::ZERO, Vec3::Y),
+            tonemapping: Tonemapping::None,
+            deband_dither: DebandDither::Disabled,
+            ..default()
+        })
+        .id();
+
+    let mesh = world
+        .resource_mut::<Assets<Mesh>>()
+        .add(Rectangle::from_size(Vec2::splat(2.0)));
+    let texture = world.resource_mut::<Assets<Image>>().add(Image::default());
+    world.spawn(BillboardTextureBundle {
+        mesh: BillboardMeshHandle(mesh),
+        texture: BillboardTextureHandle(texture),
+        ..default()
+    });
+
+    update_until(&mut app, |app| pipeline_ready(app, camera));
+    app.update();
+
+    // Bounds of the lit pixels of the viewport
+    let pixels = read_render_target(&app, &image);
+    let (mut min, mut max) = (UVec2::MAX, UVec2::ZERO);
+    for (index, pixel) in pixels.chunks_exact(4).enumerate() {
+        let position = UVec2::new(index as u32 % 64, index as u32 / 64);
+        if position.y < 32 && pixel[..3].iter().all(|&channel| channel > 128) {
+            min = min.min(position);
+            max = max.max(position);
+        }
+    }
+
+    let size = max - min + 1;
+    assert_eq!(size, UVec2::splat(16), "footprint from {min} to {max}");
+}
+
+#[test]
+fn text_billboards_are_extracted_and_queued() {
+    let Some(mut app) = render_app() else {
+        return;
+    };
+
+    let world = app.world_mut();
+    let camera = spawn_camera(world);
+    let font = world.resource::<AssetServer>().load("FiraSans-Regular.ttf");
+
+    let text = world
+        .spawn(BillboardTextBundle {
+            text: Text::from_section(
+                "text",
+                TextStyle {
+                    font,
+                    font_size: 60.0,
+                    color: Color::WHITE,
+                },
+            ),
+            ..default()
+        })
+        .id();
+
+    update_until(&mut app, |app| !queued_billboards(app, camera).is_empty());
+
+    assert_eq!(queued_billboards(&app, camera), vec![text]);
+}
+
+#[test]
+fn every_atlas_group_of_a_text_is_queued() {
+    let Some(mut app) = render_app() else {
+        return;
+    };
+
+    let world = app.world_mut();
+    let camera = spawn_camera(world);
+    let font = world.resource::<AssetServer>().load("FiraSans-Regular.ttf");
+
+    // Two fonts have their own atlases, so the text has two atlas groups
+    let text = world
+        .spawn(BillboardTextBundle {
+            text: Text::from_sections([
+                TextSection::new(
+                    "Fira Sans",
+                    TextStyle {
+                        font,
+                        font_size: 60.0,
+                        color: Color::WHITE,
+                    },
+                ),
+                TextSection::new(
+                    " and the default font",
+                    TextStyle {
+                        font_size: 60.0,
+                        ..default()
+                    },
+                ),
+            ]),
+            ..default()
+        })
+        .id();
+
+    capture_render_components::<RenderBillboardImage>(&mut app);
+
+    update_until(&mut app, |app| queued_billboards(app, camera).len() == 2);
+
+    let handles = app.world().get::<BillboardTextHandles>(text).unwrap();
+    assert_eq!(handles.len(), 2);
+
+    // Each group is queued with its own atlas, the first one on the text entity itself
+    let queued = queued_billboards(&app, camera);
+    let images = captured_render_components::<RenderBillboardImage>(&app);
+    let image_of = |entity| {
+        images
+            .iter()
+            .find(|(image_entity, _)| *image_entity == entity)
+            .map(|(_, image)| image.id)
+    };
+    assert!(queued.contains(&text));
+    assert_eq!(image_of(text), Some(handles[0].image().id()));
+
+    let other = *queued.iter().find(|&&entity| entity != text).unwrap();
+    assert_eq!(image_of(other), Some(handles[1].image().id()));
+    assert_ne!(handles[0].image().id(), handles[1].image().id());
+}
+
+#[test]
+fn texture_array_text_is_a_single_render_item() {
+    let Some(mut app) = render_app() else {
+        return;
+    };
+
+    app.world_mut()
+        .resource_mut::<BillboardTextSettings>()
+        .texture_arrays = true;
+
+    let world = app.world_mut();
+    let camera = spawn_camera(world);
+    let font = world.resource::<AssetServer>().load("FiraSans-Regular.ttf");
+
+    world.spawn(BillboardTextBundle {
+        text: Text::from_sections([
+            TextSection::new(
+                "Fira Sans",
+                TextStyle {
+                    font,
+                    font_size: 60.0,
+                    color: Color::WHITE,
+                },
+            ),
+            TextSection::new(
+                " and the default font",
+                TextStyle {
+                    font_size: 60.0,
+                    ..default()
+                },
+            ),
+        ]),
+        ..default()
+    });
+
+    // The texture array variant of the shader compiles
+    update_until(&mut app, |app| pipeline_ready(app, camera));
+
+    assert_eq!(queued_billboards(&app, camera).len(), 1);
+}
+
+#[test]
+fn batched_texts_sharing_an_atlas_are_a_single_render_item() {
+    let Some(mut app) = render_app() else {
+        return;
+    };
+
+    if !app
+        .sub_app(RenderApp)
+        .world()
+        .resource::<ExtractedBillboardTextBatches>()
+        .supported
+    {
+        return;
+    }
+
+    app.world_mut()
+        .resource_mut::<BillboardTextSettings>()
+        .batching = true;
+
+    let world = app.world_mut();
+    let camera = spawn_camera(world);
+    let font: Handle<Font> = world.resource::<AssetServer>().load("FiraSans-Regular.ttf");
+
+    let mut spawn_text = |value: &str, visibility| {
+        world
+            .spawn(BillboardTextBundle {
+                text: Text::from_section(
+                    value,
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 60.0,
+                        color: Color::WHITE,
+                    },
+                ),
+                visibility,
+                ..default()
+            })
+            .id()
+    };
+    let texts = [
+        spawn_text("ab", Visibility::Inherited),
+        spawn_text("cd", Visibility::Inherited),
+        spawn_text("ef", Visibility::Hidden),
+    ];
+
+    capture_render_components::<BillboardTextBatch>(&mut app);
+
+    update_until(&mut app, |app| {
+        pipeline_ready(app, camera)
+            && !captured_render_components::<BillboardTextBatch>(app).is_empty()
+    });
+
+    let queued = queued_billboards(&app, camera);
+    assert_eq!(queued.len(), 1);
+    assert!(!texts.contains(&queued[0]));
+
+    // Two quads of the two visible texts
+    let batches = captured_render_components::<BillboardTextBatch>(&app);
+    assert_eq!(batches.len(), 1);
+    assert_eq!(batches[0].1.indices.len(), 2 * 2 * 6);
+}
+
+#[test]
+fn batched_texts_share_their_vertices_between_views() {
+    let Some(mut app) = render_app() else {
+        return;
+    };
+
+    if !app
+        .sub_app(RenderApp)
+        .world()
+        .resource::<ExtractedBillboardTextBatches>()
+        .supported
+    {
+        return;
+    }
+
+    app.world_mut()
+        .resource_mut::<BillboardTextSettings>()
+        .batching = true;
+
+    let world = app.world_mut();
+    let main_camera = spawn_camera(world);
+    let other_camera = spawn_camera(world);
+    let font: Handle<Font> = world.resource::<AssetServer>().load("FiraSans-Regular.ttf");
+
+    for value in ["ab", "cd"] {
+        world.spawn(BillboardTextBundle {
+            text: Text::from_section(
+                value,
+                TextStyle {
+                    font: font.clone(),
+                    font_size: 60.0,
+                    color: Color::WHITE,
+                },
+            ),
+            ..default()
+        });
+    }
+
+    capture_render_components::<BillboardTextBatch>(&mut app);
+
+    update_until(&mut app, |app| {
+        pipeline_ready(app, main_camera)
+            && pipeline_ready(app, other_camera)
+            && captured_render_components::<BillboardTextBatch>(app).len() == 2
+    });
+
+    // A batch per view, drawing the same two quads of the two texts
+    let batches = captured_render_components::<BillboardTextBatch>(&app);
+    assert!(batches
+        .iter()
+        .all(|(_, batch)| batch.indices.len() == 2 * 2 * 6));
+
+    let vertex_count = app
+        .sub_app(RenderApp)
+        .world()
+        .resource::<BillboardTextBatches>()
+        .vertex_count();
+    assert_eq!(vertex_count, 2 * 2 * 4);
+}
+
+#[test]
+fn gpu_culled_billboards_are_a_single_render_item() {
+    let Some(mut app) = render_app() else {
+        return;
+    };
+
+    let supported = app
+        .sub_app(RenderApp)
+        .world()
+        .resource::<ExtractedBillboardGpuInstances>()
+        .supported;
+
+    let world = app.world_mut();
+    let camera = spawn_camera(world);
+    let mesh = world
+        .resource_mut::<Assets<Mesh>>()
+        .add(Rectangle::from_size(Vec2::ONE));
+    let image = world.resource_mut::<Assets<Image>>().add(Image::default());
+
+    let billboards = [
+        (-1.0, Visibility::Inherited),
+        (1.0, Visibility::Inherited),
+        (0.0, Visibility::Hidden),
+    ]
+    .map(|(x, visibility)| {
+        world
+            .spawn((
+                BillboardTextureBundle {
+                    mesh: BillboardMeshHandle(mesh.clone()),
+                    texture: BillboardTextureHandle(image.clone()),
+                    transform: Transform::from_xyz(x, 0., 0.),
+                    visibility,
+                    ..default()
+                },
+                BillboardGpuCulling::default(),
+            ))
+            .id()
+    });
+
+    // Both the culling and the drawing shaders compile
+    let culling_ready = |app: &App| {
+        app.sub_app(RenderApp)
+            .world()
+            .resource::<PipelineCache>()
+            .pipelines()
+            .any(|pipeline| {
+                matches!(
+                    (&pipeline.descriptor, &pipeline.state),
+                    (
+                        PipelineDescriptor::ComputePipelineDescriptor(_),
+                        CachedPipelineState::Ok(_)
+                    )
+                )
+            })
+    };
+    update_until(&mut app, |app| {
+        pipeline_ready(app, camera) && (!supported || culling_ready(app))
+    });
+
+    let mut queued = queued_billboards(&app, camera);
+
+    if supported {
+        assert_eq!(queued.len(), 1);
+        assert!(!billboards.contains(&queued[0]));
+
+        let render_world = app.sub_app(RenderApp).world();
+        assert_eq!(
+            render_world
+                .resource::<ExtractedBillboardGpuInstances>()
+                .len(),
+            2
+        );
+    } else {
+        // Drawn one by one instead
+        queued.sort();
+        assert_eq!(queued, billboards[..2]);
+    }
+}
+
+#[test]
+fn gpu_culled_billboards_respect_render_layers() {
+    let Some(mut app) = render_app() else {
+        return;
+    };
+
+    let world = app.world_mut();
+    let main_camera = spawn_camera(world);
+    let minimap_camera = spawn_camera(world);
+    world
+        .entity_mut(minimap_camera)
+        .insert(RenderLayers::layer(1));
+    world.get_mut::<Camera>(minimap_camera).unwrap().order = 1;
+
+    let mesh = world
+        .resource_mut::<Assets<Mesh>>()
+        .add(Rectangle::from_size(Vec2::ONE));
+    let image = world.resource_mut::<Assets<Image>>().add(Image::default());
+    let mut spawn_billboard = |x: f32| {
+        world
+            .spawn((
+                BillboardTextureBundle {
+                    mesh: BillboardMeshHandle(mesh.clone()),
+                    texture: BillboardTextureHandle(image.clone()),
+                    transform: Transform::from_xyz(x, 0., 0.),
+                    ..default()
+                },
+                BillboardGpuCulling::default(),
+            ))
+            .id()
+    };
+
+    let default_layer = spawn_billboard(-1.0);
+    let minimap_layer = spawn_billboard(1.0);
+    world
+        .entity_mut(minimap_layer)
+        .insert(RenderLayers::layer(1));
+
+    update_until(&mut app, |app| {
+        pipeline_ready(app, main_camera) && pipeline_ready(app, minimap_camera)
+    });
+
+    // Drawn one by one, as views can't be filtered per billboard on the GPU
+    assert_eq!(queued_billboards(&app, minimap_camera), vec![minimap_layer]);
+
+    let main_queued = queued_billboards(&app, main_camera);
+    assert_eq!(main_queued.len(), 1);
+    assert!(!main_queued.contains(&minimap_layer));
+    let supported = app
+        .sub_app(RenderApp)
+        .world()
+        .resource::<ExtractedBillboardGpuInstances>()
+        .supported;
+    if !supported {
+        assert_eq!(main_queued, vec![default_layer]);
+    }
+}
+
+#[test]
+fn billboard_instances_are_a_single_render_item() {
+    let Some(mut app) = render_app() else {
+        return;
+    };
+
+    let world = app.world_mut();
+    let camera = spawn_camera(world);
+    let image = world.resource_mut::<Assets<Image>>().add(Image::default());
+
+    let billboard = world
+        .spawn(BillboardInstancesBundle {
+            instances: BillboardInstances {
+                instances: (0..3)
+                    .map(|x| BillboardInstance {
+                        position: Vec3::new(x as f32, 0., 0.),
+                        ..default()
+                    })
+                    .collect(),
+                atlas: None,
+            },
+            texture: BillboardTextureHandle(image),
+            ..default()
+        })
+        .id();
+
+    // The instanced variant of the shader compiles
+    update_until(&mut app, |app| pipeline_ready(app, camera));
+
+    assert_eq!(queued_billboards(&app, camera), vec![billboard]);
+}
+
+#[test]
+fn billboard_instances_are_uploaded_again_when_their_atlas_layout_loads() {
+    let Some(mut app) = render_app() else {
+        return;
+    };
+
+    /// uv rects of the instances of the billboard extracted in the last update.
+    #[derive(Resource, Default)]
+    struct ExtractedUvRects(Option<Vec<[f32; 4]>>);
+
+    let world = app.world_mut();
+    spawn_camera(world);
+    let image = world.resource_mut::<Assets<Image>>().add(Image::default());
+    let layout = world
+        .resource::<Assets<TextureAtlasLayout>>()
+        .reserve_handle();
+
+    let billboard = world
+        .spawn(BillboardInstancesBundle {
+            instances: BillboardInstances {
+                instances: vec![BillboardInstance {
+                    atlas_index: 1,
+                    ..default()
+                }],
+                atlas: Some(layout.clone()),
+            },
+            texture: BillboardTextureHandle(image),
+            ..default()
+        })
+        .id();
+
+    app.sub_app_mut(RenderApp)
+        .init_resource::<ExtractedUvRects>()
+        .add_systems(
+            Render,
+            (move |extracted: Res<ExtractedBillboardInstances>,
+                   mut uv_rects: ResMut<ExtractedUvRects>| {
+                uv_rects.0 = extracted
+                    .get(billboard)
+                    .map(|vertices| vertices.iter().map(|vertex| vertex.uv_rect).collect());
+            })
+            .in_set(RenderSet::Queue),
+        );
+    let extracted_uv_rects = |app: &App| {
+        app.sub_app(RenderApp)
+            .world()
+            .resource::<ExtractedUvRects>()
+            .0
+            .clone()
+    };
+
+    // Without its layout yet, the instance shows the whole texture
+    app.update();
+    assert_eq!(extracted_uv_rects(&app), Some(vec![[0.0, 0.0, 1.0, 1.0]]));
+
+    app.update();
+    assert_eq!(extracted_uv_rects(&app), None);
+
+    let mut atlas = TextureAtlasLayout::new_empty(UVec2::new(4, 2));
+    atlas.add_texture(URect::new(0, 0, 2, 2));
+    atlas.add_texture(URect::new(2, 0, 4, 2));
+    app.world_mut()
+        .resource_mut::<Assets<TextureAtlasLayout>>()
+        .insert(&layout, atlas);
+
+    update_until(&mut app, |app| extracted_uv_rects(app).is_some());
+    assert_eq!(extracted_uv_rects(&app), Some(vec![[0.5, 0.0, 1.0, 1.0]]));
+}
+
+/// Center pixel of a camera with [`BillboardOit`] seeing a red and a blue billboard, half
+/// transparent and overlapping, spawned in the given order.
+fn oit_center_pixel(msaa: Msaa, blue_first: bool) -> Option<[u8; 4]> {
+    let mut app = render_app()?;
+    app.insert_resource(msaa);
+
+    let world = app.world_mut();
+    let target = render_target(&mut world.resource_mut::<Assets<Image>>());
+    let RenderTarget::Image(image) = target.clone() else {
+        unreachable!("Render targets are images");
+    };
+
+    let camera = world
+        .spawn((
+            Camera3dBundle {
+                camera: Camera {
+                    target,
+                    clear_color: ClearColorConfig::Custom(Color::BLACK),
+                    ..default()
+                },
+                transform: Transform::from_xyz(0., 0., 5.).looking_at(Vec3::ZERO, Vec3::Y),
+                tonemapping: Tonemapping::None,
+                deband_dither: DebandDither::Disabled,
+                ..default()
+            },
+            BillboardOit,
+        ))
+        .id();
+
+    let mesh = world
+        .resource_mut::<Assets<Mesh>>()
+        .add(Rectangle::from_size(Vec2::splat(2.0)));
+    let mut spawn_billboard = |color: [u8; 4], z: f32| {
+        let texture = world.resource_mut::<Assets<Image>>().add(Image::new_fill(
+            Extent3d::default(),
+            TextureDimension::D2,
+            &color,
+            TextureFormat::Rgba8UnormSrgb,
+            RenderAssetUsages::all(),
+        ));
+        world.spawn(BillboardTextureBundle {
+            mesh: BillboardMeshHandle(mesh.clone()),
+            texture: BillboardTextureHandle(texture),
+            transform: Transform::from_xyz(0., 0., z),
+            ..default()
+        });
+    };
+
+    let red = ([255, 0, 0, 128], 0.0);
+    let blue = ([0, 0, 255, 128], 0.5);
+    let (first, second) = if blue_first { (blue, red) } else { (red, blue) };
+    spawn_billboard(first.0, first.1);
+    spawn_billboard(second.0, second.1);
+
+    update_until(&mut app, |app| {
+        phase_pipeline_ready::<BillboardOit3d>(app, camera)
+    });
+    app.update();
+
+    let pixels = read_render_target(&app, &image);
+    let center = (32 * 64 + 32) * 4;
+    Some(pixels[center..center + 4].try_into().unwrap())
+}
+
+#[test]
+fn oit_composites_overlapping_billboards_in_any_order() {
+    for msaa in [Msaa::Off, Msaa::Sample4] {
+        let (Some(red_first), Some(blue_first)) =
+            (oit_center_pixel(msaa, false), oit_center_pixel(msaa, true))
+        else {
+            return;
+        };
+
+        // Both billboards show through, in BGRA
+        let [blue, _, red, _] = red_first;
+        assert!(red > 32 && blue > 32, "{msaa:?}: {red_first:?}");
+
+        assert_eq!(red_first, blue_first, "{msaa:?}");
+    }
+}
+
+/// Red channel down the center column of a white billboard crossing a green floor, from the top,
+/// and the backend it was rendered with.
+fn soft_edge_center_column(
+    msaa: Msaa,
+    soft_edge: Option<BillboardSoftEdge>,
+) -> Option<(Vec<u8>, Backend)> {
+    let mut app = render_app()?;
+    app.insert_resource(msaa);
+
+    let world = app.world_mut();
+    let target = render_target(&mut world.resource_mut::<Assets<Image>>());
+    let RenderTarget::Image(image) = target.clone() else {
+        unreachable!("Render targets are images");
+    };
+
+    let camera = world
+        .spawn((
+            Camera3dBundle {
+                camera: Camera {
+                    target,
+                    clear_color: ClearColorConfig::Custom(Color::BLACK),
+                    ..default()
+                },
+                transform: Transform::from_xyz(0., 2., 5.).looking_at(Vec3::ZERO, Vec3::Y),
+                tonemapping: Tonemapping::None,
+                deband_dither: DebandDither::Disabled,
+                ..default()
+            },
+            DepthPrepass,
+        ))
+        .id();
+
+    let floor_mesh = world
+        .resource_mut::<Assets<Mesh>>()
+        .add(Plane3d::default().mesh().size(20., 20.));
+    let floor_material = world
+        .resource_mut::<Assets<StandardMaterial>>()
+        .add(StandardMaterial {
+            base_color: Color::srgb(0., 1., 0.),
+            unlit: true,
+            ..default()
+        });
+    world.spawn(PbrBundle {
+        mesh: floor_mesh,
+        material: floor_material,
+        ..default()
+    });
+
+    let mesh = world
+        .resource_mut::<Assets<Mesh>>()
+        .add(Rectangle::from_size(Vec2::splat(2.0)));
+    let texture = world.resource_mut::<Assets<Image>>().add(Image::default());
+    let mut billboard = world.spawn(BillboardTextureBundle {
+        mesh: BillboardMeshHandle(mesh),
+        texture: BillboardTextureHandle(texture),
+        ..default()
+    });
+    if let Some(soft_edge) = soft_edge {
+        billboard.insert(soft_edge);
+    }
+
+    update_until(&mut app, |app| pipeline_ready(app, camera));
+    app.update();
+
+    let pixels = read_render_target(&app, &image);
+    let column = (0..64).map(|row| pixels[(row * 64 + 32) * 4 + 2]).collect();
+    let backend = app
+        .sub_app(RenderApp)
+        .world()
+        .resource::<RenderAdapterInfo>()
+        .backend;
+    Some((column, backend))
+}
+
+#[test]
+fn soft_edge_fades_billboards_near_the_geometry_they_cross() {
+    for msaa in [Msaa::Off, Msaa::Sample4] {
+        let (Some((hard, _)), Some((soft, backend))) = (
+            soft_edge_center_column(msaa, None),
+            soft_edge_center_column(msaa, Some(BillboardSoftEdge(1.0))),
+        ) else {
+            return;
+        };
+
+        // The GL backend can't copy depth textures, so bevy's depth prepass stays empty there and
+        // nothing is drawn with MSAA. The fade is then unverified on the GPU, only the pipelines
+        // compiling are checked, while tests/soft_edge.rs checks the fade factor on the CPU
+        let copies_depth = backend != Backend::Gl;
+        if !copies_depth && msaa != Msaa::Off {
+            eprintln!("Depth prepass with MSAA isn't drawn on the GL backend, skipping the check");
+            continue;
+        }
+
+        // Rows where the billboard is above the floor, the lowest one touching it
+        let rows: Vec<_> = (0..64).filter(|&row| hard[row] == 255).collect();
+        let (Some(&top), Some(&bottom)) = (rows.first(), rows.last()) else {
+            panic!("{msaa:?}: the billboard should be drawn, {hard:?}");
+        };
+
+        // Far from the floor the billboard is opaque
+        assert_eq!(soft[top], 255, "{msaa:?}: {soft:?}");
+
+        if !copies_depth {
+            eprintln!("Depth prepass is empty on the GL backend, skipping the fade check");
+            continue;
+        }
+
+        // Closer to the floor it fades out
+        assert!(soft[bottom] < 128, "{msaa:?}: {soft:?}");
+        assert!(
+            rows.windows(2).all(|rows| soft[rows[0]] >= soft[rows[1]]),
+            "{msaa:?}: {soft:?}"
+        );
+    }
+}
@@ -0,0 +1,251 @@
+mod common;
+
+use bevy::ecs::system::{RunSystemOnce, SystemState};
+use bevy::prelude::*;
+use bevy::render::camera::{camera_system, ManualTextureViews, RenderTarget};
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::render::view::VisibleEntities;
+use bevy::window::{WindowCreated, WindowResized, WindowScaleFactorChanged};
+use bevy_mod_billboard::declutter::{
+    declutter_billboards, BillboardDeclutter, BillboardDeclutterMode, BillboardDeclutterPriority,
+    BillboardDeclutterState,
+};
+use bevy_mod_billboard::screen::BillboardScreenRects;
+use bevy_mod_billboard::Billboard;
+use common::{spawn_billboard, spawn_camera_seeing};
+use std::time::Duration;
+
+const EPSILON: f32 = 1e-3;
+
+/// A camera rendering to an 800x600 image from `(0, 0, 5)`, seeing `billboards`.
+fn spawn_camera(world: &mut World, billboards: &[Entity]) -> Entity {
+    world.init_resource::<Events<WindowResized>>();
+    world.init_resource::<Events<WindowCreated>>();
+    world.init_resource::<Events<WindowScaleFactorChanged>>();
+    world.init_resource::<Events<AssetEvent<Image>>>();
+    world.init_resource::<Assets<Image>>();
+    world.init_resource::<ManualTextureViews>();
+
+    let image = world.resource_mut::<Assets<Image>>().add(Image::new_fill(
+        Extent3d {
+            width: 800,
+            height: 600,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &[0; 4],
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::all(),
+    ));
+
+    let camera = Camera {
+        target: RenderTarget::Image(image),
+        ..default()
+    };
+    let transform = Transform::from_xyz(0., 0., 5.).looking_at(Vec3::ZERO, Vec3::Y);
+    let camera = spawn_camera_seeing(world, camera, transform, billboards);
+    world
+        .entity_mut(camera)
+        .insert(PerspectiveProjection::default());
+
+    // Computes the projection of the camera for its target
+    world.run_system_once(camera_system::<PerspectiveProjection>);
+    camera
+}
+
+#[test]
+fn screen_rects_match_the_projection() {
+    let mut world = World::new();
+
+    let centered = spawn_billboard(&mut world, Transform::IDENTITY);
+    let behind = spawn_billboard(&mut world, Transform::from_xyz(0., 0., 10.));
+    let camera = spawn_camera(&mut world, &[centered]);
+
+    let mut state = SystemState::<BillboardScreenRects>::new(&mut world);
+    let screen_rects = state.get(&world);
+
+    // 2 units tall at a distance of 5, with the default vertical field of view
+    let fov = PerspectiveProjection::default().fov;
+    let size = 2. / (2. * 5. * (fov / 2.).tan()) * 600.;
+
+    let rect = screen_rects.get(camera, centered).unwrap();
+    assert!(rect.center().abs_diff_eq(Vec2::new(400., 300.), EPSILON));
+    assert!(rect.size().abs_diff_eq(Vec2::splat(size), EPSILON));
+
+    assert_eq!(screen_rects.get(camera, behind), None);
+
+    // Only the billboards the camera sees
+    let rects: Vec<_> = screen_rects.iter(camera).collect();
+    assert_eq!(rects, vec![(centered, rect)]);
+}
+
+/// A decluttering camera over a billboard at the origin of priority 1, and billboards of
+/// priority 0 overlapping it.
+fn declutter_world(
+    mode: BillboardDeclutterMode,
+    overlapping: usize,
+    transition_speed: f32,
+) -> (World, Entity, Vec<Entity>) {
+    let mut world = World::new();
+    world.init_resource::<Time>();
+
+    let mut billboards = vec![spawn_billboard(&mut world, Transform::IDENTITY)];
+    world
+        .entity_mut(billboards[0])
+        .insert(BillboardDeclutterPriority(1));
+    for index in 0..overlapping {
+        let billboard =
+            spawn_billboard(&mut world, Transform::from_xyz(0.5, 0.1 * index as f32, 0.));
+        world
+            .entity_mut(billboard)
+            .insert(BillboardDeclutterPriority(0));
+        billboards.push(billboard);
+    }
+
+    let camera = spawn_camera(&mut world, &billboards);
+    world.entity_mut(camera).insert(BillboardDeclutter {
+        mode,
+        max_offset: f32::INFINITY,
+        transition_speed,
+        ..default()
+    });
+
+    (world, camera, billboards)
+}
+
+/// Declutters the billboards of [`declutter_world`] right away, returning their states.
+fn declutter(mode: BillboardDeclutterMode, overlapping: usize) -> (World, Entity, Vec<Entity>) {
+    let (mut world, camera, billboards) = declutter_world(mode, overlapping, f32::INFINITY);
+    world.run_system_once(declutter_billboards);
+    (world, camera, billboards)
+}
+
+fn state(world: &World, billboard: Entity) -> BillboardDeclutterState {
+    *world.get::<BillboardDeclutterState>(billboard).unwrap()
+}
+
+#[test]
+fn declutter_hides_lower_priority_billboards() {
+    let (world, camera, billboards) = declutter(BillboardDeclutterMode::Hide, 1);
+
+    assert_eq!(state(&world, billboards[0]).alpha(), 1.);
+    assert_eq!(state(&world, billboards[1]).alpha(), 0.);
+    assert_eq!(state(&world, billboards[1]).offset(), Vec3::ZERO);
+
+    // Faded out billboards aren't drawn anymore
+    let visible_entities = world.get::<VisibleEntities>(camera).unwrap();
+    assert_eq!(visible_entities.get::<With<Billboard>>(), &billboards[..1]);
+}
+
+#[test]
+fn declutter_nudges_lower_priority_billboards_up() {
+    let (mut world, camera, billboards) = declutter(BillboardDeclutterMode::Nudge, 1);
+
+    let anchor = state(&world, billboards[0]);
+    assert_eq!(anchor.screen_offset(), Vec2::ZERO);
+
+    let nudged = state(&world, billboards[1]);
+    assert_eq!(nudged.alpha(), 1.);
+    assert_eq!(nudged.screen_offset().x, 0.);
+    assert!(nudged.screen_offset().y < 0.);
+    assert!(nudged.offset().y > 0.);
+
+    // Screen rects follow the billboards where they are drawn, just above each other
+    let mut state = SystemState::<BillboardScreenRects>::new(&mut world);
+    let screen_rects = state.get(&world);
+    let anchor_rect = screen_rects.get(camera, billboards[0]).unwrap();
+    let nudged_rect = screen_rects.get(camera, billboards[1]).unwrap();
+    let margin = BillboardDeclutter::default().margin;
+    assert!((anchor_rect.min.y - margin - nudged_rect.max.y).abs() < 0.1);
+}
+
+#[test]
+fn declutter_stacks_lower_priority_billboards_in_a_column() {
+    let (world, _, billboards) = declutter(BillboardDeclutterMode::Stack, 2);
+
+    let first = state(&world, billboards[1]).screen_offset();
+    let second = state(&world, billboards[2]).screen_offset();
+
+    // Centered on the billboard they overlapped, one above the other
+    assert!(first.x < 0. && second.x < 0.);
+    assert!(second.y < first.y && first.y < 0.);
+}
+
+#[test]
+fn declutter_hides_stacked_billboards_landing_on_a_neighbor() {
+    let (mut world, camera, billboards) =
+        declutter_world(BillboardDeclutterMode::Stack, 1, f32::INFINITY);
+
+    // Just above the billboard at the origin, without overlapping it
+    let neighbor = spawn_billboard(&mut world, Transform::from_xyz(0., 2.2, 0.));
+    world
+        .entity_mut(neighbor)
+        .insert(BillboardDeclutterPriority(1));
+    world
+        .get_mut::<VisibleEntities>(camera)
+        .unwrap()
+        .get_mut::<With<Billboard>>()
+        .push(neighbor);
+
+    world.run_system_once(declutter_billboards);
+
+    assert_eq!(state(&world, billboards[0]).screen_offset(), Vec2::ZERO);
+    assert_eq!(state(&world, neighbor).screen_offset(), Vec2::ZERO);
+    assert_eq!(state(&world, billboards[1]).alpha(), 0.);
+}
+
+#[test]
+fn declutter_transitions_smoothly_then_snaps() {
+    let (mut world, _, billboards) = declutter_world(BillboardDeclutterMode::Nudge, 1, 10.);
+    let step = |world: &mut World| {
+        world
+            .resource_mut::<Time>()
+            .advance_by(Duration::from_millis(50));
+        world.run_system_once(declutter_billboards);
+        state(world, billboards[1]).screen_offset()
+    };
+
+    let (settled, _, _) = declutter(BillboardDeclutterMode::Nudge, 1);
+    let target = state(&settled, billboards[1]).screen_offset();
+    assert!(target.y < 0.);
+
+    // A fraction of the way there after one step, closer after every other
+    let first = step(&mut world);
+    assert!(first.y < 0. && first.y > target.y);
+    let second = step(&mut world);
+    assert!(second.y < first.y && second.y > target.y);
+
+    let mut offset = second;
+    for _ in 0..20 {
+        offset = step(&mut world);
+    }
+    assert_eq!(offset, target);
+}
+
+#[test]
+fn declutter_fades_out_smoothly_then_snaps() {
+    let (mut world, _, billboards) = declutter_world(BillboardDeclutterMode::Hide, 1, 10.);
+    let mut step = || {
+        world
+            .resource_mut::<Time>()
+            .advance_by(Duration::from_millis(50));
+        world.run_system_once(declutter_billboards);
+        state(&world, billboards[1]).alpha()
+    };
+
+    let first = step();
+    assert!(first > 0. && first < 1.);
+    let second = step();
+    assert!(second > 0. && second < first);
+
+    // Stops once fully faded out, after which it isn't drawn anymore
+    let mut alpha = second;
+    for _ in 0..20 {
+        alpha = step();
+        if alpha == 0. {
+            break;
+        }
+    }
+    assert_eq!(alpha, 0.);
+}
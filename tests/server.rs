@@ -0,0 +1,20 @@
+mod common;
+
+use bevy::prelude::*;
+use bevy::render::RenderApp;
+use bevy_mod_billboard::prelude::*;
+use common::main_world_app;
+
+#[test]
+fn plugin_builds_without_render_app() {
+    let mut app = main_world_app();
+    assert!(app.get_sub_app(RenderApp).is_none());
+
+    let registry = app.world().resource::<AppTypeRegistry>().read();
+    assert!(registry.contains(std::any::TypeId::of::<BillboardTextBounds>()));
+    assert!(registry.contains(std::any::TypeId::of::<BillboardCameras>()));
+    drop(registry);
+
+    app.world_mut().spawn(BillboardTextBundle::default());
+    app.update();
+}
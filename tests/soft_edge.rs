@@ -0,0 +1,73 @@
+use bevy::prelude::*;
+use bevy::render::camera::CameraProjection;
+use bevy_mod_billboard::math::{soft_edge_fade, view_distance};
+
+const EPSILON: f32 = 1e-4;
+
+fn projections() -> [Mat4; 2] {
+    [
+        PerspectiveProjection::default().get_clip_from_view(),
+        OrthographicProjection {
+            near: -100.0,
+            far: 100.0,
+            ..default()
+        }
+        .get_clip_from_view(),
+    ]
+}
+
+/// Depth buffer value of a point `distance` in front of the view.
+fn depth(clip_from_view: &Mat4, distance: f32) -> f32 {
+    let clip = *clip_from_view * Vec4::new(0.0, 0.0, -distance, 1.0);
+    clip.z / clip.w
+}
+
+#[test]
+fn view_distance_undoes_the_projection() {
+    for clip_from_view in projections() {
+        let view_from_clip = clip_from_view.inverse();
+
+        for distance in [0.5, 1.0, 10.0, 50.0] {
+            let depth = depth(&clip_from_view, distance);
+            let computed = view_distance(&view_from_clip, depth);
+            assert!(
+                (computed - distance).abs() < EPSILON * distance,
+                "{distance} != {computed}"
+            );
+        }
+    }
+}
+
+#[test]
+fn soft_edge_fades_linearly_towards_the_scene() {
+    for clip_from_view in projections() {
+        let view_from_clip = clip_from_view.inverse();
+        let scene = depth(&clip_from_view, 10.0);
+        let fade = |distance| {
+            soft_edge_fade(
+                &view_from_clip,
+                scene,
+                depth(&clip_from_view, distance),
+                2.0,
+            )
+        };
+
+        assert!((fade(7.0) - 1.0).abs() < EPSILON);
+        assert!((fade(8.0) - 1.0).abs() < EPSILON);
+        assert!((fade(9.0) - 0.5).abs() < EPSILON);
+        assert!((fade(9.5) - 0.25).abs() < EPSILON);
+        assert!(fade(10.0).abs() < EPSILON);
+        // Behind the scene the depth test discards the fragment, the fade doesn't go negative
+        assert_eq!(fade(11.0), 0.0);
+    }
+}
+
+#[test]
+fn no_soft_edge_leaves_billboards_opaque() {
+    let clip_from_view = PerspectiveProjection::default().get_clip_from_view();
+    let view_from_clip = clip_from_view.inverse();
+    let scene = depth(&clip_from_view, 10.0);
+
+    let fade = soft_edge_fade(&view_from_clip, scene, depth(&clip_from_view, 10.0), 0.0);
+    assert_eq!(fade, 1.0);
+}
@@ -0,0 +1,134 @@
+mod common;
+
+use bevy::prelude::*;
+use bevy_mod_billboard::prelude::*;
+use bevy_mod_billboard::text::BillboardTextHandles;
+use common::{main_world_app, render_target, update_until};
+
+fn is_laid_out(app: &App, entity: Entity) -> bool {
+    !app.world()
+        .get::<BillboardTextHandles>(entity)
+        .unwrap()
+        .is_empty()
+}
+
+fn glyph_count(app: &App, entity: Entity) -> usize {
+    let handles = app.world().get::<BillboardTextHandles>(entity).unwrap();
+    let meshes = app.world().resource::<Assets<Mesh>>();
+
+    handles
+        .iter()
+        .map(|group| meshes.get(group.mesh()).unwrap().count_vertices() / 4)
+        .sum()
+}
+
+fn spawn_text(app: &mut App, font: &Handle<Font>, translation: Vec3) -> Entity {
+    app.world_mut()
+        .spawn(BillboardTextBundle {
+            text: Text::from_section(
+                "text",
+                TextStyle {
+                    font: font.clone(),
+                    ..default()
+                },
+            ),
+            transform: Transform::from_translation(translation).with_scale(Vec3::splat(0.01)),
+            ..default()
+        })
+        .id()
+}
+
+fn setup(budget: BillboardTextBudget) -> (App, Handle<Font>) {
+    let mut app = main_world_app();
+    app.world_mut()
+        .resource_mut::<BillboardTextSettings>()
+        .budget = budget;
+
+    let world = app.world_mut();
+    let target = render_target(&mut world.resource_mut::<Assets<Image>>());
+    world.spawn(Camera3dBundle {
+        camera: Camera {
+            target,
+            ..default()
+        },
+        ..default()
+    });
+
+    let font = world.resource::<AssetServer>().load("FiraSans-Regular.ttf");
+
+    (app, font)
+}
+
+#[test]
+fn budget_lays_out_nearest_texts_first() {
+    let (mut app, font) = setup(BillboardTextBudget::Texts(2));
+
+    update_until(&mut app, |app| {
+        app.world().resource::<Assets<Font>>().contains(&font)
+    });
+
+    let texts = [4.0, 1.0, 3.0, 2.0]
+        .map(|distance| spawn_text(&mut app, &font, Vec3::new(0.0, 0.0, -distance)));
+
+    app.update();
+    assert_eq!(
+        texts.map(|text| is_laid_out(&app, text)),
+        [false, true, false, true]
+    );
+
+    app.update();
+    assert!(texts.iter().all(|&text| is_laid_out(&app, text)));
+}
+
+#[test]
+fn budget_lays_out_visible_texts_first() {
+    let (mut app, font) = setup(BillboardTextBudget::Unlimited);
+
+    // Hidden, but nearest
+    let hidden = spawn_text(&mut app, &font, Vec3::new(0.0, 0.0, -1.0));
+    let visible = spawn_text(&mut app, &font, Vec3::new(0.0, 0.0, -5.0));
+    app.world_mut()
+        .entity_mut(hidden)
+        .insert(Visibility::Hidden);
+
+    update_until(&mut app, |app| {
+        is_laid_out(app, hidden) && is_laid_out(app, visible)
+    });
+
+    app.world_mut()
+        .resource_mut::<BillboardTextSettings>()
+        .budget = BillboardTextBudget::Texts(1);
+
+    for text in [hidden, visible] {
+        app.world_mut().get_mut::<Text>(text).unwrap().sections[0].value = "changed".into();
+    }
+
+    app.update();
+    assert_eq!(glyph_count(&app, visible), "changed".len());
+    assert_eq!(glyph_count(&app, hidden), "text".len());
+
+    app.update();
+    assert_eq!(glyph_count(&app, hidden), "changed".len());
+}
+
+#[test]
+fn texts_waiting_for_their_font_do_not_spend_the_budget() {
+    let (mut app, font) = setup(BillboardTextBudget::Texts(1));
+
+    update_until(&mut app, |app| {
+        app.world().resource::<Assets<Font>>().contains(&font)
+    });
+
+    // Nearest, so it's tried first every update, but its font never loads
+    let never_loaded = Handle::weak_from_u128(0x6a2f_10c4_9e3b_4d52_8c1e_7f0a_b5d3_e9c1);
+    let waiting = spawn_text(&mut app, &never_loaded, Vec3::new(0.0, 0.0, -1.0));
+    let texts =
+        [2.0, 3.0].map(|distance| spawn_text(&mut app, &font, Vec3::new(0.0, 0.0, -distance)));
+
+    app.update();
+    assert_eq!(texts.map(|text| is_laid_out(&app, text)), [true, false]);
+
+    app.update();
+    assert!(texts.iter().all(|&text| is_laid_out(&app, text)));
+    assert!(!is_laid_out(&app, waiting));
+}
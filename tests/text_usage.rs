@@ -1,7 +1,23 @@
+mod common;
+
+use bevy::ecs::system::RunSystemOnce;
 use bevy::prelude::*;
+use bevy::render::mesh::VertexAttributeValues;
+use bevy::text::{
+    BreakLineOn, FontAtlasSets, Text2dBounds, TextPipeline, TextSettings, YAxisOrientation,
+};
 use bevy_mod_billboard::prelude::*;
+use bevy_mod_billboard::text::{
+    BillboardTextHandles, BillboardTextMeshStats, ATTRIBUTE_ATLAS_LAYER,
+};
+use common::{main_world_app, update_until};
+
+fn billboard_text_handles(app: &mut App) -> Vec<BillboardTextHandles> {
+    let world = app.world_mut();
+    let mut query = world.query::<&BillboardTextHandles>();
+    query.iter(world).cloned().collect()
+}
 
-// TODO: Doesn't work yet. WinitPlugin is incompatible with integration tests.
 #[test]
 fn text_binding_compatible_with_ui() {
     fn setup_scene(mut commands: Commands, asset_server: Res<AssetServer>) {
@@ -23,8 +39,523 @@ fn text_binding_compatible_with_ui() {
         commands.spawn(TextBundle::from_section("b", style));
     }
 
-    App::new()
-        .add_plugins((DefaultPlugins, BillboardPlugin))
-        .add_systems(Startup, setup_scene)
-        .run();
+    let mut app = main_world_app();
+    app.add_systems(Startup, setup_scene);
+
+    update_until(&mut app, |app| {
+        billboard_text_handles(app)
+            .iter()
+            .any(|handles| !handles.is_empty())
+    });
+
+    let handles = billboard_text_handles(&mut app);
+    assert_eq!(handles.len(), 1);
+    assert_eq!(handles[0].len(), 1);
+
+    let meshes = app.world().resource::<Assets<Mesh>>();
+    let mesh = meshes.get(handles[0][0].mesh()).unwrap();
+
+    // A single quad for the single glyph
+    assert_eq!(mesh.count_vertices(), 4);
+}
+
+fn text_errors(app: &App) -> Vec<Entity> {
+    app.world()
+        .resource::<Events<BillboardTextError>>()
+        .iter_current_update_events()
+        .map(|error| error.entity)
+        .collect()
+}
+
+#[test]
+fn missing_font_falls_back_after_error() {
+    let mut app = main_world_app();
+
+    let fallback: Handle<Font> = app
+        .world()
+        .resource::<AssetServer>()
+        .load("FiraSans-Regular.ttf");
+    let missing: Handle<Font> = app.world().resource::<AssetServer>().load("missing.ttf");
+
+    update_until(&mut app, |app| {
+        app.world().resource::<Assets<Font>>().contains(&fallback)
+    });
+
+    app.world_mut()
+        .resource_mut::<BillboardTextSettings>()
+        .fallback_font = Some(fallback);
+
+    let text = app
+        .world_mut()
+        .spawn(BillboardTextBundle {
+            text: Text::from_section(
+                "a",
+                TextStyle {
+                    font: missing,
+                    ..default()
+                },
+            ),
+            ..default()
+        })
+        .id();
+
+    update_until(&mut app, |app| !text_errors(app).is_empty());
+
+    assert_eq!(text_errors(&app), vec![text]);
+    assert_eq!(
+        app.world().get::<BillboardTextHandles>(text).unwrap().len(),
+        1
+    );
+}
+
+#[test]
+fn font_retries_are_limited() {
+    let mut app = main_world_app();
+
+    app.world_mut()
+        .resource_mut::<BillboardTextSettings>()
+        .retry = BillboardTextRetry::Updates(3);
+
+    // Never loaded, so this counts as still loading
+    let text = app
+        .world_mut()
+        .spawn(BillboardTextBundle {
+            text: Text::from_section(
+                "a",
+                TextStyle {
+                    font: Handle::weak_from_u128(5861620375120651291),
+                    ..default()
+                },
+            ),
+            ..default()
+        })
+        .id();
+
+    // The first attempt and three retries
+    for _ in 0..3 {
+        app.update();
+        assert!(text_errors(&app).is_empty());
+    }
+
+    app.update();
+
+    assert_eq!(text_errors(&app), vec![text]);
+    assert!(app
+        .world()
+        .get::<BillboardTextHandles>(text)
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn changed_text_reuses_mesh() {
+    let mut app = main_world_app();
+
+    let font = app
+        .world()
+        .resource::<AssetServer>()
+        .load("FiraSans-Regular.ttf");
+    let text = app
+        .world_mut()
+        .spawn(BillboardTextBundle {
+            text: Text::from_section("a", TextStyle { font, ..default() }),
+            ..default()
+        })
+        .id();
+
+    update_until(&mut app, |app| {
+        !app.world()
+            .get::<BillboardTextHandles>(text)
+            .unwrap()
+            .is_empty()
+    });
+
+    let mesh = app.world().get::<BillboardTextHandles>(text).unwrap()[0]
+        .mesh()
+        .clone();
+
+    app.world_mut().get_mut::<Text>(text).unwrap().sections[0].value = "bc".to_string();
+    app.update();
+
+    let handles = app.world().get::<BillboardTextHandles>(text).unwrap();
+    assert_eq!(handles.len(), 1);
+    assert_eq!(*handles[0].mesh(), mesh);
+
+    let meshes = app.world().resource::<Assets<Mesh>>();
+    assert_eq!(meshes.get(&mesh).unwrap().count_vertices(), 8);
+
+    let stats = app.world().resource::<BillboardTextMeshStats>();
+    assert_eq!(stats.allocated, 1);
+    assert_eq!(stats.reused, 1);
+}
+
+fn text_mesh(app: &App, entity: Entity) -> &Mesh {
+    let handles = app.world().get::<BillboardTextHandles>(entity).unwrap();
+    app.world()
+        .resource::<Assets<Mesh>>()
+        .get(handles[0].mesh())
+        .unwrap()
+}
+
+#[test]
+fn changed_characters_are_patched_like_a_full_layout() {
+    let mut app = main_world_app();
+
+    let font: Handle<Font> = app
+        .world()
+        .resource::<AssetServer>()
+        .load("FiraSans-Regular.ttf");
+    let spawn_text = |app: &mut App, value: &str| {
+        app.world_mut()
+            .spawn(BillboardTextBundle {
+                text: Text::from_section(
+                    value,
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 60.0,
+                        color: Color::WHITE,
+                    },
+                ),
+                ..default()
+            })
+            .id()
+    };
+
+    // FiraSans has proportional digits, but these pairs of letters share an advance: b and p,
+    // d and q, h and n
+    let counter = spawn_text(&mut app, "Code: bdh");
+    // Lays out the new glyphs, so they are in the font atlas already
+    let expected = spawn_text(&mut app, "Code: pqn");
+
+    update_until(&mut app, |app| {
+        !app.world()
+            .get::<BillboardTextHandles>(expected)
+            .unwrap()
+            .is_empty()
+    });
+
+    app.world_mut().get_mut::<Text>(counter).unwrap().sections[0].value = "Code: pqn".to_string();
+    app.update();
+
+    assert_eq!(app.world().resource::<BillboardTextMeshStats>().patched, 1);
+
+    for attribute in [Mesh::ATTRIBUTE_POSITION.id, Mesh::ATTRIBUTE_UV_0.id] {
+        assert_eq!(
+            text_mesh(&app, counter)
+                .attribute(attribute)
+                .unwrap()
+                .get_bytes(),
+            text_mesh(&app, expected)
+                .attribute(attribute)
+                .unwrap()
+                .get_bytes(),
+        );
+    }
+
+    // A different advance needs a full relayout
+    app.world_mut().get_mut::<Text>(counter).unwrap().sections[0].value = "Code: pWn".to_string();
+    app.update();
+
+    assert_eq!(app.world().resource::<BillboardTextMeshStats>().patched, 1);
+}
+
+#[test]
+fn patched_glyphs_match_a_relayout_at_fractional_pens() {
+    let mut app = main_world_app();
+
+    let font: Handle<Font> = app
+        .world()
+        .resource::<AssetServer>()
+        .load("FiraSans-Regular.ttf");
+    // Centered lines of an odd font size put the pens between pixels
+    let spawn_text = |app: &mut App, value: &str| {
+        app.world_mut()
+            .spawn(BillboardTextBundle {
+                text: Text::from_section(
+                    value,
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 41.3,
+                        color: Color::WHITE,
+                    },
+                )
+                .with_justify(JustifyText::Center),
+                ..default()
+            })
+            .id()
+    };
+
+    let counter = spawn_text(&mut app, "Time: bd\nhbd");
+    let expected = spawn_text(&mut app, "Time: pq\nnpq");
+
+    update_until(&mut app, |app| {
+        !app.world()
+            .get::<BillboardTextHandles>(expected)
+            .unwrap()
+            .is_empty()
+    });
+
+    app.world_mut().get_mut::<Text>(counter).unwrap().sections[0].value =
+        "Time: pq\nnpq".to_string();
+    app.update();
+
+    assert_eq!(app.world().resource::<BillboardTextMeshStats>().patched, 1);
+
+    let positions = |entity| {
+        let Some(VertexAttributeValues::Float32x3(positions)) =
+            text_mesh(&app, entity).attribute(Mesh::ATTRIBUTE_POSITION)
+        else {
+            panic!("Text meshes have 3D positions");
+        };
+        positions.clone()
+    };
+    let (patched, laid_out) = (positions(counter), positions(expected));
+    assert_eq!(patched.len(), laid_out.len());
+    for (patched, laid_out) in patched.iter().zip(&laid_out) {
+        assert!(
+            Vec3::from(*patched).abs_diff_eq(Vec3::from(*laid_out), 1e-4),
+            "{patched:?} != {laid_out:?}"
+        );
+    }
+}
+
+#[test]
+fn texture_arrays_draw_several_atlases_as_one_mesh() {
+    let mut app = main_world_app();
+
+    app.world_mut()
+        .resource_mut::<BillboardTextSettings>()
+        .texture_arrays = true;
+
+    let font = app
+        .world()
+        .resource::<AssetServer>()
+        .load("FiraSans-Regular.ttf");
+
+    // Fira Sans and the default font have atlases of their own
+    let text = app
+        .world_mut()
+        .spawn(BillboardTextBundle {
+            text: Text::from_sections([
+                TextSection::new("ab", TextStyle { font, ..default() }),
+                TextSection::new("c", TextStyle::default()),
+            ]),
+            ..default()
+        })
+        .id();
+
+    update_until(&mut app, |app| {
+        !app.world()
+            .get::<BillboardTextHandles>(text)
+            .unwrap()
+            .is_empty()
+    });
+
+    let handles = app.world().get::<BillboardTextHandles>(text).unwrap();
+    assert_eq!(handles.len(), 1);
+
+    let image = app
+        .world()
+        .resource::<Assets<Image>>()
+        .get(handles[0].image())
+        .unwrap();
+    assert_eq!(image.texture_descriptor.size.depth_or_array_layers, 2);
+
+    let layers = text_mesh(&app, text)
+        .attribute(ATTRIBUTE_ATLAS_LAYER)
+        .unwrap()
+        .get_bytes();
+    let layers: Vec<u32> = layers
+        .chunks(4)
+        .map(|layer| u32::from_ne_bytes(layer.try_into().unwrap()))
+        .step_by(4)
+        .collect();
+    assert_eq!(layers, [0, 0, 1]);
+}
+
+/// Glyph centers of a text laid out by bevy_text, relative to the center of the text, which is
+/// where billboard texts are anchored by default.
+fn text_pipeline_glyph_centers(app: &mut App, text: Text, mut bounds: Vec2) -> Vec<Vec2> {
+    // Unwrapped texts are laid out without a width, as by `Text2d`
+    if text.linebreak_behavior == BreakLineOn::NoWrap {
+        bounds.x = f32::INFINITY;
+    }
+
+    let info = app.world_mut().run_system_once(
+        move |fonts: Res<Assets<Font>>,
+              mut text_pipeline: ResMut<TextPipeline>,
+              mut font_atlas_sets: ResMut<FontAtlasSets>,
+              mut texture_atlases: ResMut<Assets<TextureAtlasLayout>>,
+              mut images: ResMut<Assets<Image>>,
+              text_settings: Res<TextSettings>| {
+            text_pipeline
+                .queue_text(
+                    &fonts,
+                    &text.sections,
+                    1.0,
+                    text.justify,
+                    text.linebreak_behavior,
+                    bounds,
+                    &mut font_atlas_sets,
+                    &mut texture_atlases,
+                    &mut images,
+                    &text_settings,
+                    YAxisOrientation::BottomToTop,
+                )
+                .unwrap()
+        },
+    );
+
+    let mut centers: Vec<_> = info
+        .glyphs
+        .iter()
+        .map(|glyph| glyph.position - info.logical_size / 2.0)
+        .collect();
+    centers.sort_by(|a, b| a.to_array().partial_cmp(&b.to_array()).unwrap());
+    centers
+}
+
+/// Glyph quad centers of a billboard text, over the meshes of all of its font sizes.
+fn billboard_glyph_centers(app: &App, entity: Entity) -> Vec<Vec2> {
+    let handles = app.world().get::<BillboardTextHandles>(entity).unwrap();
+
+    let mut centers = Vec::new();
+    for group in handles.iter() {
+        let mesh = app.world().resource::<Assets<Mesh>>().get(group.mesh());
+        let Some(VertexAttributeValues::Float32x3(positions)) =
+            mesh.unwrap().attribute(Mesh::ATTRIBUTE_POSITION)
+        else {
+            panic!("Text meshes have positions");
+        };
+
+        centers.extend(
+            positions
+                .chunks(4)
+                .map(|quad| (Vec2::from_slice(&quad[0]) + Vec2::from_slice(&quad[2])) / 2.0),
+        );
+    }
+    centers.sort_by(|a, b| a.to_array().partial_cmp(&b.to_array()).unwrap());
+    centers
+}
+
+fn spawn_shaped_texts(app: &mut App, texts: &[(Text, Vec2)]) -> Vec<Entity> {
+    let entities: Vec<_> = texts
+        .iter()
+        .map(|(text, bounds)| {
+            app.world_mut()
+                .spawn(BillboardTextBundle {
+                    text: text.clone(),
+                    text_bounds: BillboardTextBounds(Text2dBounds { size: *bounds }),
+                    ..default()
+                })
+                .id()
+        })
+        .collect();
+
+    update_until(app, |app| {
+        entities.iter().all(|&entity| {
+            !app.world()
+                .get::<BillboardTextHandles>(entity)
+                .unwrap()
+                .is_empty()
+        })
+    });
+
+    entities
+}
+
+/// Two sections in two sizes, so glyphs are spread over a mesh per font size.
+fn two_size_text(font: Handle<Font>) -> Text {
+    Text::from_sections([
+        TextSection::new(
+            "Shaped in parallel, ",
+            TextStyle {
+                font: font.clone(),
+                font_size: 40.0,
+                ..default()
+            },
+        ),
+        TextSection::new(
+            "placed serially",
+            TextStyle {
+                font,
+                font_size: 25.0,
+                ..default()
+            },
+        ),
+    ])
+}
+
+#[test]
+fn texts_are_shaped_like_the_text_pipeline_in_parallel_and_serially() {
+    for serial_layout in [false, true] {
+        let mut app = main_world_app();
+
+        app.world_mut()
+            .resource_mut::<BillboardTextSettings>()
+            .serial_layout = serial_layout;
+
+        let font: Handle<Font> = app
+            .world()
+            .resource::<AssetServer>()
+            .load("FiraSans-Regular.ttf");
+
+        // Wrapped, centered and in two sizes, so every part of the layout moves glyphs
+        let text = two_size_text(font).with_justify(JustifyText::Center);
+        let bounds = Vec2::new(200.0, f32::INFINITY);
+
+        // Enough texts for every thread to shape some
+        let entities = spawn_shaped_texts(&mut app, &vec![(text.clone(), bounds); 32]);
+        let expected = text_pipeline_glyph_centers(&mut app, text, bounds);
+
+        for &entity in &entities {
+            let handles = app.world().get::<BillboardTextHandles>(entity).unwrap();
+            assert_eq!(handles.len(), 2);
+
+            assert_eq!(
+                billboard_glyph_centers(&app, entity),
+                expected,
+                "serial layout: {serial_layout}"
+            );
+        }
+    }
+}
+
+// Texts are shaped outside of bevy_text's pipeline, which this keeps in step with it
+#[test]
+fn texts_are_shaped_like_the_text_pipeline_for_every_justify_and_line_break() {
+    let mut app = main_world_app();
+
+    let font: Handle<Font> = app
+        .world()
+        .resource::<AssetServer>()
+        .load("FiraSans-Regular.ttf");
+
+    let mut texts = Vec::new();
+    for justify in [JustifyText::Left, JustifyText::Center, JustifyText::Right] {
+        for linebreak in [
+            BreakLineOn::WordBoundary,
+            BreakLineOn::AnyCharacter,
+            BreakLineOn::NoWrap,
+        ] {
+            for bounds in [Vec2::new(150.0, f32::INFINITY), Vec2::INFINITY] {
+                let mut text = two_size_text(font.clone()).with_justify(justify);
+                text.linebreak_behavior = linebreak;
+                texts.push((text, bounds));
+            }
+        }
+    }
+
+    let entities = spawn_shaped_texts(&mut app, &texts);
+
+    for ((text, bounds), entity) in texts.into_iter().zip(entities) {
+        let description = format!("{:?} {:?} {bounds}", text.justify, text.linebreak_behavior);
+        let expected = text_pipeline_glyph_centers(&mut app, text, bounds);
+
+        assert_eq!(
+            billboard_glyph_centers(&app, entity),
+            expected,
+            "{description}"
+        );
+    }
 }